@@ -1,5 +1,6 @@
+use client_util::client::timeout::{TimeoutLayer, Timeouts};
 use std::time::Duration;
-use tower::{timeout::error::Elapsed, ServiceBuilder};
+use tower::ServiceBuilder;
 // use client_util::prelude::*;
 use client_util::prelude::*;
 #[tokio::main]
@@ -21,15 +22,11 @@ async fn main() -> client_util::Result<()> {
     let response = request
         .send(
             ServiceBuilder::new()
-                .timeout(Duration::ZERO)
+                .layer(TimeoutLayer::new(Timeouts::new().total(Duration::ZERO)))
                 .service(&mut client),
         )
         .await;
     let timeout_err = response.expect_err("should timeout");
-    use std::error::Error;
-    assert!(timeout_err
-        .source()
-        .expect("should have source")
-        .is::<Elapsed>());
+    assert!(timeout_err.is_timeout());
     Ok(())
 }
@@ -0,0 +1,265 @@
+//! A blocking client, for the programs which are not async.
+//!
+//! The requests are sent by the async clients of this crate, on a private runtime thread owned by
+//! the [`Client`]. The calling thread only waits for the results.
+//!
+//! ```no_run
+//! use client_util::blocking::{Client, RequestBlockingExt};
+//! use client_util::request::RequestBuilder;
+//! # fn main() -> client_util::Result<()> {
+//! let client = Client::new().expect("failed to start the runtime thread");
+//! let response = RequestBuilder::get("https://example.com")?
+//!     .empty()
+//!     .send_blocking(&client)?
+//!     .text()?;
+//! println!("{}", response.body());
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Read};
+use std::ops::{Deref, DerefMut};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use bytes::{Buf, Bytes};
+use http::Request;
+use http_body_util::BodyExt;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use tokio::sync::oneshot;
+
+use crate::client::global::{default_client, SharedClient};
+use crate::client::ClientBody;
+use crate::error::BoxError;
+use crate::redact::RedactedHeaders;
+use crate::request::RequestExt;
+use crate::response::{ResponseError, ResponseExt};
+
+/// A tokio runtime running on its own thread, stopped on drop.
+struct Runtime {
+    handle: tokio::runtime::Handle,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Runtime {
+    fn start() -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let handle = runtime.handle().clone();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("client-util-blocking".into())
+            .spawn(move || {
+                let _ = runtime.block_on(stopped);
+            })?;
+        Ok(Self {
+            handle,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        })
+    }
+    /// Run `future` on the runtime thread, and wait for its output.
+    fn wait<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.handle.spawn(async move {
+            let _ = sender.send(future.await);
+        });
+        receiver
+            .recv()
+            .expect("the blocking client runtime thread stopped")
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// A blocking client, cheap to clone.
+#[derive(Clone)]
+pub struct Client {
+    client: SharedClient,
+    runtime: Arc<Runtime>,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Start a client with the default async client, see [`crate::client::global`].
+    pub fn new() -> io::Result<Self> {
+        Self::with_client(default_client())
+    }
+    /// Start a client which sends the requests with `client`.
+    pub fn with_client(client: SharedClient) -> io::Result<Self> {
+        Ok(Self {
+            client,
+            runtime: Arc::new(Runtime::start()?),
+        })
+    }
+    /// Send a request, and wait for the response head.
+    pub fn send<B>(&self, request: Request<B>) -> crate::Result<Response>
+    where
+        B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+    {
+        let response = self.runtime.wait(request.send(self.client.clone()))?;
+        Ok(Response {
+            inner: response,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+/// Extension trait to send a [`http::Request`] with a blocking [`Client`].
+pub trait RequestBlockingExt {
+    fn send_blocking(self, client: &Client) -> crate::Result<Response>;
+}
+
+impl<B> RequestBlockingExt for Request<B>
+where
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    #[inline]
+    fn send_blocking(self, client: &Client) -> crate::Result<Response> {
+        client.send(self)
+    }
+}
+
+/// A response whose body is read by blocking the current thread.
+pub struct Response {
+    inner: http::Response<ClientBody>,
+    runtime: Arc<Runtime>,
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.inner.status())
+            .field("version", &self.inner.version())
+            .field("headers", &RedactedHeaders::new(self.inner.headers()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Deref for Response {
+    type Target = http::Response<ClientBody>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Response {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl Response {
+    /// The async response, its body must be polled within a tokio runtime.
+    pub fn into_inner(self) -> http::Response<ClientBody> {
+        self.inner
+    }
+    /// Deserialize the response body as json.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn json<T>(self) -> Result<http::Response<T>, ResponseError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.runtime.wait(self.inner.json())
+    }
+    /// Deserialize the response body as text, see [`ResponseExt::text`].
+    pub fn text(self) -> Result<http::Response<String>, ResponseError> {
+        self.runtime.wait(self.inner.text())
+    }
+    /// Collect the response body as bytes.
+    pub fn bytes(self) -> Result<http::Response<Bytes>, ResponseError> {
+        self.runtime.wait(self.inner.bytes())
+    }
+    /// Read the response body as it arrives.
+    pub fn reader(self) -> http::Response<BodyReader> {
+        let (parts, body) = self.inner.into_parts();
+        let reader = BodyReader {
+            body: Some(body),
+            chunk: Bytes::new(),
+            runtime: self.runtime,
+        };
+        http::Response::from_parts(parts, reader)
+    }
+}
+
+/// A response body implementing [`Read`].
+pub struct BodyReader {
+    body: Option<ClientBody>,
+    chunk: Bytes,
+    runtime: Arc<Runtime>,
+}
+
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyReader")
+            .field("buffered", &self.chunk.len())
+            .field("done", &self.body.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+impl BodyReader {
+    /// Wait for the next data frame, `false` at the end of the body.
+    fn fill(&mut self) -> io::Result<bool> {
+        while self.chunk.is_empty() {
+            let Some(mut body) = self.body.take() else {
+                return Ok(false);
+            };
+            let (body, frame) = self.runtime.wait(async move {
+                let frame = body.frame().await;
+                (body, frame)
+            });
+            match frame {
+                Some(Ok(frame)) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.chunk = data;
+                    }
+                    self.body = Some(body);
+                }
+                Some(Err(error)) => return Err(io::Error::other(error)),
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || !self.fill()? {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.chunk.len());
+        self.chunk.copy_to_slice(&mut buf[..len]);
+        Ok(len)
+    }
+}
@@ -38,6 +38,8 @@ where
     http_body_util::StreamBody::new(stream)
 }
 
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
 pub fn boxed_stream<S>(s: S) -> Body
 where
     S: Stream<Item = Result<http_body::Frame<Bytes>, crate::error::BoxError>>
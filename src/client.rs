@@ -6,26 +6,132 @@
 //! This crate provides a default client implementation using [`hyper`].
 //!
 //! However, you can use any service as a client, and add more layer upon it.
+#[cfg(all(feature = "auth", feature = "client-hyper"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "auth", feature = "client-hyper"))))]
+pub mod auth;
+#[cfg(feature = "balance")]
+#[cfg_attr(docsrs, doc(cfg(feature = "balance")))]
+pub mod balance;
+#[cfg(feature = "cache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cache")))]
+pub mod cache;
+#[cfg(feature = "circuit-breaker")]
+#[cfg_attr(docsrs, doc(cfg(feature = "circuit-breaker")))]
+pub mod circuit_breaker;
+#[cfg(feature = "cookies")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+pub mod cookie;
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub mod dns;
+mod facade;
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub mod global;
+#[cfg(feature = "client-h3")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-h3")))]
+pub mod h3;
+#[cfg(feature = "hedge")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hedge")))]
+pub mod hedge;
 #[cfg(feature = "client-hyper")]
 #[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
 pub mod hyper;
+#[cfg(feature = "logging")]
+#[cfg_attr(docsrs, doc(cfg(feature = "logging")))]
+pub mod logging;
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub mod metadata;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub mod metrics;
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub mod pool;
+#[cfg(feature = "rate-limit")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rate-limit")))]
+pub mod rate_limit;
+pub mod redirect;
+#[cfg(feature = "client-hyper")]
+mod replay;
+#[cfg(feature = "retry")]
+#[cfg_attr(docsrs, doc(cfg(feature = "retry")))]
+pub mod retry;
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub mod rt;
+pub mod timeout;
+#[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls")))
+)]
+pub mod tls;
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+pub mod trace;
+#[cfg(all(unix, feature = "client-hyper-unix"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "client-hyper-unix"))))]
+pub mod unix;
 use crate::error::BoxError as BodyError;
 use bytes::Bytes;
+pub use facade::*;
+#[cfg(feature = "client-h3")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-h3")))]
+pub use h3::*;
 use http_body_util::combinators::BoxBody;
 #[cfg(feature = "client-hyper")]
 #[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
 pub use hyper::*;
+#[cfg(all(unix, feature = "client-hyper-unix"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "client-hyper-unix"))))]
+pub use unix::*;
 
+/// Define a getter returning a clone of a lazily built client.
+#[deprecated(
+    since = "0.3.0",
+    note = "use `client::global` to share a default client"
+)]
 #[macro_export]
 macro_rules! shared_client {
     ($v:vis $getter: ident: $maker: ident -> $ClientType: ty) => {
         $v fn $getter() -> $ClientType {
-            static mut CLIENT: std::sync::OnceLock<$ClientType> = std::sync::OnceLock::new();
-            unsafe {
-                CLIENT.get_or_init($maker).clone()
-            }
+            static CLIENT: std::sync::OnceLock<$ClientType> = std::sync::OnceLock::new();
+            CLIENT.get_or_init($maker).clone()
         }
     };
 }
 
 pub type ClientBody = BoxBody<Bytes, BodyError>;
+
+/// Conversion into the service which sends a request, see [`RequestExt::send`](crate::request::RequestExt::send).
+///
+/// Services and `&mut` services are used as is, while `&` references to cloneable services are
+/// cloned, so a shared client can be used without being moved.
+///
+/// The hyper clients also implement `Service` for `&Client`, which makes `&client` ambiguous for
+/// them: pass `client.clone()` or `&mut client` instead.
+pub trait IntoClient<S> {
+    fn into_client(self) -> S;
+}
+
+impl<S> IntoClient<S> for S
+where
+    S: tower_service::Service<http::Request<ClientBody>>,
+{
+    #[inline]
+    fn into_client(self) -> S {
+        self
+    }
+}
+
+impl<S> IntoClient<S> for &S
+where
+    S: tower_service::Service<http::Request<ClientBody>> + Clone,
+{
+    #[inline]
+    fn into_client(self) -> S {
+        self.clone()
+    }
+}
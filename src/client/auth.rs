@@ -0,0 +1,593 @@
+//! Authentication of the requests, with a pluggable [`Authenticator`].
+//!
+//! The [`AuthLayer`] applies the [`Credential`] of its authenticator to each request:
+//! - [`Credential`] itself for the same credential on every request, a basic user and password, a
+//!   bearer token, or an API key in a header or in a query parameter.
+//! - [`HostCredentials`] for a credential by host.
+//! - [`Netrc`] for the logins of a `.netrc` file.
+//! - [`RefreshingToken`] for a bearer token fetched again when it's rejected.
+//!
+//! A header already set on the request, like the one of
+//! [`RequestBuilder::bearer_auth`](crate::request::RequestBuilder::bearer_auth), is kept.
+//!
+//! A `401` response with `WWW-Authenticate` challenges asks [`Authenticator::refresh`] for a new
+//! credential, and the request is sent again once with it. The `401` response to a request whose
+//! body is longer than [`AuthLayer::max_replay_body`] is returned as is.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::auth::{AuthLayer, Credential, HostCredentials};
+//! use client_util::prelude::*;
+//! let credentials = HostCredentials::new()
+//!     .host("api.example.com", Credential::bearer("token"))
+//!     .host("maps.example.com", Credential::query("key", "secret"));
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(AuthLayer::new(credentials))
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://api.example.com/")?.empty().send(client).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use http::uri::PathAndQuery;
+use http::{HeaderMap, Request, Response, StatusCode, Uri};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use crate::client::replay::{self, attempt, ReplayBody};
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+/// A credential applied to a request.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// A user and a password in the `Authorization` header.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// A token in the `Authorization` header.
+    Bearer(String),
+    /// An API key in a header.
+    Header { name: HeaderName, value: String },
+    /// An API key in a query parameter.
+    Query { name: String, value: String },
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Self::Bearer(_) => f.debug_tuple("Bearer").finish_non_exhaustive(),
+            Self::Header { name, .. } => f
+                .debug_struct("Header")
+                .field("name", name)
+                .finish_non_exhaustive(),
+            Self::Query { name, .. } => f
+                .debug_struct("Query")
+                .field("name", name)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Percent encode a query component, keeping the unreserved characters.
+fn encode_query_component(component: &str, encoded: &mut String) {
+    use std::fmt::Write;
+    for byte in component.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+}
+
+impl Credential {
+    pub fn basic(username: impl Into<String>, password: Option<impl Into<String>>) -> Self {
+        Self::Basic {
+            username: username.into(),
+            password: password.map(Into::into),
+        }
+    }
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Self::Bearer(token.into())
+    }
+    pub fn header(name: HeaderName, value: impl Into<String>) -> Self {
+        Self::Header {
+            name,
+            value: value.into(),
+        }
+    }
+    pub fn query(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::Query {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+    /// Apply the credential to `request`, replacing the one applied before if `replace`.
+    fn apply<B>(&self, request: &mut Request<B>, replace: bool) -> Result<(), BoxError> {
+        let (name, value) = match self {
+            Self::Basic { username, password } => (
+                AUTHORIZATION,
+                crate::util::basic_auth(username, password.as_ref()),
+            ),
+            Self::Bearer(token) => (AUTHORIZATION, crate::util::bearer_auth(token)),
+            Self::Header { name, value } => {
+                let mut value = HeaderValue::from_str(value)?;
+                value.set_sensitive(true);
+                (name.clone(), value)
+            }
+            Self::Query { name, value } => {
+                let uri = request.uri();
+                let mut path_and_query = uri.path().to_owned();
+                path_and_query.push('?');
+                if let Some(query) = uri.query().filter(|query| !query.is_empty()) {
+                    path_and_query.push_str(query);
+                    path_and_query.push('&');
+                }
+                encode_query_component(name, &mut path_and_query);
+                path_and_query.push('=');
+                encode_query_component(value, &mut path_and_query);
+                let mut parts = uri.clone().into_parts();
+                parts.path_and_query = Some(PathAndQuery::try_from(path_and_query)?);
+                *request.uri_mut() = Uri::from_parts(parts)?;
+                return Ok(());
+            }
+        };
+        if replace || !request.headers().contains_key(&name) {
+            request.headers_mut().insert(name, value);
+        }
+        Ok(())
+    }
+}
+
+/// A challenge of a `WWW-Authenticate` header, like `Bearer realm="api", error="invalid_token"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    /// The scheme, like `Basic` or `Bearer`.
+    pub scheme: String,
+    /// The parameters, with their names in lower case.
+    pub params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    /// The value of the parameter `name`, like `realm`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    /// The challenges of the `WWW-Authenticate` headers.
+    pub fn parse(headers: &HeaderMap) -> Vec<Challenge> {
+        let mut challenges = Vec::new();
+        for value in headers.get_all(WWW_AUTHENTICATE) {
+            if let Ok(value) = value.to_str() {
+                parse_challenges(value, &mut challenges);
+            }
+        }
+        challenges
+    }
+}
+
+fn parse_challenges(value: &str, challenges: &mut Vec<Challenge>) {
+    let is_delimiter = |c: char| c.is_ascii_whitespace() || c == ',' || c == '=';
+    let mut rest = value;
+    let mut current: Option<Challenge> = None;
+    // whether the next token follows a scheme, without a comma in between
+    let mut after_scheme = false;
+    loop {
+        let trimmed = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        after_scheme &= !rest[..rest.len() - trimmed.len()].contains(',');
+        rest = trimmed;
+        let end = rest.find(is_delimiter).unwrap_or(rest.len());
+        if end == 0 {
+            break;
+        }
+        let token = &rest[..end];
+        rest = rest[end..].trim_start();
+        let Some(after) = rest.strip_prefix('=') else {
+            // a token68 without padding, like `Negotiate abc`, isn't a scheme
+            if std::mem::take(&mut after_scheme) {
+                continue;
+            }
+            after_scheme = true;
+            challenges.extend(current.take());
+            current = Some(Challenge {
+                scheme: token.to_owned(),
+                params: Vec::new(),
+            });
+            continue;
+        };
+        // the padding of a token68, like `Negotiate abc==`, isn't a parameter
+        after_scheme = false;
+        if after.starts_with('=') || after.trim_start().is_empty() || after.starts_with(',') {
+            rest = after.trim_start_matches('=');
+            continue;
+        }
+        rest = after.trim_start();
+        let mut param = String::new();
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut chars = quoted.char_indices();
+            rest = "";
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '\\' => param.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        rest = &quoted[index + 1..];
+                        break;
+                    }
+                    c => param.push(c),
+                }
+            }
+        } else {
+            let end = rest
+                .find(|c: char| c.is_ascii_whitespace() || c == ',')
+                .unwrap_or(rest.len());
+            param.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+        if let Some(challenge) = current.as_mut() {
+            challenge.params.push((token.to_ascii_lowercase(), param));
+        }
+    }
+    challenges.extend(current);
+}
+
+/// The future returned by [`Authenticator::refresh`].
+pub type Refreshing = Pin<Box<dyn Future<Output = Result<Option<Credential>, BoxError>> + Send>>;
+
+/// The credentials of the requests.
+pub trait Authenticator: Send + Sync + 'static {
+    /// The credential of a request to `uri`, if any.
+    fn credential(&self, uri: &Uri) -> Option<Credential>;
+    /// A new credential for a request to `uri` rejected with a `401` response and its
+    /// `challenges`, to send it again. `None` by default, to return the `401` response.
+    ///
+    /// `rejected` is the credential the request was sent with, if any.
+    fn refresh(
+        &self,
+        uri: &Uri,
+        rejected: Option<Credential>,
+        challenges: Vec<Challenge>,
+    ) -> Refreshing {
+        let _ = (uri, rejected, challenges);
+        Box::pin(std::future::ready(Ok(None)))
+    }
+}
+
+impl Authenticator for Credential {
+    fn credential(&self, _uri: &Uri) -> Option<Credential> {
+        Some(self.clone())
+    }
+}
+
+/// The credentials by host, compared case insensitively.
+#[derive(Debug, Clone, Default)]
+pub struct HostCredentials {
+    hosts: HashMap<String, Credential>,
+}
+
+impl HostCredentials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Use `credential` for `host`, or for `host:port` to only match this port.
+    pub fn host(mut self, host: impl Into<String>, credential: Credential) -> Self {
+        self.hosts
+            .insert(host.into().to_ascii_lowercase(), credential);
+        self
+    }
+}
+
+impl Authenticator for HostCredentials {
+    fn credential(&self, uri: &Uri) -> Option<Credential> {
+        let authority = uri.authority()?;
+        let host = authority.host().to_ascii_lowercase();
+        authority
+            .port_u16()
+            .and_then(|port| self.hosts.get(&format!("{host}:{port}")))
+            .or_else(|| self.hosts.get(&host))
+            .cloned()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Login {
+    login: Option<String>,
+    password: Option<String>,
+}
+
+/// The logins of a `.netrc` file, sent as basic credentials.
+#[derive(Clone, Default)]
+pub struct Netrc {
+    machines: HashMap<String, Login>,
+    default: Option<Login>,
+}
+
+impl fmt::Debug for Netrc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Netrc")
+            .field("machines", &self.machines.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Netrc {
+    /// Parse the content of a `.netrc` file.
+    pub fn parse(content: &str) -> Self {
+        let mut netrc = Self::default();
+        let mut tokens = content.split_ascii_whitespace();
+        // the machine the tokens belong to, `None` for the default one
+        let mut machine: Option<Option<String>> = None;
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => {
+                    let name = tokens.next().unwrap_or_default().to_ascii_lowercase();
+                    netrc.machines.entry(name.clone()).or_default();
+                    machine = Some(Some(name));
+                }
+                "default" => {
+                    netrc.default.get_or_insert_with(Login::default);
+                    machine = Some(None);
+                }
+                "login" | "password" => {
+                    let value = tokens.next().map(str::to_owned);
+                    let login = match &machine {
+                        Some(Some(name)) => netrc.machines.get_mut(name),
+                        Some(None) => netrc.default.as_mut(),
+                        None => None,
+                    };
+                    if let Some(login) = login {
+                        if token == "login" {
+                            login.login = value;
+                        } else {
+                            login.password = value;
+                        }
+                    }
+                }
+                "account" => {
+                    tokens.next();
+                }
+                // a macro ends with an empty line, its words are skipped until the next machine
+                "macdef" => machine = None,
+                _ => {}
+            }
+        }
+        netrc
+    }
+    /// Read the `.netrc` file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::read_to_string(path).map(|content| Self::parse(&content))
+    }
+    /// Read the file of the `NETRC` environment variable, or `~/.netrc`, empty when missing.
+    pub fn from_env() -> io::Result<Self> {
+        let path = std::env::var_os("NETRC")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc")));
+        match path.map(Self::from_file) {
+            Some(Err(error)) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            Some(Ok(netrc)) => Ok(netrc),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+impl Authenticator for Netrc {
+    fn credential(&self, uri: &Uri) -> Option<Credential> {
+        let host = uri.host()?.to_ascii_lowercase();
+        let login = self.machines.get(&host).or(self.default.as_ref())?;
+        Some(Credential::basic(
+            login.login.clone().unwrap_or_default(),
+            login.password.clone(),
+        ))
+    }
+}
+
+/// A bearer token, fetched again when a `401` response challenges it.
+///
+/// The token is fetched on the first challenge when there is no initial one. A single fetch is
+/// in flight at a time: the requests rejected meanwhile, or with a token already replaced, are
+/// sent again with the current token.
+pub struct RefreshingToken<F> {
+    fetch: Arc<F>,
+    token: Arc<Mutex<Option<String>>>,
+    fetching: Arc<futures_util::lock::Mutex<()>>,
+}
+
+impl<F> fmt::Debug for RefreshingToken<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefreshingToken").finish_non_exhaustive()
+    }
+}
+
+impl<F, Fut> RefreshingToken<F>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, BoxError>> + Send + 'static,
+{
+    /// Fetch the token with `fetch`.
+    pub fn new(fetch: F) -> Self {
+        Self {
+            fetch: Arc::new(fetch),
+            token: Arc::default(),
+            fetching: Arc::default(),
+        }
+    }
+    /// The token to use until it's rejected.
+    pub fn token(self, token: impl Into<String>) -> Self {
+        *self.token.lock().unwrap_or_else(PoisonError::into_inner) = Some(token.into());
+        self
+    }
+}
+
+impl<F, Fut> Authenticator for RefreshingToken<F>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, BoxError>> + Send + 'static,
+{
+    fn credential(&self, _uri: &Uri) -> Option<Credential> {
+        self.token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .map(Credential::Bearer)
+    }
+    fn refresh(
+        &self,
+        _uri: &Uri,
+        rejected: Option<Credential>,
+        challenges: Vec<Challenge>,
+    ) -> Refreshing {
+        if !challenges
+            .iter()
+            .any(|challenge| challenge.scheme.eq_ignore_ascii_case("bearer"))
+        {
+            return Box::pin(std::future::ready(Ok(None)));
+        }
+        let rejected = match rejected {
+            Some(Credential::Bearer(token)) => Some(token),
+            _ => None,
+        };
+        let fetch = self.fetch.clone();
+        let token = self.token.clone();
+        let fetching = self.fetching.clone();
+        Box::pin(async move {
+            let _fetching = fetching.lock().await;
+            let current = token.lock().unwrap_or_else(PoisonError::into_inner).clone();
+            if current.is_some() && current != rejected {
+                return Ok(current.map(Credential::Bearer));
+            }
+            let fetched = fetch().await?;
+            *token.lock().unwrap_or_else(PoisonError::into_inner) = Some(fetched.clone());
+            Ok(Some(Credential::Bearer(fetched)))
+        })
+    }
+}
+
+/// Layer for [`Auth`].
+#[derive(Clone)]
+pub struct AuthLayer {
+    authenticator: Arc<dyn Authenticator>,
+    /// The limit of [`ReplayBody::read`].
+    max_replay_body: usize,
+}
+
+impl fmt::Debug for AuthLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthLayer")
+            .field("max_replay_body", &self.max_replay_body)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuthLayer {
+    pub fn new(authenticator: impl Authenticator) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            max_replay_body: 64 * 1024,
+        }
+    }
+    /// The longest request body which is buffered to be sent again after a `401` response,
+    /// 64KiB by default.
+    pub fn max_replay_body(mut self, max: usize) -> Self {
+        self.max_replay_body = max;
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for AuthLayer {
+    type Service = Auth<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Auth {
+            inner,
+            authenticator: self.authenticator.clone(),
+            max_replay_body: self.max_replay_body,
+        }
+    }
+}
+
+/// Authenticate the requests, see [`crate::client::auth`].
+#[derive(Clone)]
+pub struct Auth<S> {
+    inner: S,
+    authenticator: Arc<dyn Authenticator>,
+    max_replay_body: usize,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Auth<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("inner", &self.inner)
+            .field("max_replay_body", &self.max_replay_body)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Auth<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>
+        + Clone
+        + Send
+        + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let mut service = replay::take_ready(&mut self.inner);
+        let authenticator = self.authenticator.clone();
+        let max_replay_body = self.max_replay_body;
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let mut body =
+                ReplayBody::read(body.map_err(Into::into).boxed(), max_replay_body).await?;
+            let mut request = replay::request(&parts, body.next());
+            let credential = authenticator.credential(&parts.uri);
+            if let Some(credential) = &credential {
+                credential.apply(&mut request, false)?;
+            }
+            let response = attempt(service.call(request)).await?;
+            if response.status() != StatusCode::UNAUTHORIZED || !body.replayable() {
+                return Ok(response);
+            }
+            let challenges = Challenge::parse(response.headers());
+            if challenges.is_empty() {
+                return Ok(response);
+            }
+            let Some(credential) = authenticator
+                .refresh(&parts.uri, credential, challenges)
+                .await?
+            else {
+                return Ok(response);
+            };
+            let mut request = replay::request(&parts, body.next());
+            credential.apply(&mut request, true)?;
+            attempt(service.oneshot(request)).await
+        })
+    }
+}
@@ -0,0 +1,368 @@
+//! Load balancing over the replicas of a service, with failover.
+//!
+//! The [`BalanceLayer`] sends each request to one of the base uris of its [`Balancer`]: the scheme
+//! and the authority of the request are replaced, its path and query, built with
+//! [`RequestBuilder::path`](crate::request::RequestBuilder::path) and
+//! [`RequestBuilder::query`](crate::request::RequestBuilder::query) for example, are kept after the
+//! path of the base uri. The endpoint is picked by the [`BalanceStrategy`].
+//!
+//! The health of the endpoints is tracked passively: an endpoint which failed to connect
+//! [`Balancer::max_failures`] times in a row is ejected for [`Balancer::ejection`], when all the
+//! endpoints are ejected they are all used. A request which failed to connect is sent to the next
+//! endpoint, since nothing was sent. A request whose body is longer than
+//! [`Balancer::max_replay_body`] isn't failed over.
+//!
+//! The base uri of the endpoint which responded is in the [`Endpoint`] extension of the response.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::balance::{BalanceLayer, BalanceStrategy, Balancer};
+//! use client_util::prelude::*;
+//! let balancer = Balancer::new([
+//!     "http://10.0.0.1:8080".parse().unwrap(),
+//!     "http://10.0.0.2:8080".parse().unwrap(),
+//! ])
+//! .expect("invalid base uris")
+//! .strategy(BalanceStrategy::LeastOutstanding);
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(BalanceLayer::new(balancer))
+//!     .service(build_http_client());
+//! let response = RequestBuilder::get("/")?
+//!     .path("/users")?
+//!     .empty()
+//!     .send(client)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::cmp::Reverse;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::header::HOST;
+use http::{Request, Response, Uri};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use crate::client::hyper::is_connect_error;
+use crate::client::replay::{self, attempt, ReplayBody};
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+/// How to pick the endpoint of a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    /// Each endpoint in turn.
+    #[default]
+    RoundRobin,
+    /// The endpoint with the fewest requests waiting for their response.
+    LeastOutstanding,
+    /// The same endpoint for the same [`BalanceKey`], by rendezvous hashing. The requests without
+    /// a key are sent round robin.
+    ConsistentHash,
+}
+
+/// The key of a request for [`BalanceStrategy::ConsistentHash`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BalanceKey(pub String);
+
+/// The base uri of the endpoint which responded, inserted in the extensions of the response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint(pub Uri);
+
+#[derive(Debug, Default)]
+struct Health {
+    /// The consecutive connect errors.
+    failures: u32,
+    ejected_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Replica {
+    base: Uri,
+    outstanding: AtomicUsize,
+    health: Mutex<Health>,
+}
+
+impl Replica {
+    fn new(base: Uri) -> Self {
+        Self {
+            base,
+            outstanding: AtomicUsize::new(0),
+            health: Mutex::default(),
+        }
+    }
+    fn health(&self) -> MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.health().ejected_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Counts a request as outstanding until dropped.
+struct Outstanding<'a>(&'a AtomicUsize);
+
+impl<'a> Outstanding<'a> {
+    fn new(outstanding: &'a AtomicUsize) -> Self {
+        outstanding.fetch_add(1, Ordering::Relaxed);
+        Self(outstanding)
+    }
+}
+
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+struct Replicas {
+    replicas: Vec<Replica>,
+    strategy: BalanceStrategy,
+    max_failures: u32,
+    ejection: Duration,
+    /// The limit of [`ReplayBody::read`].
+    max_replay_body: usize,
+    next: AtomicUsize,
+}
+
+/// A copy of the settings, with endpoints of their own health.
+impl Clone for Replicas {
+    fn clone(&self) -> Self {
+        Self {
+            replicas: self
+                .replicas
+                .iter()
+                .map(|replica| Replica::new(replica.base.clone()))
+                .collect(),
+            strategy: self.strategy,
+            max_failures: self.max_failures,
+            ejection: self.ejection,
+            max_replay_body: self.max_replay_body,
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// The base uris a [`Balancer`] can't be built with.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum BalancerError {
+    #[error("no base uri to balance over")]
+    NoBaseUri,
+    #[error("base uri must have a scheme and an authority: {0}")]
+    RelativeBaseUri(Uri),
+}
+
+/// The shared state of the load balancing, the endpoints and their health.
+///
+/// The clones of a balancer share the health of the endpoints, but a clone configured with the
+/// builder methods is a new balancer.
+#[derive(Debug, Clone)]
+pub struct Balancer {
+    inner: Arc<Replicas>,
+}
+
+impl Balancer {
+    /// Balance the requests over the `bases` uris, round robin.
+    ///
+    /// Fails when there is no base uri, or one has no scheme or authority.
+    pub fn new(bases: impl IntoIterator<Item = Uri>) -> Result<Self, BalancerError> {
+        let replicas = bases
+            .into_iter()
+            .map(|base| {
+                if base.scheme().is_none() || base.authority().is_none() {
+                    return Err(BalancerError::RelativeBaseUri(base));
+                }
+                Ok(Replica::new(base))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if replicas.is_empty() {
+            return Err(BalancerError::NoBaseUri);
+        }
+        Ok(Self {
+            inner: Arc::new(Replicas {
+                replicas,
+                strategy: BalanceStrategy::default(),
+                max_failures: 1,
+                ejection: Duration::from_secs(10),
+                max_replay_body: 64 * 1024,
+                next: AtomicUsize::new(0),
+            }),
+        })
+    }
+    /// Configure this balancer, a balancer configured once cloned tracks the health on its own.
+    fn replicas(mut self, f: impl FnOnce(&mut Replicas)) -> Self {
+        f(Arc::make_mut(&mut self.inner));
+        self
+    }
+    pub fn strategy(self, strategy: BalanceStrategy) -> Self {
+        self.replicas(|replicas| replicas.strategy = strategy)
+    }
+    /// The consecutive connect errors which eject an endpoint, `1` by default.
+    pub fn max_failures(self, failures: u32) -> Self {
+        self.replicas(|replicas| replicas.max_failures = failures.max(1))
+    }
+    /// How long an endpoint is ejected, `10s` by default.
+    ///
+    /// A connect error once it's back ejects it again.
+    pub fn ejection(self, duration: Duration) -> Self {
+        self.replicas(|replicas| replicas.ejection = duration)
+    }
+    /// The longest request body which is buffered to be failed over, 64KiB by default.
+    pub fn max_replay_body(self, max: usize) -> Self {
+        self.replicas(|replicas| replicas.max_replay_body = max)
+    }
+    /// The base uris of the endpoints which aren't ejected.
+    pub fn healthy(&self) -> Vec<Uri> {
+        let now = Instant::now();
+        self.inner
+            .replicas
+            .iter()
+            .filter(|replica| replica.is_healthy(now))
+            .map(|replica| replica.base.clone())
+            .collect()
+    }
+    /// The endpoints to try in turn, the ejected ones last.
+    fn candidates(&self, key: Option<&BalanceKey>) -> Vec<usize> {
+        let replicas = &self.inner.replicas;
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        let mut order: Vec<_> = (0..replicas.len())
+            .map(|index| (start + index) % replicas.len())
+            .collect();
+        match (self.inner.strategy, key) {
+            (BalanceStrategy::LeastOutstanding, _) => {
+                order.sort_by_key(|index| replicas[*index].outstanding.load(Ordering::Relaxed));
+            }
+            (BalanceStrategy::ConsistentHash, Some(key)) => order.sort_by_cached_key(|index| {
+                // the same endpoint whatever the build or the process, with a fixed hash
+                let base = replicas[*index].base.to_string();
+                let hash =
+                    crate::util::fnv1a(key.0.as_bytes().iter().chain(b"\0").chain(base.as_bytes()));
+                Reverse(hash)
+            }),
+            _ => {}
+        }
+        let now = Instant::now();
+        order.sort_by_key(|index| !replicas[*index].is_healthy(now));
+        order
+    }
+    fn record(&self, index: usize, connect_failed: bool) {
+        let mut health = self.inner.replicas[index].health();
+        if !connect_failed {
+            *health = Health::default();
+            return;
+        }
+        health.failures += 1;
+        if health.failures >= self.inner.max_failures {
+            health.ejected_until = Some(Instant::now() + self.inner.ejection);
+        }
+    }
+}
+
+/// Layer for [`Balance`].
+#[derive(Debug, Clone)]
+pub struct BalanceLayer {
+    balancer: Balancer,
+}
+
+impl BalanceLayer {
+    pub fn new(balancer: Balancer) -> Self {
+        Self { balancer }
+    }
+}
+
+impl<S> tower::Layer<S> for BalanceLayer {
+    type Service = Balance<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Balance {
+            inner,
+            balancer: self.balancer.clone(),
+        }
+    }
+}
+
+/// Send the requests to the endpoints of a [`Balancer`], see [`crate::client::balance`].
+#[derive(Debug, Clone)]
+pub struct Balance<S> {
+    inner: S,
+    balancer: Balancer,
+}
+
+impl<S> Balance<S> {
+    pub fn balancer(&self) -> &Balancer {
+        &self.balancer
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Balance<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>
+        + Clone
+        + Send
+        + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let mut service = replay::take_ready(&mut self.inner);
+        let balancer = self.balancer.clone();
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let candidates = balancer.candidates(parts.extensions.get::<BalanceKey>());
+            let mut body = ReplayBody::read(
+                body.map_err(Into::into).boxed(),
+                balancer.inner.max_replay_body,
+            )
+            .await?;
+            let mut last_error = None;
+            for (tried, index) in candidates.into_iter().enumerate() {
+                if tried > 0 && !body.replayable() {
+                    break;
+                }
+                let replica = &balancer.inner.replicas[index];
+                let mut request = replay::request(&parts, body.next());
+                *request.uri_mut() = crate::util::rebase(&replica.base, request.uri())?;
+                request.headers_mut().remove(HOST);
+                let outstanding = Outstanding::new(&replica.outstanding);
+                let result = if tried == 0 {
+                    attempt(service.call(request)).await
+                } else {
+                    attempt(service.clone().oneshot(request)).await
+                };
+                drop(outstanding);
+                match result {
+                    Err(error) if is_connect_error(&*error) => {
+                        balancer.record(index, true);
+                        last_error = Some(error);
+                    }
+                    Err(error) => return Err(error),
+                    Ok(mut response) => {
+                        balancer.record(index, false);
+                        response
+                            .extensions_mut()
+                            .insert(Endpoint(replica.base.clone()));
+                        return Ok(response);
+                    }
+                }
+            }
+            Err(last_error.expect("at least one endpoint was tried"))
+        })
+    }
+}
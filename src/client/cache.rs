@@ -0,0 +1,801 @@
+//! A private HTTP cache, following RFC 9111.
+//!
+//! The [`CacheLayer`] stores the responses to the `GET` requests in a [`CacheStore`], in memory
+//! with [`MemoryStore`] or on disk with [`DiskStore`]:
+//! - a fresh response, by `Cache-Control` and `Expires`, is served from the store.
+//! - a stale response is revalidated with `If-None-Match` and `If-Modified-Since`, a `304` is
+//!   served from the store.
+//! - the responses are selected by the request headers named by `Vary`.
+//! - a successful unsafe request, like a `POST`, removes the responses of its uri.
+//!
+//! With [`CacheLayer::stale_if_error`], a stale response is served when the request fails or the
+//! server responds with a `5xx`, for example when offline.
+//!
+//! Where the response comes from is in its [`CacheStatus`] extension.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::cache::{CacheLayer, CacheStatus, DiskStore};
+//! use client_util::prelude::*;
+//! let store = DiskStore::new("/tmp/http-cache").expect("failed to create the cache directory");
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(CacheLayer::new(store).stale_if_error(true))
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/config.json")?
+//!     .empty()
+//!     .send(client)
+//!     .await?;
+//! let status = response.extensions().get::<CacheStatus>();
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use http::header::{
+    HeaderName, AGE, CACHE_CONTROL, CONTENT_LENGTH, DATE, ETAG, EXPIRES, IF_MATCH,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE, LAST_MODIFIED, PRAGMA, VARY,
+};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri, Version};
+use http_body_util::{BodyExt, Full};
+use serde::{Deserialize, Serialize};
+
+use crate::client::replay::{attempt, ReplayBody};
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+/// Where a response comes from, inserted in its extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheStatus {
+    /// Served from the store, fresh.
+    Hit,
+    /// Served from the store, after the server responded `304 Not Modified`.
+    Revalidated,
+    /// Served from the store, stale, since the server couldn't be reached or failed.
+    Stale,
+    /// Sent by the server.
+    Miss,
+    /// Sent by the server, the request can't be served from the store.
+    Bypass,
+}
+
+/// A stored response.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+    /// The request headers named by `Vary`.
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+}
+
+impl CachedResponse {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+    /// When the response was received.
+    pub fn response_time(&self) -> SystemTime {
+        self.response_time
+    }
+    fn matches(&self, request: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| joined(request, name) == *value)
+    }
+    /// The current age, RFC 9111 section 4.2.3.
+    fn age(&self, now: SystemTime) -> Duration {
+        let date = http_date(&self.headers, DATE).unwrap_or(self.response_time);
+        let apparent_age = self.response_time.duration_since(date).unwrap_or_default();
+        let age_value = self
+            .headers
+            .get(AGE)
+            .and_then(|age| age.to_str().ok()?.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let response_delay = self
+            .response_time
+            .duration_since(self.request_time)
+            .unwrap_or_default();
+        let corrected_initial_age = apparent_age.max(age_value + response_delay);
+        corrected_initial_age + now.duration_since(self.response_time).unwrap_or_default()
+    }
+    /// The freshness lifetime, RFC 9111 section 4.2.1.
+    fn lifetime(&self, directives: &CacheControl) -> Duration {
+        if let Some(max_age) = directives.max_age {
+            return max_age;
+        }
+        let date = http_date(&self.headers, DATE).unwrap_or(self.response_time);
+        if self.headers.contains_key(EXPIRES) {
+            // an invalid date is in the past
+            return http_date(&self.headers, EXPIRES)
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or_default();
+        }
+        // heuristic freshness, a tenth of the time since the last modification
+        match http_date(&self.headers, LAST_MODIFIED) {
+            Some(modified) if is_heuristically_cacheable(self.status) => {
+                date.duration_since(modified).unwrap_or_default() / 10
+            }
+            _ => Duration::ZERO,
+        }
+    }
+    fn has_validators(&self) -> bool {
+        self.headers.contains_key(ETAG) || self.headers.contains_key(LAST_MODIFIED)
+    }
+    fn to_response(&self, status: CacheStatus, now: SystemTime) -> Response<ClientBody> {
+        let mut response = Response::new(BodyExt::boxed(
+            Full::new(self.body.clone()).map_err(|never| match never {}),
+        ));
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .headers_mut()
+            .insert(AGE, HeaderValue::from(self.age(now).as_secs()));
+        response.extensions_mut().insert(status);
+        response
+    }
+}
+
+fn http_date(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    httpdate::parse_http_date(headers.get(name)?.to_str().ok()?).ok()
+}
+
+/// The values of a header, joined with commas.
+fn joined(headers: &HeaderMap, name: &HeaderName) -> Option<HeaderValue> {
+    let mut values = headers.get_all(name).iter();
+    let first = values.next()?;
+    let mut joined = first.as_bytes().to_vec();
+    for value in values {
+        joined.extend_from_slice(b", ");
+        joined.extend_from_slice(value.as_bytes());
+    }
+    HeaderValue::from_bytes(&joined).ok()
+}
+
+/// The statuses which may be stored without explicit freshness, RFC 9110 section 15.1.
+fn is_heuristically_cacheable(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 206 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// The directives of the `Cache-Control` headers, with the seconds as durations.
+#[derive(Debug, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    public: bool,
+    must_revalidate: bool,
+    only_if_cached: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+    /// `Duration::MAX` without argument, any staleness is accepted.
+    max_stale: Option<Duration>,
+    min_fresh: Option<Duration>,
+    stale_if_error: Option<Duration>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut directives = CacheControl::default();
+        let values = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in values {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = argument
+                .and_then(|argument| argument.parse().ok())
+                .map(Duration::from_secs);
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "must-revalidate" | "proxy-revalidate" => directives.must_revalidate = true,
+                "only-if-cached" => directives.only_if_cached = true,
+                "max-age" => directives.max_age = Some(seconds.unwrap_or_default()),
+                "s-maxage" => directives.s_maxage = seconds,
+                "max-stale" => directives.max_stale = Some(seconds.unwrap_or(Duration::MAX)),
+                "min-fresh" => directives.min_fresh = seconds,
+                "stale-if-error" => directives.stale_if_error = seconds,
+                _ => {}
+            }
+        }
+        // HTTP/1.0 `Pragma: no-cache` of the requests
+        if headers
+            .get(PRAGMA)
+            .is_some_and(|pragma| pragma.as_bytes().eq_ignore_ascii_case(b"no-cache"))
+        {
+            directives.no_cache = true;
+        }
+        directives
+    }
+}
+
+/// The future returned by the methods of [`CacheStore`].
+pub type Storing<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A storage of the cached responses, by uri.
+///
+/// A uri has a response for each set of the request headers named by `Vary`. The methods are
+/// awaited by the requests, a store doing blocking I/O runs it off the async tasks, like
+/// [`DiskStore`].
+pub trait CacheStore: Send + Sync + 'static {
+    fn get(&self, key: &str) -> Storing<Vec<CachedResponse>>;
+    fn put(&self, key: &str, responses: Vec<CachedResponse>) -> Storing<()>;
+    fn remove(&self, key: &str) -> Storing<()>;
+}
+
+/// A store in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    responses: Mutex<HashMap<String, Vec<CachedResponse>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn responses(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<CachedResponse>>> {
+        self.responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Storing<Vec<CachedResponse>> {
+        let responses = self.responses().get(key).cloned().unwrap_or_default();
+        Box::pin(std::future::ready(responses))
+    }
+    fn put(&self, key: &str, responses: Vec<CachedResponse>) -> Storing<()> {
+        self.responses().insert(key.to_owned(), responses);
+        Box::pin(std::future::ready(()))
+    }
+    fn remove(&self, key: &str) -> Storing<()> {
+        self.responses().remove(key);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// A store on disk, a file for each uri in a directory.
+///
+/// A file is a line of JSON with the heads of the responses, followed by their bodies.
+///
+/// The files are read and written on the blocking threads of tokio, or of smol with the
+/// `runtime-smol` feature outside of a tokio runtime. Elsewhere the I/O blocks the thread polling
+/// the request.
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    directory: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredHead {
+    status: u16,
+    version: String,
+    headers: Vec<(String, Vec<u8>)>,
+    vary: Vec<(String, Option<Vec<u8>>)>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+    body_length: usize,
+}
+
+impl DiskStore {
+    /// Store the responses in `directory`, created if missing.
+    pub fn new(directory: impl Into<PathBuf>) -> io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = crate::util::fnv1a(key.as_bytes());
+        self.directory.join(format!("{hash:016x}"))
+    }
+    fn read(&self, key: &str) -> io::Result<Vec<CachedResponse>> {
+        let mut file = io::BufReader::new(std::fs::File::open(self.path(key))?);
+        let mut line = String::new();
+        file.read_line(&mut line)?;
+        let (stored_key, heads): (String, Vec<StoredHead>) = serde_json::from_str(&line)?;
+        // a hash collision
+        if stored_key != key {
+            return Ok(Vec::new());
+        }
+        let invalid = |error: &dyn fmt::Display| {
+            io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+        };
+        heads
+            .into_iter()
+            .map(|head| {
+                let mut body = vec![0; head.body_length];
+                file.read_exact(&mut body)?;
+                let mut headers = HeaderMap::new();
+                for (name, value) in head.headers {
+                    headers.append(
+                        HeaderName::try_from(name).map_err(|e| invalid(&e))?,
+                        HeaderValue::from_bytes(&value).map_err(|e| invalid(&e))?,
+                    );
+                }
+                let vary = head
+                    .vary
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let name = HeaderName::try_from(name).map_err(|e| invalid(&e))?;
+                        let value = value
+                            .map(|value| HeaderValue::from_bytes(&value))
+                            .transpose()
+                            .map_err(|e| invalid(&e))?;
+                        Ok((name, value))
+                    })
+                    .collect::<io::Result<_>>()?;
+                Ok(CachedResponse {
+                    status: StatusCode::from_u16(head.status).map_err(|e| invalid(&e))?,
+                    version: match head.version.as_str() {
+                        "HTTP/0.9" => Version::HTTP_09,
+                        "HTTP/1.0" => Version::HTTP_10,
+                        "HTTP/2.0" => Version::HTTP_2,
+                        "HTTP/3.0" => Version::HTTP_3,
+                        _ => Version::HTTP_11,
+                    },
+                    headers,
+                    body: body.into(),
+                    vary,
+                    request_time: head.request_time,
+                    response_time: head.response_time,
+                })
+            })
+            .collect()
+    }
+    fn write(&self, key: &str, responses: &[CachedResponse]) -> io::Result<()> {
+        let heads: Vec<StoredHead> = responses
+            .iter()
+            .map(|response| StoredHead {
+                status: response.status.as_u16(),
+                version: format!("{:?}", response.version),
+                headers: response
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+                    .collect(),
+                vary: response
+                    .vary
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            name.to_string(),
+                            value.as_ref().map(|value| value.as_bytes().to_vec()),
+                        )
+                    })
+                    .collect(),
+                request_time: response.request_time,
+                response_time: response.response_time,
+                body_length: response.body.len(),
+            })
+            .collect();
+        let path = self.path(key);
+        // written aside then renamed, a reader never sees a partial file: the name is unique to
+        // the write, so concurrent writes of a key, from this process or another one, don't
+        // interleave and the last rename wins
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+        let partial = path.with_extension(format!(
+            "{}-{}.partial",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = (|| {
+            let mut file = io::BufWriter::new(std::fs::File::create(&partial)?);
+            serde_json::to_writer(&mut file, &(key, heads))?;
+            file.write_all(b"\n")?;
+            for response in responses {
+                file.write_all(&response.body)?;
+            }
+            file.into_inner().map_err(io::IntoInnerError::into_error)?;
+            std::fs::rename(&partial, path)
+        })();
+        if written.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        written
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&self, key: &str) -> Storing<Vec<CachedResponse>> {
+        let (store, key) = (self.clone(), key.to_owned());
+        Box::pin(unblock(move || store.read(&key).unwrap_or_default()))
+    }
+    fn put(&self, key: &str, responses: Vec<CachedResponse>) -> Storing<()> {
+        let (store, key) = (self.clone(), key.to_owned());
+        Box::pin(unblock(move || {
+            // a response which can't be stored is fetched again
+            let _ = store.write(&key, &responses);
+        }))
+    }
+    fn remove(&self, key: &str) -> Storing<()> {
+        let path = self.path(key);
+        Box::pin(unblock(move || {
+            let _ = std::fs::remove_file(path);
+        }))
+    }
+}
+
+/// Run the blocking `f` on the blocking threads of the runtime, see [`DiskStore`]. The default
+/// value is returned when the runtime shuts down first.
+async fn unblock<T, F>(f: F) -> T
+where
+    T: Default + Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        return match runtime.spawn_blocking(f).await {
+            Ok(value) => value,
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => T::default(),
+        };
+    }
+    #[cfg(feature = "runtime-smol")]
+    return smol::unblock(f).await;
+    #[cfg(not(feature = "runtime-smol"))]
+    f()
+}
+
+#[derive(Clone)]
+struct Config {
+    store: Arc<dyn CacheStore>,
+    shared: bool,
+    stale_if_error: bool,
+    max_body: usize,
+}
+
+/// Layer for [`Cache`].
+#[derive(Clone)]
+pub struct CacheLayer {
+    config: Arc<Config>,
+}
+
+impl fmt::Debug for CacheLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheLayer")
+            .field("shared", &self.config.shared)
+            .field("stale_if_error", &self.config.stale_if_error)
+            .field("max_body", &self.config.max_body)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CacheLayer {
+    /// Cache the responses in `store`.
+    pub fn new(store: impl CacheStore) -> Self {
+        Self {
+            config: Arc::new(Config {
+                store: Arc::new(store),
+                shared: false,
+                stale_if_error: false,
+                max_body: 8 * 1024 * 1024,
+            }),
+        }
+    }
+    /// Cache the responses in memory.
+    pub fn memory() -> Self {
+        Self::new(MemoryStore::new())
+    }
+    /// Configure this layer, the clones of a layer keep the same store.
+    fn config(mut self, f: impl FnOnce(&mut Config)) -> Self {
+        f(Arc::make_mut(&mut self.config));
+        self
+    }
+    /// Behave as a shared cache, honoring `s-maxage` and not storing `private` responses, disabled
+    /// by default.
+    pub fn shared(self, shared: bool) -> Self {
+        self.config(|config| config.shared = shared)
+    }
+    /// Serve a stale response when the request fails or the server responds with a `5xx`, unless
+    /// it must be revalidated, disabled by default.
+    ///
+    /// Without it, the `stale-if-error` directive of the response is honored.
+    pub fn stale_if_error(self, enable: bool) -> Self {
+        self.config(|config| config.stale_if_error = enable)
+    }
+    /// The longest body which is stored, 8MiB by default.
+    pub fn max_body(self, max: usize) -> Self {
+        self.config(|config| config.max_body = max)
+    }
+}
+
+impl<S> tower::Layer<S> for CacheLayer {
+    type Service = Cache<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Cache {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Cache the responses, see [`crate::client::cache`].
+#[derive(Clone)]
+pub struct Cache<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Cache<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+fn key(uri: &Uri) -> String {
+    uri.to_string()
+}
+
+fn is_unsafe(method: &Method) -> bool {
+    !matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+fn is_conditional(headers: &HeaderMap) -> bool {
+    [
+        IF_MATCH,
+        IF_NONE_MATCH,
+        IF_MODIFIED_SINCE,
+        IF_UNMODIFIED_SINCE,
+        IF_RANGE,
+    ]
+    .iter()
+    .any(|name| headers.contains_key(name))
+}
+
+impl Config {
+    /// Whether the response can be stored, RFC 9111 section 3.
+    fn storable(&self, request: &HeaderMap, response: &Response<ClientBody>) -> bool {
+        let directives = CacheControl::parse(response.headers());
+        let has_validators =
+            response.headers().contains_key(ETAG) || response.headers().contains_key(LAST_MODIFIED);
+        let explicit = directives.max_age.is_some()
+            || (self.shared && directives.s_maxage.is_some())
+            || response.headers().contains_key(EXPIRES)
+            || directives.public;
+        let varies_on_all = response
+            .headers()
+            .get_all(VARY)
+            .iter()
+            .any(|vary| vary.as_bytes().trim_ascii() == b"*");
+        if directives.no_store
+            || CacheControl::parse(request).no_store
+            || varies_on_all
+            || response.status() == StatusCode::PARTIAL_CONTENT
+        {
+            return false;
+        }
+        // a shared cache doesn't store the private or authenticated responses
+        let authorized =
+            directives.public || directives.s_maxage.is_some() || directives.must_revalidate;
+        if self.shared
+            && (directives.private
+                || (request.contains_key(http::header::AUTHORIZATION) && !authorized))
+        {
+            return false;
+        }
+        explicit || (is_heuristically_cacheable(response.status()) && has_validators)
+    }
+    fn lifetime(&self, cached: &CachedResponse) -> Duration {
+        let mut directives = CacheControl::parse(&cached.headers);
+        if self.shared {
+            directives.max_age = directives.s_maxage.or(directives.max_age);
+        }
+        cached.lifetime(&directives)
+    }
+    /// Whether a stale response can be served after an error.
+    fn serve_stale_on_error(&self, cached: &CachedResponse, now: SystemTime) -> bool {
+        let directives = CacheControl::parse(&cached.headers);
+        if directives.must_revalidate || directives.no_cache {
+            return false;
+        }
+        let staleness = cached.age(now).saturating_sub(self.lifetime(cached));
+        self.stale_if_error
+            || directives
+                .stale_if_error
+                .is_some_and(|allowed| staleness <= allowed)
+    }
+    async fn store(&self, key: &str, mut entries: Vec<CachedResponse>, cached: CachedResponse) {
+        // a response for the same request headers is replaced
+        entries.retain(|entry| entry.vary != cached.vary);
+        entries.push(cached);
+        self.store.put(key, entries).await;
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Cache<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+    RB: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    RB::Error: Into<BoxError>,
+{
+    type Response = Response<ClientBody>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // the ready service is taken, it's dropped when the response is served from the store
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        let key = key(request.uri());
+        let boxed = |response: Response<RB>, status: CacheStatus| {
+            let mut response = response.map(|body| BodyExt::boxed(body.map_err(Into::into)));
+            response.extensions_mut().insert(status);
+            response
+        };
+        let requested = CacheControl::parse(request.headers());
+        if request.method() != Method::GET
+            || requested.no_store
+            || is_conditional(request.headers())
+        {
+            let invalidate = is_unsafe(request.method());
+            let mut inner = inner;
+            let sending = attempt(inner.call(request));
+            return Box::pin(async move {
+                let response = sending.await?;
+                if invalidate
+                    && (response.status().is_success() || response.status().is_redirection())
+                {
+                    config.store.remove(&key).await;
+                }
+                Ok(boxed(response, CacheStatus::Bypass))
+            });
+        }
+        let headers = request.headers().clone();
+        let mut inner = inner;
+        Box::pin(async move {
+            let entries = config.store.get(&key).await;
+            let now = SystemTime::now();
+            let cached = entries
+                .iter()
+                .find(|entry| entry.matches(&headers))
+                .cloned();
+            if let Some(cached) = &cached {
+                let directives = CacheControl::parse(&cached.headers);
+                let age = cached.age(now);
+                let lifetime = config.lifetime(cached);
+                let fresh = age + requested.min_fresh.unwrap_or_default() < lifetime
+                    && requested.max_age.is_none_or(|max_age| age <= max_age);
+                let stale_allowed = !fresh
+                    && !directives.must_revalidate
+                    && requested
+                        .max_stale
+                        .is_some_and(|max_stale| age <= lifetime.saturating_add(max_stale));
+                if (fresh || stale_allowed) && !directives.no_cache && !requested.no_cache {
+                    return Ok(cached.to_response(CacheStatus::Hit, now));
+                }
+            }
+            if requested.only_if_cached {
+                let mut response = Response::new(ClientBody::default());
+                *response.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+                response.extensions_mut().insert(CacheStatus::Miss);
+                return Ok(response);
+            }
+            if let Some(cached) = cached.as_ref().filter(|cached| cached.has_validators()) {
+                if let Some(etag) = cached.headers.get(ETAG) {
+                    request.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+                }
+                if let Some(modified) = cached.headers.get(LAST_MODIFIED) {
+                    request
+                        .headers_mut()
+                        .insert(IF_MODIFIED_SINCE, modified.clone());
+                }
+            }
+            let request_time = now;
+            let result = attempt(inner.call(request)).await;
+            let now = SystemTime::now();
+            let response = match (result, cached) {
+                (Ok(response), Some(mut cached))
+                    if response.status() == StatusCode::NOT_MODIFIED =>
+                {
+                    // the headers of the 304 update the stored ones
+                    for name in response.headers().keys() {
+                        if *name == CONTENT_LENGTH {
+                            continue;
+                        }
+                        cached.headers.remove(name);
+                        for value in response.headers().get_all(name) {
+                            cached.headers.append(name.clone(), value.clone());
+                        }
+                    }
+                    cached.request_time = request_time;
+                    cached.response_time = now;
+                    let response = cached.to_response(CacheStatus::Revalidated, now);
+                    config.store(&key, entries, cached).await;
+                    return Ok(response);
+                }
+                (Ok(response), Some(cached))
+                    if response.status().is_server_error()
+                        && config.serve_stale_on_error(&cached, now) =>
+                {
+                    return Ok(cached.to_response(CacheStatus::Stale, now));
+                }
+                (Err(_), Some(cached)) if config.serve_stale_on_error(&cached, now) => {
+                    return Ok(cached.to_response(CacheStatus::Stale, now));
+                }
+                (result, _) => result?,
+            };
+            let mut response = boxed(response, CacheStatus::Miss);
+            if !config.storable(&headers, &response) {
+                return Ok(response);
+            }
+            let body = std::mem::take(response.body_mut());
+            let (data, trailers) = match ReplayBody::read(body, config.max_body).await? {
+                ReplayBody::Buffered { data, trailers } => (data, trailers),
+                ReplayBody::Streaming(body) => {
+                    *response.body_mut() = body;
+                    return Ok(response);
+                }
+            };
+            let vary = response
+                .headers()
+                .get_all(VARY)
+                .iter()
+                .filter_map(|vary| vary.to_str().ok())
+                .flat_map(|vary| vary.split(','))
+                .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+                .map(|name| {
+                    let value = joined(&headers, &name);
+                    (name, value)
+                })
+                .collect();
+            let cached = CachedResponse {
+                status: response.status(),
+                version: response.version(),
+                headers: response.headers().clone(),
+                body: data,
+                vary,
+                request_time,
+                response_time: now,
+            };
+            *response.body_mut() = ReplayBody::Buffered {
+                data: cached.body.clone(),
+                trailers,
+            }
+            .next();
+            config.store(&key, entries, cached).await;
+            Ok(response)
+        })
+    }
+}
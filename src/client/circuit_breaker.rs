@@ -0,0 +1,439 @@
+//! Circuit breaker, by origin.
+//!
+//! The [`CircuitBreakerLayer`] counts the failures of the requests to each origin, the scheme and
+//! the authority of their uri, see [`origin`]:
+//! - closed: the requests are sent, [`CircuitPolicy::failure_threshold`] consecutive failures open
+//!   the circuit.
+//! - open: the requests fail at once with a [`CircuitOpenError`], until
+//!   [`CircuitPolicy::open_duration`] elapsed.
+//! - half-open: up to [`CircuitPolicy::probes`] requests are sent at once as probes, the other ones
+//!   are rejected. A failed probe opens the circuit again, it's closed once as many probes
+//!   succeeded.
+//!
+//! A failure is a connect error, a timeout, see [`crate::client::timeout`], a `5xx` response, or
+//! another error of the inner client. The first three can be ignored with the policy: an ignored
+//! failure neither counts as a failure nor as a success.
+//!
+//! The rejected requests fail with [`Error::CircuitOpen`](crate::Error::CircuitOpen):
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use std::time::Duration;
+//! use client_util::client::circuit_breaker::{CircuitBreaker, CircuitBreakerLayer, CircuitPolicy};
+//! use client_util::prelude::*;
+//! let breaker = CircuitBreaker::new(
+//!     CircuitPolicy::new()
+//!         .failure_threshold(3)
+//!         .open_duration(Duration::from_secs(10)),
+//! );
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(CircuitBreakerLayer::new(breaker.clone()))
+//!     .service(build_https_client().expect("failed to build client"));
+//! match RequestBuilder::get("https://example.com/")?.empty().send(client).await {
+//!     Err(client_util::Error::CircuitOpen(open)) => println!("retry in {:?}", open.retry_in),
+//!     response => drop(response?),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use http::{Request, Response, Uri};
+
+use crate::client::hyper::is_connect_error;
+use crate::client::replay::attempt;
+use crate::client::timeout::TimeoutError;
+use crate::error::BoxError;
+
+/// When to open and close the circuits.
+#[derive(Debug, Clone)]
+pub struct CircuitPolicy {
+    failure_threshold: u32,
+    open_duration: Duration,
+    probes: u32,
+    connect_errors: bool,
+    timeouts: bool,
+    server_errors: bool,
+}
+
+impl Default for CircuitPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+            probes: 1,
+            connect_errors: true,
+            timeouts: true,
+            server_errors: true,
+        }
+    }
+}
+
+impl CircuitPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The consecutive failures which open the circuit, `5` by default.
+    pub fn failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+    /// How long the circuit stays open before probing the origin, `30s` by default.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+    /// The probes of a half-open circuit, sent at once and which must all succeed, `1` by default.
+    pub fn probes(mut self, probes: u32) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+    /// Count the connect errors as failures, enabled by default.
+    pub fn connect_errors(mut self, enable: bool) -> Self {
+        self.connect_errors = enable;
+        self
+    }
+    /// Count the timeouts as failures, enabled by default.
+    pub fn timeouts(mut self, enable: bool) -> Self {
+        self.timeouts = enable;
+        self
+    }
+    /// Count the `5xx` responses as failures, enabled by default.
+    pub fn server_errors(mut self, enable: bool) -> Self {
+        self.server_errors = enable;
+        self
+    }
+}
+
+/// The state of the circuit of an origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The requests are sent.
+    Closed,
+    /// The requests are rejected.
+    Open,
+    /// Some requests are sent to probe the origin.
+    HalfOpen,
+}
+
+/// A request rejected by an open circuit.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("circuit open for {origin}, retry in {retry_in:?}")]
+pub struct CircuitOpenError {
+    pub origin: String,
+    /// The time before the circuit is half-open, zero when it's half-open and the probes are
+    /// already sent.
+    pub retry_in: Duration,
+}
+
+impl CircuitOpenError {
+    /// Find a circuit open error in the chain of sources of `error`.
+    pub fn find(error: &(dyn std::error::Error + 'static)) -> Option<CircuitOpenError> {
+        let mut error = Some(error);
+        while let Some(current) = error {
+            if let Some(open) = current.downcast_ref::<CircuitOpenError>() {
+                return Some(open.clone());
+            }
+            error = current.source();
+        }
+        None
+    }
+}
+
+/// The key of the circuit of a request, its scheme and authority, like `https://example.com`.
+pub fn origin(uri: &Uri) -> String {
+    format!(
+        "{}://{}",
+        uri.scheme_str().unwrap_or("http"),
+        uri.authority()
+            .map(|authority| authority.as_str())
+            .unwrap_or_default()
+    )
+    .to_ascii_lowercase()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed,
+    Open { until: Instant },
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: State,
+    /// The consecutive failures, when closed.
+    failures: u32,
+    /// The probes sent and succeeded, when half-open.
+    probing: u32,
+    succeeded: u32,
+    /// Incremented when the circuit becomes half-open, the probes of a previous period are ignored.
+    period: u64,
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self {
+            state: State::Closed,
+            failures: 0,
+            probing: 0,
+            succeeded: 0,
+            period: 0,
+        }
+    }
+}
+
+impl Circuit {
+    /// Close the circuit, keeping its period.
+    fn close(&mut self) {
+        *self = Circuit {
+            period: self.period,
+            ..Circuit::default()
+        };
+    }
+}
+
+#[derive(Debug)]
+struct Circuits {
+    policy: CircuitPolicy,
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+/// The shared state of the circuit breaker, a circuit for each origin.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Circuits>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: CircuitPolicy) -> Self {
+        Self {
+            inner: Arc::new(Circuits {
+                policy,
+                circuits: Mutex::default(),
+            }),
+        }
+    }
+    pub fn policy(&self) -> &CircuitPolicy {
+        &self.inner.policy
+    }
+    fn circuits(&self) -> MutexGuard<'_, HashMap<String, Circuit>> {
+        self.inner
+            .circuits
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// The state of the circuit of `origin`, see [`origin`].
+    pub fn state(&self, origin: &str) -> CircuitState {
+        match self.circuits().get(origin).map(|circuit| circuit.state) {
+            None | Some(State::Closed) => CircuitState::Closed,
+            Some(State::Open { until }) if until > Instant::now() => CircuitState::Open,
+            Some(State::Open { .. } | State::HalfOpen) => CircuitState::HalfOpen,
+        }
+    }
+    /// Close the circuit of `origin`.
+    pub fn reset(&self, origin: &str) {
+        if let Some(circuit) = self.circuits().get_mut(origin) {
+            circuit.close();
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Let a request through, the period of the probe when half-open.
+    fn acquire(&self, origin: &str) -> Result<Option<u64>, CircuitOpenError> {
+        let now = Instant::now();
+        let probes = self.inner.policy.probes;
+        let mut circuits = self.circuits();
+        let circuit = circuits.entry(origin.to_owned()).or_default();
+        if let State::Open { until } = circuit.state {
+            if until > now {
+                return Err(CircuitOpenError {
+                    origin: origin.to_owned(),
+                    retry_in: until - now,
+                });
+            }
+            circuit.state = State::HalfOpen;
+            circuit.probing = 0;
+            circuit.succeeded = 0;
+            circuit.period += 1;
+        }
+        match circuit.state {
+            State::Closed => Ok(None),
+            State::HalfOpen if circuit.probing < probes => {
+                circuit.probing += 1;
+                Ok(Some(circuit.period))
+            }
+            State::HalfOpen | State::Open { .. } => Err(CircuitOpenError {
+                origin: origin.to_owned(),
+                retry_in: Duration::ZERO,
+            }),
+        }
+    }
+    /// Record the outcome of a request, `None` when it was dropped before the response or its
+    /// failure is ignored.
+    fn record(&self, origin: &str, probe: Option<u64>, failed: Option<bool>) {
+        let policy = &self.inner.policy;
+        let mut circuits = self.circuits();
+        let Some(circuit) = circuits.get_mut(origin) else {
+            return;
+        };
+        let open = |circuit: &mut Circuit| {
+            circuit.state = State::Open {
+                until: Instant::now() + policy.open_duration,
+            };
+            circuit.failures = 0;
+        };
+        match (circuit.state, probe) {
+            (State::Closed, None) => match failed {
+                Some(true) => {
+                    circuit.failures += 1;
+                    if circuit.failures >= policy.failure_threshold {
+                        open(circuit);
+                    }
+                }
+                Some(false) => circuit.failures = 0,
+                None => {}
+            },
+            (State::HalfOpen, Some(period)) if period == circuit.period => {
+                circuit.probing -= 1;
+                match failed {
+                    Some(true) => open(circuit),
+                    Some(false) => {
+                        circuit.succeeded += 1;
+                        if circuit.succeeded >= policy.probes {
+                            circuit.close();
+                        }
+                    }
+                    None => {}
+                }
+            }
+            // the circuit changed while the request was sent
+            _ => {}
+        }
+    }
+    /// Whether `error` is a failure, `None` when the policy ignores it.
+    fn is_failure(&self, error: &(dyn std::error::Error + 'static)) -> Option<bool> {
+        let policy = &self.inner.policy;
+        if is_connect_error(error) {
+            policy.connect_errors.then_some(true)
+        } else if TimeoutError::find(error).is_some() {
+            policy.timeouts.then_some(true)
+        } else {
+            Some(true)
+        }
+    }
+}
+
+/// A request let through, recorded as dropped unless it completes.
+struct Pending {
+    breaker: CircuitBreaker,
+    origin: String,
+    probe: Option<u64>,
+    done: bool,
+}
+
+impl Pending {
+    fn complete(mut self, failed: Option<bool>) {
+        self.done = true;
+        self.breaker.record(&self.origin, self.probe, failed);
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if !self.done {
+            self.breaker.record(&self.origin, self.probe, None);
+        }
+    }
+}
+
+/// Layer for [`CircuitBreak`].
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerLayer {
+    breaker: CircuitBreaker,
+}
+
+impl CircuitBreakerLayer {
+    pub fn new(breaker: CircuitBreaker) -> Self {
+        Self { breaker }
+    }
+}
+
+impl<S> tower::Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreak<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreak {
+            inner,
+            breaker: self.breaker.clone(),
+        }
+    }
+}
+
+/// Reject the requests to the origins whose circuit is open, see
+/// [`crate::client::circuit_breaker`].
+#[derive(Debug, Clone)]
+pub struct CircuitBreak<S> {
+    inner: S,
+    breaker: CircuitBreaker,
+}
+
+impl<S> CircuitBreak<S> {
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for CircuitBreak<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let origin = origin(request.uri());
+        let probe = match self.breaker.acquire(&origin) {
+            Ok(probe) => probe,
+            Err(open) => return Box::pin(std::future::ready(Err(open.into()))),
+        };
+        let pending = Pending {
+            breaker: self.breaker.clone(),
+            origin,
+            probe,
+            done: false,
+        };
+        let response = attempt(self.inner.call(request));
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => {
+                    let failed = if !response.status().is_server_error() {
+                        Some(false)
+                    } else {
+                        pending.breaker.inner.policy.server_errors.then_some(true)
+                    };
+                    pending.complete(failed);
+                    Ok(response)
+                }
+                Err(error) => {
+                    let failed = pending.breaker.is_failure(&*error);
+                    pending.complete(failed);
+                    Err(error)
+                }
+            }
+        })
+    }
+}
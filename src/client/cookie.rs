@@ -0,0 +1,395 @@
+//! A client cookie store, following RFC 6265.
+//!
+//! The [`CookieLayer`] stores the cookies of the `Set-Cookie` headers of the responses in a
+//! [`CookieJar`], and sends the matching ones in the `Cookie` header of the requests: by domain,
+//! path, `Secure` attribute and expiry.
+//!
+//! Put the layer inside the [`RedirectLayer`](crate::client::redirect::RedirectLayer), so each hop
+//! sends and stores its own cookies.
+//!
+//! The jar can be saved to and loaded from JSON, to keep a session across runs:
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::cookie::{CookieJar, CookieLayer};
+//! use client_util::prelude::*;
+//! let jar = match std::fs::File::open("cookies.json") {
+//!     Ok(file) => CookieJar::load_json(std::io::BufReader::new(file)).expect("invalid jar"),
+//!     Err(_) => CookieJar::new(),
+//! };
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(CookieLayer::new(jar.clone()))
+//!     .service(build_https_client().expect("failed to build client"));
+//! RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! let file = std::fs::File::create("cookies.json").expect("failed to create file");
+//! jar.save_json(file).expect("failed to save jar");
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use futures_util::TryFutureExt;
+use http::header::{COOKIE, SET_COOKIE};
+use http::{HeaderMap, HeaderValue, Request, Response, Uri};
+use publicsuffix::Psl;
+use serde::{Deserialize, Serialize};
+
+pub use publicsuffix::List as PublicSuffixList;
+
+/// A stored cookie.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// The domain, lowercase and without leading dot.
+    pub domain: String,
+    /// Whether the cookie is only sent to its domain, not to its subdomains.
+    pub host_only: bool,
+    pub path: String,
+    pub secure: bool,
+    pub http_only: bool,
+    /// `None` for a session cookie.
+    pub expires: Option<SystemTime>,
+    created: SystemTime,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+    /// Whether the cookie is sent with a request to `uri`.
+    pub fn matches(&self, uri: &Uri) -> bool {
+        let Some(host) = uri.host() else {
+            return false;
+        };
+        let host = normalize_host(host);
+        let domain_match = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_match
+            && path_match(uri.path(), &self.path)
+            && (!self.secure || uri.scheme_str() == Some("https"))
+    }
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase()
+}
+
+/// Whether `host` is `domain` or one of its subdomains, an ip address only matches itself.
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<IpAddr>().is_err())
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The directory of the request path, the path of a cookie without `Path` attribute.
+fn default_path(uri: &Uri) -> String {
+    let path = uri.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(end) => path[..end].to_owned(),
+    }
+}
+
+type Key = (String, String, String);
+
+#[derive(Default)]
+struct Store {
+    cookies: HashMap<Key, Cookie>,
+}
+
+/// A shared store of cookies.
+#[derive(Clone, Default)]
+pub struct CookieJar {
+    store: Arc<RwLock<Store>>,
+    public_suffixes: Option<Arc<PublicSuffixList>>,
+}
+
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the values are credentials
+        f.debug_struct("CookieJar")
+            .field("len", &self.read().cookies.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reject the cookies whose domain is a public suffix, like `com` or `co.uk`, which would be
+    /// sent to every site under it.
+    ///
+    /// The list is parsed from the `public_suffix_list.dat` of <https://publicsuffix.org/list/>.
+    pub fn public_suffix_list(mut self, list: PublicSuffixList) -> Self {
+        self.public_suffixes = Some(Arc::new(list));
+        self
+    }
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Store> {
+        self.store.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Store> {
+        self.store.write().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn is_public_suffix(&self, domain: &str) -> bool {
+        self.public_suffixes.as_ref().is_some_and(|list| {
+            list.suffix(domain.as_bytes())
+                .is_some_and(|suffix| suffix.is_known() && suffix == domain)
+        })
+    }
+    /// Store the cookies of the `Set-Cookie` headers of a response to a request to `uri`.
+    pub fn store_response_cookies(&self, uri: &Uri, headers: &HeaderMap) {
+        let now = SystemTime::now();
+        for header in headers.get_all(SET_COOKIE) {
+            if let Some(cookie) = header
+                .to_str()
+                .ok()
+                .and_then(|header| self.parse(uri, header, now))
+            {
+                self.insert(cookie, now);
+            }
+        }
+    }
+    /// Store a cookie received in a response to a request to `uri`, it's ignored when invalid.
+    pub fn store(&self, uri: &Uri, set_cookie: &str) {
+        let now = SystemTime::now();
+        if let Some(cookie) = self.parse(uri, set_cookie, now) {
+            self.insert(cookie, now);
+        }
+    }
+    fn parse(&self, uri: &Uri, set_cookie: &str, now: SystemTime) -> Option<Cookie> {
+        let raw = ::cookie::Cookie::parse(set_cookie).ok()?;
+        let host = normalize_host(uri.host()?);
+        let secure_origin = uri.scheme_str() == Some("https");
+        let secure = raw.secure().unwrap_or(false);
+        if secure && !secure_origin {
+            return None;
+        }
+        let (domain, host_only) = match raw.domain().map(str::to_ascii_lowercase) {
+            Some(domain) if !domain.is_empty() => {
+                if self.is_public_suffix(&domain) {
+                    if domain != host {
+                        return None;
+                    }
+                    (domain, true)
+                } else if domain_match(&host, &domain) {
+                    (domain, false)
+                } else {
+                    return None;
+                }
+            }
+            _ => (host, true),
+        };
+        let path = match raw.path() {
+            Some(path) if path.starts_with('/') => path.to_owned(),
+            _ => default_path(uri),
+        };
+        // Max-Age takes precedence over Expires
+        let expires = match (raw.max_age(), raw.expires_datetime()) {
+            (Some(max_age), _) => Some(match u64::try_from(max_age.whole_seconds()) {
+                Ok(seconds) => now
+                    .checked_add(Duration::from_secs(seconds))
+                    .unwrap_or(now + Duration::from_secs(u32::MAX.into())),
+                Err(_) => SystemTime::UNIX_EPOCH,
+            }),
+            (None, Some(expires)) => Some(
+                u64::try_from(expires.unix_timestamp()).map_or(SystemTime::UNIX_EPOCH, |seconds| {
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+                }),
+            ),
+            (None, None) => None,
+        };
+        Some(Cookie {
+            name: raw.name().to_owned(),
+            value: raw.value().to_owned(),
+            domain,
+            host_only,
+            path,
+            secure,
+            http_only: raw.http_only().unwrap_or(false),
+            expires,
+            created: now,
+        })
+    }
+    fn insert(&self, mut cookie: Cookie, now: SystemTime) {
+        let key = (
+            cookie.domain.clone(),
+            cookie.path.clone(),
+            cookie.name.clone(),
+        );
+        let mut store = self.write();
+        // an expired cookie deletes the stored one
+        if cookie.is_expired(now) {
+            store.cookies.remove(&key);
+            return;
+        }
+        if let Some(old) = store.cookies.get(&key) {
+            cookie.created = old.created;
+        }
+        store.cookies.insert(key, cookie);
+    }
+    /// The cookies to send with a request to `uri`, longer paths first.
+    pub fn matching(&self, uri: &Uri) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        let mut cookies: Vec<Cookie> = self
+            .read()
+            .cookies
+            .values()
+            .filter(|cookie| !cookie.is_expired(now) && cookie.matches(uri))
+            .cloned()
+            .collect();
+        cookies.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.created.cmp(&b.created))
+        });
+        cookies
+    }
+    /// The `Cookie` header of a request to `uri`, `None` without matching cookie.
+    pub fn cookie_header(&self, uri: &Uri) -> Option<HeaderValue> {
+        let cookies = self.matching(uri);
+        if cookies.is_empty() {
+            return None;
+        }
+        let header = cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut header = HeaderValue::from_str(&header).ok()?;
+        header.set_sensitive(true);
+        Some(header)
+    }
+    /// All the cookies which aren't expired.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        self.read()
+            .cookies
+            .values()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect()
+    }
+    pub fn remove(&self, domain: &str, path: &str, name: &str) -> Option<Cookie> {
+        self.write()
+            .cookies
+            .remove(&(domain.to_owned(), path.to_owned(), name.to_owned()))
+    }
+    pub fn clear(&self) {
+        self.write().cookies.clear();
+    }
+    /// Remove the session cookies, as when the session ends.
+    pub fn clear_session_cookies(&self) {
+        self.write()
+            .cookies
+            .retain(|_, cookie| cookie.expires.is_some());
+    }
+    /// Save the cookies which aren't expired as JSON, session cookies included.
+    pub fn save_json<W: std::io::Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, &self.cookies())
+    }
+    /// Load a jar saved with [`CookieJar::save_json`], without the expired cookies.
+    pub fn load_json<R: std::io::Read>(reader: R) -> serde_json::Result<Self> {
+        let cookies: Vec<Cookie> = serde_json::from_reader(reader)?;
+        let jar = Self::new();
+        let now = SystemTime::now();
+        for cookie in cookies {
+            jar.insert(cookie, now);
+        }
+        Ok(jar)
+    }
+}
+
+/// Layer for [`Cookies`].
+#[derive(Debug, Clone, Default)]
+pub struct CookieLayer {
+    jar: CookieJar,
+}
+
+impl CookieLayer {
+    pub fn new(jar: CookieJar) -> Self {
+        Self { jar }
+    }
+}
+
+impl<S> tower::Layer<S> for CookieLayer {
+    type Service = Cookies<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Cookies {
+            inner,
+            jar: self.jar.clone(),
+        }
+    }
+}
+
+/// Send and store the cookies of a [`CookieJar`].
+#[derive(Debug, Clone)]
+pub struct Cookies<S> {
+    inner: S,
+    jar: CookieJar,
+}
+
+impl<S> Cookies<S> {
+    pub fn jar(&self) -> &CookieJar {
+        &self.jar
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Cookies<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>>,
+{
+    type Response = Response<RB>;
+    type Error = S::Error;
+    type Future = futures_util::future::MapOk<
+        S::Future,
+        Box<dyn FnOnce(Response<RB>) -> Response<RB> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let uri = request.uri().clone();
+        if let Some(cookies) = self.jar.cookie_header(&uri) {
+            // the cookies set on the request are kept, before the ones of the jar
+            let header = match request.headers().get(COOKIE) {
+                Some(own) => {
+                    let mut joined = own.as_bytes().to_vec();
+                    joined.extend_from_slice(b"; ");
+                    joined.extend_from_slice(cookies.as_bytes());
+                    let mut joined = HeaderValue::from_bytes(&joined).unwrap_or(cookies);
+                    joined.set_sensitive(true);
+                    joined
+                }
+                None => cookies,
+            };
+            request.headers_mut().insert(COOKIE, header);
+        }
+        let jar = self.jar.clone();
+        self.inner.call(request).map_ok(Box::new(move |response| {
+            jar.store_response_cookies(&uri, response.headers());
+            response
+        }))
+    }
+}
@@ -0,0 +1,238 @@
+//! DNS resolution for the hyper client.
+//!
+//! [`DnsResolver`] is the resolver used by the connectors built in [`crate::client::hyper`].
+//! It wraps a pluggable [`Resolve`] implementation and adds:
+//! - static host overrides, like curl's `--resolve`. The request uri is untouched,
+//!   so the `Host` header and TLS SNI still use the original name.
+//! - pinning the resolved addresses to IPv4 or IPv6.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+pub use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+
+use crate::error::BoxError;
+
+/// Resolved addresses.
+pub type Addrs = Box<dyn Iterator<Item = SocketAddr> + Send>;
+
+/// The future returned by [`Resolve::resolve`].
+pub type Resolving = Pin<Box<dyn Future<Output = Result<Addrs, BoxError>> + Send>>;
+
+/// An async DNS resolver.
+///
+/// The port of the returned addresses is ignored when the request uri has an explicit port.
+/// Return port `0` to always use the port of the uri.
+pub trait Resolve: Send + Sync {
+    fn resolve(&self, name: Name) -> Resolving;
+}
+
+impl Resolve for GaiResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let mut resolver = self.clone();
+        Box::pin(async move {
+            let addrs = tower_service::Service::call(&mut resolver, name).await?;
+            Ok(Box::new(addrs) as Addrs)
+        })
+    }
+}
+
+/// Which ip family the resolved addresses are restricted to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IpFamily {
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    #[inline]
+    pub fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpFamily::Any => true,
+            IpFamily::V4 => addr.is_ipv4(),
+            IpFamily::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("no {family:?} address resolved for {name}")]
+pub struct NoAddressError {
+    pub name: String,
+    pub family: IpFamily,
+}
+
+/// The resolver used by the hyper client connectors.
+#[derive(Clone)]
+pub struct DnsResolver {
+    resolver: Arc<dyn Resolve>,
+    overrides: Arc<HashMap<String, Vec<SocketAddr>>>,
+    ip_family: IpFamily,
+}
+
+impl fmt::Debug for DnsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DnsResolver")
+            .field("overrides", &self.overrides)
+            .field("ip_family", &self.ip_family)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for DnsResolver {
+    fn default() -> Self {
+        Self::new(GaiResolver::new())
+    }
+}
+
+impl DnsResolver {
+    /// Create a resolver backed by `resolver`.
+    pub fn new<R>(resolver: R) -> Self
+    where
+        R: Resolve + 'static,
+    {
+        Self {
+            resolver: Arc::new(resolver),
+            overrides: Default::default(),
+            ip_family: IpFamily::Any,
+        }
+    }
+    /// Create a resolver backed by the system resolver (`getaddrinfo`).
+    pub fn system() -> Self {
+        Self::default()
+    }
+    /// Create a resolver backed by hickory-dns, configured from the system configuration.
+    ///
+    /// Lookups are cached, and cached records expire according to their TTL.
+    #[cfg(feature = "hickory-dns")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hickory-dns")))]
+    pub fn hickory() -> Result<Self, BoxError> {
+        Ok(Self::new(HickoryResolver::from_system_conf()?))
+    }
+    /// Resolve `host` to `addrs` without asking the underlying resolver.
+    ///
+    /// Calling this again with the same host replaces the previous override.
+    pub fn with_override(
+        mut self,
+        host: impl Into<String>,
+        addrs: impl IntoIterator<Item = SocketAddr>,
+    ) -> Self {
+        let mut host = host.into();
+        host.make_ascii_lowercase();
+        Arc::make_mut(&mut self.overrides).insert(host, addrs.into_iter().collect());
+        self
+    }
+    /// Only connect to addresses of the given family.
+    pub fn with_ip_family(mut self, ip_family: IpFamily) -> Self {
+        self.ip_family = ip_family;
+        self
+    }
+    #[inline]
+    pub fn ip_family(&self) -> IpFamily {
+        self.ip_family
+    }
+}
+
+impl tower_service::Service<Name> for DnsResolver {
+    type Response = Addrs;
+    type Error = BoxError;
+    type Future = Resolving;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let ip_family = self.ip_family;
+        let host = name.as_str().to_ascii_lowercase();
+        let resolving = match self.overrides.get(&host) {
+            Some(addrs) => {
+                let addrs = addrs.clone();
+                Box::pin(async move { Ok(Box::new(addrs.into_iter()) as Addrs) })
+            }
+            None => self.resolver.resolve(name),
+        };
+        Box::pin(async move {
+            let addrs = resolving
+                .await?
+                .filter(|addr| ip_family.matches(addr))
+                .collect::<Vec<_>>();
+            crate::client::metadata::record_dns();
+            if addrs.is_empty() {
+                return Err(NoAddressError {
+                    name: host,
+                    family: ip_family,
+                }
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(feature = "hickory-dns")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hickory-dns")))]
+pub use hickory::*;
+
+#[cfg(feature = "hickory-dns")]
+mod hickory {
+    use super::{Addrs, Name, Resolve, Resolving};
+    use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+    use hickory_resolver::name_server::TokioConnectionProvider;
+    use hickory_resolver::TokioResolver;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    /// A [`Resolve`] implementation backed by hickory-dns.
+    ///
+    /// hickory keeps a cache of the looked up records, sized by [`ResolverOpts::cache_size`],
+    /// and respects the record TTLs.
+    #[derive(Debug, Clone)]
+    pub struct HickoryResolver {
+        inner: Arc<TokioResolver>,
+    }
+
+    impl HickoryResolver {
+        /// Read the configuration from `/etc/resolv.conf` on unix, or the registry on windows.
+        pub fn from_system_conf() -> Result<Self, hickory_resolver::ResolveError> {
+            let resolver = TokioResolver::builder_tokio()?.build();
+            Ok(Self::from_resolver(resolver))
+        }
+        pub fn new(config: ResolverConfig, options: ResolverOpts) -> Self {
+            let resolver =
+                TokioResolver::builder_with_config(config, TokioConnectionProvider::default())
+                    .with_options(options)
+                    .build();
+            Self::from_resolver(resolver)
+        }
+        pub fn from_resolver(resolver: TokioResolver) -> Self {
+            Self {
+                inner: Arc::new(resolver),
+            }
+        }
+        /// Drop all the cached records.
+        pub fn clear_cache(&self) {
+            self.inner.clear_cache();
+        }
+    }
+
+    impl Resolve for HickoryResolver {
+        fn resolve(&self, name: Name) -> Resolving {
+            let resolver = self.inner.clone();
+            Box::pin(async move {
+                let lookup = resolver.lookup_ip(name.as_str()).await?;
+                let addrs = lookup
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, 0))
+                    .collect::<Vec<_>>();
+                Ok(Box::new(addrs.into_iter()) as Addrs)
+            })
+        }
+    }
+}
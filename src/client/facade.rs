@@ -0,0 +1,484 @@
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::future::{Either, MapErr, Ready};
+use futures_util::TryFutureExt;
+use http::header::{
+    GetAll, HeaderMap, HeaderName, HeaderValue, IntoHeaderName, AUTHORIZATION, COOKIE,
+    PROXY_AUTHORIZATION, USER_AGENT,
+};
+use http::{Extensions, Method, Request, Response, Uri, Version};
+use http_body_util::{Empty, Full};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::client::timeout::Timeouts;
+use crate::client::ClientBody;
+use crate::error::BoxError;
+use crate::redact::{RedactedHeaders, RedactedUri};
+use crate::request::{BuildPathError, BuildRequestError, RequestBuilder, RequestExt};
+
+/// Settings applied to every request sent through a [`Client`].
+#[derive(Clone, Default)]
+struct Defaults {
+    base_uri: Option<Uri>,
+    headers: HeaderMap,
+    version: Option<Version>,
+    extensions: Extensions,
+}
+
+impl fmt::Debug for Defaults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Defaults")
+            .field("base_uri", &self.base_uri.as_ref().map(RedactedUri))
+            .field("headers", &RedactedHeaders::new(&self.headers))
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A cloneable client over any service, with a base uri and default request settings.
+///
+/// Requests whose uri has no scheme are resolved against the base uri: the base path is used as a
+/// prefix, so `/users` against `https://api.example.com/v1` becomes `https://api.example.com/v1/users`.
+///
+/// Default headers are only added when the request doesn't carry the same header, and default
+/// extensions never replace the ones of the request. With a base uri, the default credentials,
+/// the `Authorization`, `Proxy-Authorization` and `Cookie` headers and the sensitive ones, are
+/// only added to the requests to its origin.
+///
+/// ```no_run
+/// # async fn example() -> client_util::Result<()> {
+/// use client_util::prelude::*;
+/// let client = Client::new(build_https_client().expect("no native root certificates"))
+///     .base_uri("https://api.example.com/v1")?
+///     .user_agent("example/1.0")?;
+/// let response = client.get("/users")?.empty().send().await?.text().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Client<S> {
+    inner: S,
+    defaults: Arc<Defaults>,
+}
+
+macro_rules! client_methods {
+    ($fn: ident $method: expr) => {
+        pub fn $fn<T>(&self, uri: T) -> Result<ClientRequestBuilder<S>, BuildRequestError>
+        where
+            T: TryInto<Uri>,
+            <T as TryInto<Uri>>::Error: Into<BuildRequestError>,
+            S: Clone,
+        {
+            self.request($method, uri)
+        }
+    };
+}
+
+impl<S> Client<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            defaults: Arc::default(),
+        }
+    }
+    /// Set the base uri, it must have a scheme and an authority.
+    pub fn base_uri<T>(mut self, uri: T) -> Result<Self, BuildRequestError>
+    where
+        T: TryInto<Uri>,
+        <T as TryInto<Uri>>::Error: Into<BuildRequestError>,
+    {
+        let uri = uri.try_into().map_err(Into::into)?;
+        if uri.scheme().is_none() || uri.authority().is_none() {
+            return Err(BuildRequestError::RelativeBaseUri(uri));
+        }
+        Arc::make_mut(&mut self.defaults).base_uri = Some(uri);
+        Ok(self)
+    }
+    /// Set a default header, replacing the previous default values of this header.
+    pub fn default_header<V>(
+        mut self,
+        key: impl IntoHeaderName,
+        value: V,
+    ) -> Result<Self, BuildRequestError>
+    where
+        V: TryInto<HeaderValue>,
+        V::Error: Into<BuildRequestError>,
+    {
+        let value = value.try_into().map_err(Into::into)?;
+        Arc::make_mut(&mut self.defaults).headers.insert(key, value);
+        Ok(self)
+    }
+    /// Extend the default headers.
+    pub fn default_headers(mut self, header_map: HeaderMap) -> Self {
+        Arc::make_mut(&mut self.defaults).headers.extend(header_map);
+        self
+    }
+    pub fn user_agent<V>(self, value: V) -> Result<Self, BuildRequestError>
+    where
+        V: TryInto<HeaderValue>,
+        V::Error: Into<BuildRequestError>,
+    {
+        self.default_header(USER_AGENT, value)
+    }
+    #[cfg(feature = "auth")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+    pub fn basic_auth<U, P>(self, username: U, password: Option<P>) -> Self
+    where
+        U: std::fmt::Display,
+        P: std::fmt::Display,
+    {
+        let header_value = crate::util::basic_auth(username, password);
+        self.default_header(http::header::AUTHORIZATION, header_value)
+            .expect("base64 should always be a valid header value")
+    }
+    #[cfg(feature = "auth")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+    pub fn bearer_auth<T>(self, token: T) -> Self
+    where
+        T: std::fmt::Display,
+    {
+        let header_value = crate::util::bearer_auth(token);
+        self.default_header(http::header::AUTHORIZATION, header_value)
+            .expect("base64 should always be a valid header value")
+    }
+    /// Set the version of the requests built by this client.
+    pub fn default_version(mut self, version: Version) -> Self {
+        Arc::make_mut(&mut self.defaults).version = Some(version);
+        self
+    }
+    /// Insert a default extension.
+    pub fn default_extension<T>(mut self, extension: T) -> Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.defaults)
+            .extensions
+            .insert(extension);
+        self
+    }
+    pub fn get_base_uri(&self) -> Option<&Uri> {
+        self.defaults.base_uri.as_ref()
+    }
+    pub fn get_default_headers(&self) -> &HeaderMap {
+        &self.defaults.headers
+    }
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+    /// Start building a request bound to this client.
+    pub fn request<T>(
+        &self,
+        method: Method,
+        uri: T,
+    ) -> Result<ClientRequestBuilder<S>, BuildRequestError>
+    where
+        T: TryInto<Uri>,
+        <T as TryInto<Uri>>::Error: Into<BuildRequestError>,
+        S: Clone,
+    {
+        let mut builder = RequestBuilder::new()
+            .method(method)
+            .uri(uri.try_into().map_err(Into::into)?);
+        if let Some(version) = self.defaults.version {
+            builder = builder.version(version);
+        }
+        Ok(ClientRequestBuilder {
+            client: self.clone(),
+            builder,
+        })
+    }
+    client_methods!(get Method::GET);
+    client_methods!(post Method::POST);
+    client_methods!(put Method::PUT);
+    client_methods!(delete Method::DELETE);
+    client_methods!(head Method::HEAD);
+    client_methods!(patch Method::PATCH);
+    client_methods!(options Method::OPTIONS);
+    client_methods!(trace Method::TRACE);
+    client_methods!(connect Method::CONNECT);
+
+    fn apply_defaults<B>(&self, request: &mut Request<B>) -> Result<(), BuildPathError> {
+        let defaults = &self.defaults;
+        if let Some(base) = &defaults.base_uri {
+            if request.uri().scheme().is_none() {
+                *request.uri_mut() = crate::util::rebase(base, request.uri())?;
+            }
+        }
+        // like on a redirect, the credentials aren't sent to another origin
+        let other_origin = defaults
+            .base_uri
+            .as_ref()
+            .is_some_and(|base| !crate::util::same_origin(base, request.uri()));
+        let headers = request.headers_mut();
+        for key in defaults.headers.keys() {
+            if other_origin && is_credential(key, defaults.headers.get_all(key)) {
+                continue;
+            }
+            if !headers.contains_key(key) {
+                for value in defaults.headers.get_all(key) {
+                    headers.append(key.clone(), value.clone());
+                }
+            }
+        }
+        if !defaults.extensions.is_empty() {
+            let mut extensions = defaults.extensions.clone();
+            extensions.extend(std::mem::take(request.extensions_mut()));
+            *request.extensions_mut() = extensions;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a default header is a credential, only sent to the origin of the base uri.
+fn is_credential(name: &HeaderName, values: GetAll<'_, HeaderValue>) -> bool {
+    [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE].contains(name)
+        || values.iter().any(HeaderValue::is_sensitive)
+}
+
+impl<S, B> tower_service::Service<Request<B>> for Client<S>
+where
+    S: tower_service::Service<Request<B>>,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future =
+        Either<Ready<Result<S::Response, BoxError>>, MapErr<S::Future, fn(S::Error) -> BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        if let Err(error) = self.apply_defaults(&mut request) {
+            return Either::Left(futures_util::future::ready(Err(error.into())));
+        }
+        Either::Right(self.inner.call(request).map_err(Into::into as fn(_) -> _))
+    }
+}
+
+/// A [`RequestBuilder`] bound to a [`Client`].
+#[derive(Debug)]
+pub struct ClientRequestBuilder<S> {
+    client: Client<S>,
+    builder: RequestBuilder,
+}
+
+impl<S> Deref for ClientRequestBuilder<S> {
+    type Target = RequestBuilder;
+    fn deref(&self) -> &Self::Target {
+        &self.builder
+    }
+}
+
+impl<S> DerefMut for ClientRequestBuilder<S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.builder
+    }
+}
+
+impl<S> ClientRequestBuilder<S> {
+    fn map(self, f: impl FnOnce(RequestBuilder) -> RequestBuilder) -> Self {
+        Self {
+            client: self.client,
+            builder: f(self.builder),
+        }
+    }
+    fn try_map(
+        self,
+        f: impl FnOnce(RequestBuilder) -> Result<RequestBuilder, BuildRequestError>,
+    ) -> Result<Self, BuildRequestError> {
+        Ok(Self {
+            client: self.client,
+            builder: f(self.builder)?,
+        })
+    }
+    fn finish<B>(
+        self,
+        f: impl FnOnce(RequestBuilder) -> Result<Request<B>, BuildRequestError>,
+    ) -> Result<ClientRequest<S, B>, BuildRequestError> {
+        Ok(ClientRequest {
+            client: self.client,
+            request: f(self.builder)?,
+        })
+    }
+    pub fn uri(self, uri: Uri) -> Self {
+        self.map(|builder| builder.uri(uri))
+    }
+    pub fn method(self, method: Method) -> Self {
+        self.map(|builder| builder.method(method))
+    }
+    pub fn version(self, version: Version) -> Self {
+        self.map(|builder| builder.version(version))
+    }
+    pub fn path(self, path: impl AsRef<str>) -> Result<Self, BuildRequestError> {
+        self.try_map(|builder| builder.path(path))
+    }
+    pub fn timeouts(self, timeouts: Timeouts) -> Self {
+        self.map(|builder| builder.timeouts(timeouts))
+    }
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.map(|builder| builder.timeout(timeout))
+    }
+    pub fn read_timeout(self, timeout: Duration) -> Self {
+        self.map(|builder| builder.read_timeout(timeout))
+    }
+    #[cfg(feature = "retry")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "retry")))]
+    pub fn idempotent(self) -> Self {
+        self.map(|builder| builder.idempotent())
+    }
+    #[cfg(feature = "hedge")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hedge")))]
+    pub fn hedge(self, enable: bool) -> Self {
+        self.map(|builder| builder.hedge(enable))
+    }
+    #[cfg(feature = "balance")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "balance")))]
+    pub fn balance_key(self, key: impl Into<String>) -> Self {
+        self.map(|builder| builder.balance_key(key))
+    }
+    #[cfg(feature = "query")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "query")))]
+    pub fn query<Q: Serialize + ?Sized>(self, query: &Q) -> Result<Self, BuildRequestError> {
+        self.try_map(|builder| builder.query(query))
+    }
+    pub fn headers(self, header_map: HeaderMap) -> Self {
+        self.map(|builder| builder.headers(header_map))
+    }
+    pub fn header<V>(self, key: impl IntoHeaderName, value: V) -> Result<Self, BuildRequestError>
+    where
+        V: TryInto<HeaderValue>,
+        V::Error: Into<BuildRequestError>,
+    {
+        self.try_map(|builder| builder.header(key, value))
+    }
+    #[cfg(feature = "auth")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+    pub fn basic_auth<U, P>(self, username: U, password: Option<P>) -> Self
+    where
+        U: std::fmt::Display,
+        P: std::fmt::Display,
+    {
+        self.map(|builder| builder.basic_auth(username, password))
+    }
+    #[cfg(feature = "auth")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+    pub fn bearer_auth<T>(self, token: T) -> Self
+    where
+        T: std::fmt::Display,
+    {
+        self.map(|builder| builder.bearer_auth(token))
+    }
+    pub fn extension<T>(mut self, extension: T) -> Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.builder.extensions.insert(extension);
+        self
+    }
+    pub fn body<B>(self, body: B) -> Result<ClientRequest<S, B>, BuildRequestError>
+    where
+        B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+    {
+        self.finish(|builder| builder.body(body))
+    }
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn json<T: Serialize + ?Sized>(
+        self,
+        body: &T,
+    ) -> Result<ClientRequest<S, Full<Bytes>>, BuildRequestError> {
+        self.finish(|builder| builder.json(body))
+    }
+    #[cfg(feature = "form")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "form")))]
+    pub fn form<T: Serialize + ?Sized>(
+        self,
+        form: &T,
+    ) -> Result<ClientRequest<S, Full<Bytes>>, BuildRequestError> {
+        self.finish(|builder| builder.form(form))
+    }
+    #[cfg(feature = "multipart")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
+    pub fn multipart(
+        self,
+        form: crate::request::Form,
+    ) -> Result<ClientRequest<S, crate::Body>, BuildRequestError> {
+        self.finish(|builder| builder.multipart(form))
+    }
+    pub fn plain_text(self, body: impl Into<Bytes>) -> ClientRequest<S, Full<Bytes>> {
+        ClientRequest {
+            client: self.client,
+            request: self.builder.plain_text(body),
+        }
+    }
+    pub fn empty(self) -> ClientRequest<S, Empty<Bytes>> {
+        ClientRequest {
+            client: self.client,
+            request: self.builder.empty(),
+        }
+    }
+}
+
+/// A request bound to a [`Client`], ready to be sent.
+pub struct ClientRequest<S, B> {
+    client: Client<S>,
+    request: Request<B>,
+}
+
+impl<S: fmt::Debug, B> fmt::Debug for ClientRequest<S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientRequest")
+            .field("client", &self.client)
+            .field("method", self.request.method())
+            .field("uri", &RedactedUri(self.request.uri()))
+            .field("headers", &RedactedHeaders::new(self.request.headers()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, B> Deref for ClientRequest<S, B> {
+    type Target = Request<B>;
+    fn deref(&self) -> &Self::Target {
+        &self.request
+    }
+}
+
+impl<S, B> DerefMut for ClientRequest<S, B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.request
+    }
+}
+
+impl<S, B> ClientRequest<S, B> {
+    pub fn into_parts(self) -> (Client<S>, Request<B>) {
+        (self.client, self.request)
+    }
+    /// Send the request through the bound client, with the client defaults applied.
+    pub fn send<R>(self) -> impl Future<Output = crate::Result<Response<R>>> + Send
+    where
+        B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+        S: tower_service::Service<Request<ClientBody>, Response = Response<R>> + Send + Sync,
+        S::Error: Into<BoxError>,
+        S::Future: Send,
+        R: http_body::Body + Send + Sync + 'static,
+    {
+        self.request.send(self.client)
+    }
+}
@@ -0,0 +1,195 @@
+//! A process wide default client.
+//!
+//! The default client is built lazily on first use: an https client when a TLS backend is enabled,
+//! a plain http client otherwise. It can be replaced with [`set_global_client`], reset with
+//! [`reset_global_client`], or overridden for a single future with [`with_client`].
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::prelude::*;
+//! let response = client_util::get("https://example.com").await?.text().await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{PoisonError, RwLock};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::{Request, Response, Uri};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use tower::util::BoxCloneSyncService;
+use tower::ServiceExt;
+
+use crate::client::ClientBody;
+use crate::error::BoxError;
+use crate::request::{BuildRequestError, RequestBuilder, RequestExt};
+
+type BoxedClient = BoxCloneSyncService<Request<ClientBody>, Response<ClientBody>, BoxError>;
+
+/// A type erased, cloneable client.
+#[derive(Clone)]
+pub struct SharedClient {
+    inner: BoxedClient,
+}
+
+impl fmt::Debug for SharedClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedClient").finish_non_exhaustive()
+    }
+}
+
+impl SharedClient {
+    pub fn new<S, B>(client: S) -> Self
+    where
+        S: tower_service::Service<Request<ClientBody>, Response = Response<B>>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+        B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+    {
+        let inner = client
+            .map_response(|response: Response<B>| {
+                response.map(|body| BoxBody::new(body.map_err(Into::into)))
+            })
+            .map_err(Into::into);
+        Self {
+            inner: BoxCloneSyncService::new(inner),
+        }
+    }
+}
+
+impl tower_service::Service<Request<ClientBody>> for SharedClient {
+    type Response = Response<ClientBody>;
+    type Error = BoxError;
+    type Future = <BoxedClient as tower_service::Service<Request<ClientBody>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ClientBody>) -> Self::Future {
+        self.inner.call(request)
+    }
+}
+
+static GLOBAL: RwLock<Option<SharedClient>> = RwLock::new(None);
+
+thread_local! {
+    static SCOPED: RefCell<Option<SharedClient>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn default_client() -> SharedClient {
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    {
+        let client = crate::client::build_https_client::<ClientBody>()
+            .expect("failed to build the default https client");
+        SharedClient::new(client)
+    }
+    #[cfg(not(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls")))]
+    {
+        SharedClient::new(crate::client::build_http_client::<ClientBody>())
+    }
+}
+
+/// Get the current client: the one of the enclosing [`with_client`] scope, or the global one.
+///
+/// # Panics
+/// When the default https client is built and no root certificate can be loaded.
+pub fn global_client() -> SharedClient {
+    if let Some(client) = SCOPED.with_borrow(Clone::clone) {
+        return client;
+    }
+    if let Some(client) = GLOBAL
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+    {
+        return client.clone();
+    }
+    GLOBAL
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(default_client)
+        .clone()
+}
+
+/// Replace the global client, returning the previous one.
+pub fn set_global_client(client: SharedClient) -> Option<SharedClient> {
+    GLOBAL
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replace(client)
+}
+
+/// Remove the global client, the default one will be built again on next use.
+pub fn reset_global_client() -> Option<SharedClient> {
+    GLOBAL
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+}
+
+/// Use `client` as the current client while `future` is polled.
+///
+/// The override doesn't apply to the tasks spawned by the future.
+pub fn with_client<F: Future>(client: SharedClient, future: F) -> WithClient<F> {
+    WithClient {
+        client: Some(client),
+        future,
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Future for [`with_client`].
+    #[derive(Debug)]
+    pub struct WithClient<F> {
+        client: Option<SharedClient>,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F: Future> Future for WithClient<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// Put the outer client back, even if the inner future panics.
+        struct Restore<'a> {
+            slot: &'a mut Option<SharedClient>,
+            outer: Option<SharedClient>,
+        }
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                *self.slot = SCOPED.replace(self.outer.take());
+            }
+        }
+        let this = self.project();
+        let outer = SCOPED.replace(this.client.take());
+        let _restore = Restore {
+            slot: this.client,
+            outer,
+        };
+        this.future.poll(cx)
+    }
+}
+
+/// Send a `GET` request with the current client, see [`global_client`].
+pub async fn get<T>(uri: T) -> crate::Result<Response<ClientBody>>
+where
+    T: TryInto<Uri>,
+    <T as TryInto<Uri>>::Error: Into<BuildRequestError>,
+{
+    RequestBuilder::get(uri)?
+        .empty()
+        .send(global_client())
+        .await
+}
@@ -0,0 +1,501 @@
+//! HTTP/3 client over QUIC, backed by [`quinn`] and [`h3`].
+//!
+//! [`H3Client`] is a tower service like the hyper clients, so the same
+//! [`RequestBuilder`](crate::request::RequestBuilder) and [`ResponseExt`](crate::response::ResponseExt)
+//! code works with it.
+//!
+//! [`AltSvc`] can be layered on top of another client to discover HTTP/3 endpoints from the `Alt-Svc`
+//! response header, and send the following requests to the same origin over HTTP/3.
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, Bytes};
+use futures_util::TryFutureExt;
+use http::uri::{Authority, Scheme};
+use http::{HeaderValue, Request, Response};
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use quinn::crypto::rustls::QuicClientConfig;
+
+use crate::client::dns::{DnsResolver, Name};
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+type SendRequest = h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+/// The connection of an origin, locked while it's established so a single one is opened.
+type ConnectionSlot = Arc<futures_util::lock::Mutex<Option<SendRequest>>>;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = Result<T, BoxError>> + Send>>;
+
+/// The ALPN protocol id of HTTP/3.
+pub const ALPN_H3: &[u8] = b"h3";
+
+#[derive(Debug, thiserror::Error)]
+pub enum H3Error {
+    #[error("request uri has no host: {0}")]
+    MissingHost(http::Uri),
+    #[error("invalid tls config for quic: {0}")]
+    TlsConfig(#[source] BoxError),
+    #[error("failed to connect: {0}")]
+    Connect(#[from] quinn::ConnectError),
+    #[error("connection error: {0}")]
+    Connection(#[from] quinn::ConnectionError),
+    #[error("http/3 connection error: {0}")]
+    H3Connection(#[from] h3::error::ConnectionError),
+    #[error("http/3 stream error: {0}")]
+    H3Stream(#[from] h3::error::StreamError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ConnectionKey {
+    server_name: String,
+    host: String,
+    port: u16,
+}
+
+/// Where to connect for a request, when it's not the authority of the request uri.
+#[derive(Debug, Clone)]
+struct AltEndpoint {
+    host: Option<String>,
+    port: u16,
+}
+
+/// An HTTP/3 client.
+///
+/// Connections are kept and reused for every origin, until the peer closes them.
+/// Cloning the client is cheap, the clones share the connections.
+///
+/// The client runs on tokio, unlike the hyper clients it has no executor option: the quinn
+/// endpoints, the connection drivers and the response bodies use the current tokio runtime, a
+/// request sent outside of one panics.
+#[derive(Clone)]
+pub struct H3Client {
+    config: quinn::ClientConfig,
+    resolver: DnsResolver,
+    endpoints: Arc<Mutex<HashMap<bool, quinn::Endpoint>>>,
+    connections: Arc<Mutex<HashMap<ConnectionKey, ConnectionSlot>>>,
+}
+
+impl std::fmt::Debug for H3Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("H3Client")
+            .field("resolver", &self.resolver)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Build an HTTP/3 client, using rustls with the native roots.
+pub fn build_h3_client() -> std::io::Result<H3Client> {
+    use hyper_rustls::ConfigBuilderExt;
+    let tls = rustls::ClientConfig::builder()
+        .with_native_roots()?
+        .with_no_client_auth();
+    H3Client::new(tls).map_err(std::io::Error::other)
+}
+
+impl H3Client {
+    /// Create a client with a rustls config. The ALPN protocols are overridden with `h3`.
+    pub fn new(mut tls: rustls::ClientConfig) -> Result<Self, H3Error> {
+        tls.alpn_protocols = vec![ALPN_H3.to_vec()];
+        let crypto =
+            QuicClientConfig::try_from(tls).map_err(|error| H3Error::TlsConfig(error.into()))?;
+        Ok(Self {
+            config: quinn::ClientConfig::new(Arc::new(crypto)),
+            resolver: DnsResolver::default(),
+            endpoints: Default::default(),
+            connections: Default::default(),
+        })
+    }
+    /// Set the dns resolver.
+    pub fn with_resolver(mut self, resolver: DnsResolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+    fn endpoint(&self, ipv6: bool) -> Result<quinn::Endpoint, H3Error> {
+        let mut endpoints = self.endpoints.lock().expect("never poisoned");
+        if let Some(endpoint) = endpoints.get(&ipv6) {
+            return Ok(endpoint.clone());
+        }
+        let bind: SocketAddr = if ipv6 {
+            (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+        } else {
+            (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+        };
+        let endpoint = quinn::Endpoint::client(bind)?;
+        endpoints.insert(ipv6, endpoint.clone());
+        Ok(endpoint)
+    }
+    async fn resolve(&self, host: &str, port: u16) -> Result<SocketAddr, BoxError> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = IpAddr::from_str(host) {
+            return Ok(SocketAddr::new(ip, port));
+        }
+        let mut resolver = self.resolver.clone();
+        let mut addr = tower_service::Service::call(&mut resolver, Name::from_str(host)?)
+            .await?
+            .next()
+            .ok_or_else(|| format!("no address resolved for {host}"))?;
+        addr.set_port(port);
+        Ok(addr)
+    }
+    async fn connection(&self, key: ConnectionKey) -> Result<SendRequest, BoxError> {
+        let slot = self
+            .connections
+            .lock()
+            .expect("never poisoned")
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        // the concurrent requests to the origin wait for the connection of the first one
+        let mut current = slot.lock().await;
+        if let Some(send_request) = &*current {
+            return Ok(send_request.clone());
+        }
+        let addr = self.resolve(&key.host, key.port).await?;
+        let connection = self
+            .endpoint(addr.is_ipv6())?
+            .connect_with(self.config.clone(), addr, &key.server_name)
+            .map_err(H3Error::from)?
+            .await
+            .map_err(H3Error::from)?;
+        let (mut driver, send_request) = h3::client::new(h3_quinn::Connection::new(connection))
+            .await
+            .map_err(H3Error::from)?;
+        *current = Some(send_request.clone());
+        let slot = slot.clone();
+        tokio::spawn(async move {
+            let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+            *slot.lock().await = None;
+        });
+        Ok(send_request)
+    }
+    async fn send(self, request: Request<ClientBody>) -> Result<Response<ClientBody>, BoxError> {
+        let (parts, mut body) = request.into_parts();
+        let server_name = parts
+            .uri
+            .host()
+            .ok_or_else(|| H3Error::MissingHost(parts.uri.clone()))?
+            .to_owned();
+        let port = parts.uri.port_u16().unwrap_or(443);
+        let key = match parts.extensions.get::<AltEndpoint>() {
+            Some(alt) => ConnectionKey {
+                host: alt.host.clone().unwrap_or_else(|| server_name.clone()),
+                port: alt.port,
+                server_name,
+            },
+            None => ConnectionKey {
+                host: server_name.clone(),
+                port,
+                server_name,
+            },
+        };
+        let mut send_request = self.connection(key).await?;
+        let mut stream = send_request
+            .send_request(Request::from_parts(parts, ()))
+            .await
+            .map_err(H3Error::from)?;
+        while let Some(frame) = body.frame().await {
+            match frame?.into_data() {
+                Ok(data) => stream.send_data(data).await.map_err(H3Error::from)?,
+                Err(frame) => {
+                    if let Ok(trailers) = frame.into_trailers() {
+                        stream
+                            .send_trailers(trailers)
+                            .await
+                            .map_err(H3Error::from)?;
+                    }
+                }
+            }
+        }
+        stream.finish().await.map_err(H3Error::from)?;
+        let response = stream.recv_response().await.map_err(H3Error::from)?;
+
+        // pump the response body through a channel, so the body is `Sync` and dropping it cancels the stream.
+        let (sender, mut receiver) =
+            tokio::sync::mpsc::channel::<Result<Frame<Bytes>, BoxError>>(1);
+        tokio::spawn(async move {
+            loop {
+                let frame = match stream.recv_data().await {
+                    Ok(Some(mut data)) => Ok(Frame::data(data.copy_to_bytes(data.remaining()))),
+                    Ok(None) => break,
+                    Err(error) => Err(H3Error::from(error).into()),
+                };
+                let is_error = frame.is_err();
+                if sender.send(frame).await.is_err() {
+                    stream.stop_sending(h3::error::Code::H3_REQUEST_CANCELLED);
+                    return;
+                }
+                if is_error {
+                    return;
+                }
+            }
+            let trailers = match stream.recv_trailers().await {
+                Ok(Some(trailers)) => Ok(Frame::trailers(trailers)),
+                Ok(None) => return,
+                Err(error) => Err(H3Error::from(error).into()),
+            };
+            let _ = sender.send(trailers).await;
+        });
+        let body = StreamBody::new(futures_util::stream::poll_fn(move |cx| {
+            receiver.poll_recv(cx)
+        }))
+        .boxed();
+        Ok(response.map(|_| body))
+    }
+}
+
+impl tower_service::Service<Request<ClientBody>> for H3Client {
+    type Response = Response<ClientBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<Response<ClientBody>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<ClientBody>) -> Self::Future {
+        Box::pin(self.clone().send(request))
+    }
+}
+
+/// A parsed `Alt-Svc` header, only the `h3` alternative is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AltSvcDirective {
+    /// `Alt-Svc: clear`, forget all the alternatives of the origin.
+    Clear,
+    H3 {
+        /// The alternative host, `None` for the same host as the origin.
+        host: Option<String>,
+        port: u16,
+        max_age: Duration,
+    },
+}
+
+impl AltSvcDirective {
+    /// The default freshness of an alternative, when there is no `ma` parameter.
+    pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Parse an `Alt-Svc` header value, returns `None` if there is no `h3` alternative.
+    pub fn parse(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        if value.eq_ignore_ascii_case("clear") {
+            return Some(AltSvcDirective::Clear);
+        }
+        value.split(',').find_map(|alternative| {
+            let mut params = alternative.split(';').map(str::trim);
+            let (protocol, authority) = params.next()?.split_once('=')?;
+            if protocol != "h3" {
+                return None;
+            }
+            let (host, port) = authority.trim_matches('"').rsplit_once(':')?;
+            let port = port.parse().ok()?;
+            let max_age = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| *key == "ma")
+                .and_then(|(_, value)| value.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(Self::DEFAULT_MAX_AGE);
+            Some(AltSvcDirective::H3 {
+                host: (!host.is_empty()).then(|| host.to_owned()),
+                port,
+                max_age,
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AltSvcEntry {
+    endpoint: AltEndpoint,
+    expires: Instant,
+}
+
+/// The HTTP/3 alternatives discovered by [`AltSvc`], per origin.
+#[derive(Debug, Clone, Default)]
+pub struct AltSvcCache {
+    inner: Arc<Mutex<HashMap<Authority, AltSvcEntry>>>,
+}
+
+impl AltSvcCache {
+    fn get(&self, authority: &Authority) -> Option<AltEndpoint> {
+        let mut inner = self.inner.lock().expect("never poisoned");
+        match inner.get(authority) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.endpoint.clone()),
+            Some(_) => {
+                inner.remove(authority);
+                None
+            }
+            None => None,
+        }
+    }
+    fn update(&self, authority: Authority, directive: AltSvcDirective) {
+        let mut inner = self.inner.lock().expect("never poisoned");
+        match directive {
+            AltSvcDirective::Clear => {
+                inner.remove(&authority);
+            }
+            AltSvcDirective::H3 {
+                host,
+                port,
+                max_age,
+            } => {
+                inner.insert(
+                    authority,
+                    AltSvcEntry {
+                        endpoint: AltEndpoint { host, port },
+                        expires: Instant::now() + max_age,
+                    },
+                );
+            }
+        }
+    }
+    /// Forget the alternative of `authority`.
+    pub fn remove(&self, authority: &Authority) {
+        self.inner.lock().expect("never poisoned").remove(authority);
+    }
+    /// Check if requests to `authority` are currently sent over HTTP/3.
+    pub fn contains(&self, authority: &Authority) -> bool {
+        self.get(authority).is_some()
+    }
+}
+
+/// Layer for [`AltSvc`].
+#[derive(Debug, Clone)]
+pub struct AltSvcLayer {
+    h3: H3Client,
+    cache: AltSvcCache,
+}
+
+impl AltSvcLayer {
+    pub fn new(h3: H3Client) -> Self {
+        Self {
+            h3,
+            cache: AltSvcCache::default(),
+        }
+    }
+    /// Share the discovered alternatives with another cache handle.
+    pub fn with_cache(mut self, cache: AltSvcCache) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for AltSvcLayer {
+    type Service = AltSvc<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        AltSvc {
+            inner,
+            h3: self.h3.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+/// Upgrade https origins to HTTP/3 after they advertise an `h3` alternative in the `Alt-Svc` header.
+///
+/// If an HTTP/3 request fails, the alternative is forgotten and the next requests go through the inner client again.
+#[derive(Debug, Clone)]
+pub struct AltSvc<S> {
+    inner: S,
+    h3: H3Client,
+    cache: AltSvcCache,
+}
+
+impl<S> AltSvc<S> {
+    pub fn cache(&self) -> &AltSvcCache {
+        &self.cache
+    }
+}
+
+impl<S, R> tower_service::Service<Request<ClientBody>> for AltSvc<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<R>>,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError> + 'static,
+    R: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    R::Error: Into<BoxError>,
+{
+    type Response = Response<ClientBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<Response<ClientBody>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<ClientBody>) -> Self::Future {
+        let authority = match request.uri().scheme() {
+            Some(scheme) if *scheme == Scheme::HTTPS => request.uri().authority().cloned(),
+            _ => None,
+        };
+        let Some(authority) = authority else {
+            return Box::pin(
+                self.inner
+                    .call(request)
+                    .map_err(Into::into)
+                    .map_ok(|response| response.map(|body| body.map_err(Into::into).boxed())),
+            );
+        };
+        if let Some(endpoint) = self.cache.get(&authority) {
+            request.extensions_mut().insert(endpoint);
+            let response = self.h3.clone().send(request);
+            let cache = self.cache.clone();
+            return Box::pin(
+                async move { response.await.inspect_err(|_| cache.remove(&authority)) },
+            );
+        }
+        let cache = self.cache.clone();
+        Box::pin(
+            self.inner
+                .call(request)
+                .map_err(Into::into)
+                .map_ok(move |response| {
+                    if let Some(directive) = response
+                        .headers()
+                        .get(http::header::ALT_SVC)
+                        .and_then(AltSvcDirective::parse)
+                    {
+                        cache.update(authority, directive);
+                    }
+                    response.map(|body| body.map_err(Into::into).boxed())
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_alt_svc() {
+        let parse = |value: &'static str| AltSvcDirective::parse(&HeaderValue::from_static(value));
+        assert_eq!(parse("clear"), Some(AltSvcDirective::Clear));
+        assert_eq!(
+            parse(r#"h3=":443"; ma=3600, h3-29=":443""#),
+            Some(AltSvcDirective::H3 {
+                host: None,
+                port: 443,
+                max_age: Duration::from_secs(3600),
+            })
+        );
+        assert_eq!(
+            parse(r#"h2="alt.example.com:8443", h3="alt.example.com:8443""#),
+            Some(AltSvcDirective::H3 {
+                host: Some("alt.example.com".to_owned()),
+                port: 8443,
+                max_age: AltSvcDirective::DEFAULT_MAX_AGE,
+            })
+        );
+        assert_eq!(parse(r#"h2=":443""#), None);
+    }
+}
@@ -0,0 +1,351 @@
+//! Hedging of the slow requests, for the tail latency.
+//!
+//! The [`HedgeLayer`] sends a copy of a request when the first attempt has no response yet after
+//! a delay, the [`HedgePolicy::percentile`] of the latencies of the previous responses. The first
+//! response wins, the other attempt is dropped, cancelling it. A failed attempt waits for the other
+//! one.
+//!
+//! The copies are limited by a budget: each request which can be hedged earns
+//! [`HedgePolicy::max_ratio`] of a copy, and a copy is only sent once a whole one is earned, so a
+//! slow server isn't sent twice as many requests.
+//!
+//! Only the read-only requests are hedged, the `GET`, `HEAD` and `OPTIONS` requests by default,
+//! see [`RequestBuilder::hedge`](crate::request::RequestBuilder::hedge) to change it for a
+//! request. A request whose body is longer than [`HedgePolicy::max_replay_body`] isn't hedged.
+//!
+//! The copy can be sent to another base uri, with [`HedgePolicy::alternate`]. Whether the
+//! response comes from the copy is in its [`Hedged`] extension.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::hedge::{HedgeLayer, HedgePolicy};
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(HedgeLayer::new(
+//!         HedgePolicy::new()
+//!             .percentile(0.9)
+//!             .alternate("https://replica.example.com".parse().unwrap()),
+//!     ))
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::future::{select, Either};
+use http::header::HOST;
+use http::{Method, Request, Response, Uri};
+use http_body_util::BodyExt;
+use hyper::rt::Timer;
+use tower::ServiceExt;
+
+use crate::client::replay::{self, attempt, ReplayBody};
+use crate::client::rt::SharedTimer;
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+/// Whether to hedge a request, whatever its method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hedgeable(pub bool);
+
+/// Whether the response comes from the copy of the request, inserted in its extensions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hedged(pub bool);
+
+/// When to send the copy of a request.
+#[derive(Debug, Clone)]
+pub struct HedgePolicy {
+    percentile: f64,
+    initial_delay: Duration,
+    min_delay: Duration,
+    min_samples: usize,
+    window: usize,
+    alternate: Option<Uri>,
+    /// The limit of [`ReplayBody::read`].
+    max_replay_body: usize,
+    max_ratio: f64,
+}
+
+impl Default for HedgePolicy {
+    fn default() -> Self {
+        Self {
+            percentile: 0.95,
+            initial_delay: Duration::from_millis(100),
+            min_delay: Duration::from_millis(1),
+            min_samples: 20,
+            window: 1000,
+            alternate: None,
+            max_replay_body: 64 * 1024,
+            max_ratio: 0.1,
+        }
+    }
+}
+
+impl HedgePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The percentile of the latencies after which the copy is sent, `0.95` by default.
+    pub fn percentile(mut self, percentile: f64) -> Self {
+        self.percentile = percentile.clamp(0.0, 1.0);
+        self
+    }
+    /// The delay until [`HedgePolicy::min_samples`] latencies are known, `100ms` by default.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+    /// The shortest delay, `1ms` by default.
+    pub fn min_delay(mut self, delay: Duration) -> Self {
+        self.min_delay = delay;
+        self
+    }
+    /// The latencies needed for the percentile, `20` by default.
+    pub fn min_samples(mut self, samples: usize) -> Self {
+        self.min_samples = samples.max(1);
+        self
+    }
+    /// The number of recent latencies kept, `1000` by default.
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+    /// Send the copy to another base uri, its path prefixes the path of the request.
+    pub fn alternate(mut self, base: Uri) -> Self {
+        self.alternate = Some(base);
+        self
+    }
+    /// The longest request body which is buffered to be sent twice, 64KiB by default.
+    pub fn max_replay_body(mut self, max: usize) -> Self {
+        self.max_replay_body = max;
+        self
+    }
+    /// The most copies per request which can be hedged, `0.1` by default.
+    ///
+    /// The first copy is always allowed, the unspent ones are kept up to the copies earned over
+    /// the [`HedgePolicy::window`].
+    pub fn max_ratio(mut self, ratio: f64) -> Self {
+        self.max_ratio = ratio.max(0.0);
+        self
+    }
+}
+
+/// Whether a method is read-only, hedged by default.
+fn is_read_only(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// The recent latencies, to the response headers, and the copies which can still be sent.
+#[derive(Debug)]
+struct Latencies {
+    samples: VecDeque<Duration>,
+    budget: f64,
+}
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            budget: 1.0,
+        }
+    }
+}
+
+impl Latencies {
+    fn earn(&mut self, policy: &HedgePolicy) {
+        #[allow(clippy::cast_precision_loss)]
+        let max = (policy.max_ratio * policy.window as f64).max(1.0);
+        self.budget = (self.budget + policy.max_ratio).min(max);
+    }
+    fn spend(&mut self) -> bool {
+        let spent = self.budget >= 1.0;
+        if spent {
+            self.budget -= 1.0;
+        }
+        spent
+    }
+    fn record(&mut self, latency: Duration, window: usize) {
+        while self.samples.len() >= window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+    fn delay(&self, policy: &HedgePolicy) -> Duration {
+        if self.samples.len() < policy.min_samples {
+            return policy.initial_delay.max(policy.min_delay);
+        }
+        let mut samples = Vec::from(self.samples.clone());
+        samples.sort_unstable();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = (policy.percentile * samples.len() as f64).ceil() as usize;
+        samples[rank.clamp(1, samples.len()) - 1].max(policy.min_delay)
+    }
+}
+
+/// Layer for [`Hedge`].
+#[derive(Debug, Clone)]
+pub struct HedgeLayer {
+    policy: HedgePolicy,
+    timer: SharedTimer,
+}
+
+impl HedgeLayer {
+    /// Hedge with `policy`, waiting with the tokio timer.
+    pub fn new(policy: HedgePolicy) -> Self {
+        Self {
+            policy,
+            timer: SharedTimer::tokio(),
+        }
+    }
+    /// Set the timer delaying the copies, for another runtime than tokio.
+    pub fn timer<T>(mut self, timer: T) -> Self
+    where
+        T: Timer + Send + Sync + 'static,
+    {
+        self.timer = SharedTimer::new(timer);
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for HedgeLayer {
+    type Service = Hedge<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Hedge {
+            inner,
+            policy: self.policy.clone(),
+            timer: self.timer.clone(),
+            latencies: Arc::default(),
+        }
+    }
+}
+
+/// Hedge the slow requests, see [`crate::client::hedge`].
+#[derive(Clone)]
+pub struct Hedge<S> {
+    inner: S,
+    policy: HedgePolicy,
+    timer: SharedTimer,
+    latencies: Arc<Mutex<Latencies>>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Hedge<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hedge")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Hedge<S> {
+    /// The current delay before sending a copy.
+    pub fn delay(&self) -> Duration {
+        self.latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .delay(&self.policy)
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Hedge<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>
+        + Clone
+        + Send
+        + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let mut service = replay::take_ready(&mut self.inner);
+        let policy = self.policy.clone();
+        let timer = self.timer.clone();
+        let latencies = self.latencies.clone();
+        let delay = self.delay();
+        Box::pin(async move {
+            let latencies = || latencies.lock().unwrap_or_else(PoisonError::into_inner);
+            let record = |start: Instant| latencies().record(start.elapsed(), policy.window);
+            let (parts, body) = request.into_parts();
+            let hedge = parts
+                .extensions
+                .get::<Hedgeable>()
+                .map_or_else(|| is_read_only(&parts.method), |hedge| hedge.0);
+            let body = body.map_err(Into::into).boxed();
+            if !hedge {
+                let mut response = attempt(service.call(Request::from_parts(parts, body))).await?;
+                response.extensions_mut().insert(Hedged(false));
+                return Ok(response);
+            }
+            latencies().earn(&policy);
+            let mut body = ReplayBody::read(body, policy.max_replay_body).await?;
+            let start = Instant::now();
+            let first = attempt(service.call(replay::request(&parts, body.next())));
+            if !body.replayable() {
+                let mut response = first.await?;
+                record(start);
+                response.extensions_mut().insert(Hedged(false));
+                return Ok(response);
+            }
+            let first = match select(first, timer.sleep(delay)).await {
+                Either::Left((result, _)) => {
+                    let mut response = result?;
+                    record(start);
+                    response.extensions_mut().insert(Hedged(false));
+                    return Ok(response);
+                }
+                Either::Right((_, first)) => first,
+            };
+            if !latencies().spend() {
+                let mut response = first.await?;
+                record(start);
+                response.extensions_mut().insert(Hedged(false));
+                return Ok(response);
+            }
+            let mut copy = replay::request(&parts, body.next());
+            if let Some(uri) = policy
+                .alternate
+                .as_ref()
+                .and_then(|base| crate::util::rebase(base, copy.uri()).ok())
+            {
+                *copy.uri_mut() = uri;
+                copy.headers_mut().remove(HOST);
+            }
+            let second = attempt(service.oneshot(copy));
+            // the first response wins, unless it's an error and the other attempt is pending
+            let (result, hedged) = match select(first, second).await {
+                Either::Left((Ok(response), _)) => (Ok(response), false),
+                Either::Right((Ok(response), _)) => (Ok(response), true),
+                Either::Left((Err(_), second)) => (second.await, true),
+                Either::Right((Err(_), first)) => (first.await, false),
+            };
+            let mut response = result?;
+            // the latency of the request, not of the copy, sets the delay
+            record(start);
+            response.extensions_mut().insert(Hedged(hedged));
+            Ok(response)
+        })
+    }
+}
@@ -1,51 +1,605 @@
-use hyper_util::{
-    client::legacy::{connect::HttpConnector, Client as HyperClient},
-    rt::TokioExecutor,
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::future::{Either, MapErr, Ready};
+use futures_util::TryFutureExt;
+use http::{Request, Uri, Version};
+use hyper_util::client::legacy::{
+    connect::{Connect, HttpConnector},
+    Builder, Client as HyperClient,
 };
 
-/// TLS support
+use crate::client::dns::DnsResolver;
+use crate::client::metadata::{record_transport, MetadataConnector};
+use crate::client::rt::{SharedExecutor, SharedTimer};
+#[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+use crate::client::tls::{Certificate, Identity, TlsOptions};
+use crate::error::BoxError;
+
+/// The tcp connector used by the hyper clients of this crate.
+///
+/// It derefs to the [`HttpConnector`] it wraps, and records the connection timings of the
+/// [`ConnectionInfo`](crate::client::metadata::ConnectionInfo).
+#[derive(Debug, Clone)]
+pub struct HyperHttpConnector {
+    inner: HttpConnector<DnsResolver>,
+}
+
+impl HyperHttpConnector {
+    pub fn new(inner: HttpConnector<DnsResolver>) -> Self {
+        Self { inner }
+    }
+    pub fn into_inner(self) -> HttpConnector<DnsResolver> {
+        self.inner
+    }
+}
+
+impl Deref for HyperHttpConnector {
+    type Target = HttpConnector<DnsResolver>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for HyperHttpConnector {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl tower_service::Service<Uri> for HyperHttpConnector {
+    type Response = <HttpConnector<DnsResolver> as tower_service::Service<Uri>>::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let io = connecting.await?;
+            record_transport();
+            Ok(io)
+        })
+    }
+}
+
+/// TLS support with rustls
 #[cfg(feature = "client-hyper-rustls")]
 #[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-rustls")))]
-mod tls {
-    use hyper_util::{
-        client::legacy::{connect::HttpConnector, Client as HyperClient},
-        rt::TokioExecutor,
-    };
-    use rustls::ClientConfig;
+pub mod https_rustls {
+    use super::{HttpVersionMode, HyperClientBuilder, HyperHttpConnector};
+    use crate::client::metadata::MetadataConnector;
+    use hyper_util::client::legacy::Client as HyperClient;
+    use rustls::{ClientConfig, RootCertStore};
+
+    pub type HyperHttpsClient<B> =
+        HyperClient<MetadataConnector<HttpsConnector<HyperHttpConnector>>, B>;
+
+    use hyper_rustls::HttpsConnector;
+    pub fn build_https_client<B>() -> std::io::Result<HyperHttpsClient<B>>
+    where
+        B: http_body::Body + Send,
+        B::Data: Send,
+    {
+        HyperClientBuilder::new().build_https_rustls()
+    }
+
+    impl HyperClientBuilder {
+        fn rustls_config(&self) -> std::io::Result<ClientConfig> {
+            let mut roots = RootCertStore::empty();
+            if self.tls.built_in_roots {
+                let native = rustls_native_certs::load_native_certs();
+                let (added, _) = roots.add_parsable_certificates(native.certs);
+                if added == 0 && self.tls.roots.is_empty() {
+                    return Err(std::io::Error::other(format!(
+                        "no native root CA certificates found (errors: {:?})",
+                        native.errors
+                    )));
+                }
+            }
+            for certificate in &self.tls.roots {
+                for der in certificate.to_rustls()? {
+                    roots.add(der).map_err(std::io::Error::other)?;
+                }
+            }
+            let builder = ClientConfig::builder().with_root_certificates(roots);
+            match &self.tls.identity {
+                Some(identity) => {
+                    let (chain, key) = identity.to_rustls()?;
+                    builder
+                        .with_client_auth_cert(chain, key)
+                        .map_err(std::io::Error::other)
+                }
+                None => Ok(builder.with_no_client_auth()),
+            }
+        }
+        /// Build a client which supports both http and https, using rustls.
+        ///
+        /// The ALPN protocols are derived from the [`HttpVersionMode`].
+        pub fn build_https_rustls<B>(&self) -> std::io::Result<HyperHttpsClient<B>>
+        where
+            B: http_body::Body + Send,
+            B::Data: Send,
+        {
+            let connector = self.metadata_connector(self.https_rustls_connector()?);
+            Ok(self.hyper_builder().build(connector))
+        }
+        pub(crate) fn https_rustls_connector(
+            &self,
+        ) -> std::io::Result<HttpsConnector<HyperHttpConnector>> {
+            let mut http = self.http_connector();
+            http.enforce_http(false);
+            self.rustls_connector(http)
+        }
+        /// Wrap a tcp connector with rustls, the ALPN protocols are derived from the [`HttpVersionMode`].
+        pub(super) fn rustls_connector<C>(&self, tcp: C) -> std::io::Result<HttpsConnector<C>> {
+            let builder = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_tls_config(self.rustls_config()?)
+                .https_or_http();
+            Ok(match self.version {
+                HttpVersionMode::Auto => builder.enable_all_versions().wrap_connector(tcp),
+                HttpVersionMode::Http1Only => builder.enable_http1().wrap_connector(tcp),
+                HttpVersionMode::Http2PriorKnowledge => builder.enable_http2().wrap_connector(tcp),
+            })
+        }
+        /// Build a client which supports both http and https, with the default TLS backend.
+        pub fn build_https<B>(&self) -> std::io::Result<HyperHttpsClient<B>>
+        where
+            B: http_body::Body + Send,
+            B::Data: Send,
+        {
+            self.build_https_rustls()
+        }
+    }
+}
 
-    pub type HyperHttpsClient<B> = HyperClient<HttpsConnector<HttpConnector>, B>;
+/// TLS support with native-tls, which is OpenSSL on linux.
+#[cfg(feature = "client-hyper-native-tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-native-tls")))]
+pub mod https_native_tls {
+    use super::{HttpVersionMode, HyperClientBuilder, HyperHttpConnector};
+    use crate::client::metadata::MetadataConnector;
+    use hyper_tls::HttpsConnector;
+    use hyper_util::client::legacy::Client as HyperClient;
+
+    pub type HyperHttpsClient<B> =
+        HyperClient<MetadataConnector<HttpsConnector<HyperHttpConnector>>, B>;
 
-    use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
     pub fn build_https_client<B>() -> std::io::Result<HyperHttpsClient<B>>
     where
         B: http_body::Body + Send,
         B::Data: Send,
     {
-        let client = HyperClient::builder(TokioExecutor::default()).build(
-            HttpsConnector::<HttpConnector>::builder()
-                .with_tls_config(
-                    ClientConfig::builder()
-                        .with_native_roots()?
-                        .with_no_client_auth(),
-                )
-                .https_or_http()
-                .enable_all_versions()
-                .build(),
-        );
-        Ok(client)
+        HyperClientBuilder::new().build_https_native_tls()
+    }
+
+    impl HyperClientBuilder {
+        fn native_tls_connector(&self) -> std::io::Result<native_tls::TlsConnector> {
+            let mut builder = native_tls::TlsConnector::builder();
+            builder.disable_built_in_roots(!self.tls.built_in_roots);
+            for certificate in &self.tls.roots {
+                builder.add_root_certificate(certificate.to_native_tls()?);
+            }
+            if let Some(identity) = &self.tls.identity {
+                builder.identity(identity.to_native_tls()?);
+            }
+            let alpn: &[&str] = match self.version {
+                HttpVersionMode::Auto => &["h2", "http/1.1"],
+                HttpVersionMode::Http1Only => &["http/1.1"],
+                HttpVersionMode::Http2PriorKnowledge => &["h2"],
+            };
+            builder.request_alpns(alpn);
+            builder.build().map_err(std::io::Error::other)
+        }
+        /// Build a client which supports both http and https, using native-tls.
+        ///
+        /// The ALPN protocols are derived from the [`HttpVersionMode`].
+        pub fn build_https_native_tls<B>(&self) -> std::io::Result<HyperHttpsClient<B>>
+        where
+            B: http_body::Body + Send,
+            B::Data: Send,
+        {
+            let connector = self.metadata_connector(self.https_native_tls_connector()?);
+            Ok(self.hyper_builder().build(connector))
+        }
+        pub(crate) fn https_native_tls_connector(
+            &self,
+        ) -> std::io::Result<HttpsConnector<HyperHttpConnector>> {
+            let mut http = self.http_connector();
+            http.enforce_http(false);
+            let tls = tokio_native_tls::TlsConnector::from(self.native_tls_connector()?);
+            Ok(HttpsConnector::from((http, tls)))
+        }
+        /// Build a client which supports both http and https, with the default TLS backend.
+        #[cfg(not(feature = "client-hyper-rustls"))]
+        pub fn build_https<B>(&self) -> std::io::Result<HyperHttpsClient<B>>
+        where
+            B: http_body::Body + Send,
+            B::Data: Send,
+        {
+            self.build_https_native_tls()
+        }
     }
 }
 
+// rustls is the default TLS backend when both are enabled.
 #[cfg(feature = "client-hyper-rustls")]
 #[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-rustls")))]
-pub use tls::*;
+pub use self::https_rustls::{build_https_client, HyperHttpsClient};
+
+/// The connector of the default TLS backend.
+#[cfg(feature = "client-hyper-rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-rustls")))]
+pub type HttpsConnector = hyper_rustls::HttpsConnector<HyperHttpConnector>;
+
+#[cfg(all(
+    feature = "client-hyper-native-tls",
+    not(feature = "client-hyper-rustls")
+))]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-native-tls")))]
+pub type HttpsConnector = hyper_tls::HttpsConnector<HyperHttpConnector>;
+
+impl HyperClientBuilder {
+    /// The connector of [`HyperClientBuilder::build_https`].
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    pub(crate) fn https_connector(&self) -> std::io::Result<HttpsConnector> {
+        #[cfg(feature = "client-hyper-rustls")]
+        return self.https_rustls_connector();
+        #[cfg(not(feature = "client-hyper-rustls"))]
+        return self.https_native_tls_connector();
+    }
+}
 
-type HyperHttpClient<B> = HyperClient<HttpConnector, B>;
+#[cfg(all(
+    feature = "client-hyper-native-tls",
+    not(feature = "client-hyper-rustls")
+))]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-native-tls")))]
+pub use self::https_native_tls::{build_https_client, HyperHttpsClient};
+
+/// Clients for the smol runtime, they don't need a tokio runtime, though tokio is still compiled
+/// in through hyper-util and h2.
+#[cfg(feature = "runtime-smol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "runtime-smol")))]
+pub mod runtime_smol {
+    use super::HyperClientBuilder;
+    use crate::client::dns::DnsResolver;
+    use crate::client::metadata::MetadataConnector;
+    use crate::client::rt::{
+        SharedExecutor, SharedTimer, SmolConnector, SmolExecutor, SmolResolver, SmolTimer,
+    };
+    use hyper_util::client::legacy::Client as HyperClient;
+
+    pub type HyperSmolClient<B> = HyperClient<MetadataConnector<SmolConnector>, B>;
+    #[cfg(feature = "client-hyper-rustls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-rustls")))]
+    pub type HyperSmolHttpsClient<B> =
+        HyperClient<MetadataConnector<hyper_rustls::HttpsConnector<SmolConnector>>, B>;
+
+    impl HyperClientBuilder {
+        /// This builder, with the smol executor and timer unless others were set.
+        fn with_smol_defaults(&self) -> Self {
+            let mut builder = self.clone();
+            builder
+                .executor
+                .get_or_insert_with(|| SharedExecutor::new(SmolExecutor));
+            builder
+                .timer
+                .get_or_insert_with(|| SharedTimer::new(SmolTimer::new()));
+            builder
+        }
+        fn smol_connector(&self) -> SmolConnector {
+            let resolver = self
+                .resolver
+                .clone()
+                .unwrap_or_else(|| DnsResolver::new(SmolResolver));
+            SmolConnector::new(resolver)
+        }
+        /// Build a plain http client running on smol.
+        pub fn build_smol_http<B>(&self) -> HyperSmolClient<B>
+        where
+            B: http_body::Body + Send,
+            B::Data: Send,
+        {
+            let builder = self.with_smol_defaults();
+            builder.build_with_connector(builder.metadata_connector(self.smol_connector()))
+        }
+        /// Build a client running on smol which supports both http and https, using rustls.
+        #[cfg(feature = "client-hyper-rustls")]
+        #[cfg_attr(docsrs, doc(cfg(feature = "client-hyper-rustls")))]
+        pub fn build_smol_https<B>(&self) -> std::io::Result<HyperSmolHttpsClient<B>>
+        where
+            B: http_body::Body + Send,
+            B::Data: Send,
+        {
+            let builder = self.with_smol_defaults();
+            let connector =
+                builder.metadata_connector(self.rustls_connector(self.smol_connector())?);
+            Ok(builder.build_with_connector(connector))
+        }
+    }
+}
+
+pub type HyperHttpClient<B> = HyperClient<MetadataConnector<HyperHttpConnector>, B>;
 
 pub fn build_http_client<B>() -> HyperHttpClient<B>
 where
     B: http_body::Body + Send,
     B::Data: Send,
 {
-    HyperClient::builder(TokioExecutor::default()).build(HttpConnector::new())
+    HyperClientBuilder::new().build_http()
+}
+
+/// Builder for the hyper clients, when the defaults of [`build_http_client`] and `build_https_client` don't fit.
+///
+/// ```rust
+/// use client_util::client::{dns::{DnsResolver, IpFamily}, HyperClientBuilder};
+/// let resolver = DnsResolver::system()
+///     .with_override("api.internal", [([10, 0, 0, 1], 0).into()])
+///     .with_ip_family(IpFamily::V4);
+/// let client = HyperClientBuilder::new()
+///     .resolver(resolver)
+///     .build_http::<client_util::client::ClientBody>();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HyperClientBuilder {
+    resolver: Option<DnsResolver>,
+    version: HttpVersionMode,
+    executor: Option<SharedExecutor>,
+    timer: Option<SharedTimer>,
+    connect_timeout: Option<Duration>,
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    tls: TlsOptions,
+}
+
+impl HyperClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the dns resolver.
+    pub fn resolver(mut self, resolver: DnsResolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+    /// Set the executor driving the connections, tokio by default.
+    pub fn executor<E>(mut self, executor: E) -> Self
+    where
+        E: hyper::rt::Executor<crate::client::rt::BoxSendFuture> + Send + Sync + 'static,
+    {
+        self.executor = Some(SharedExecutor::new(executor));
+        self
+    }
+    /// Set the timer used for the pool idle timeout and the protocol timers.
+    pub fn timer<T>(mut self, timer: T) -> Self
+    where
+        T: hyper::rt::Timer + Send + Sync + 'static,
+    {
+        self.timer = Some(SharedTimer::new(timer));
+        self
+    }
+    /// Bound the dns resolution, the tcp connection and the TLS handshake of a new connection.
+    ///
+    /// The elapsed timeout is reported as a [`TimeoutError`](crate::client::timeout::TimeoutError),
+    /// the other timeouts are set with a [`TimeoutLayer`](crate::client::timeout::TimeoutLayer).
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+    /// Set which http versions the client may speak.
+    pub fn http_version(mut self, version: HttpVersionMode) -> Self {
+        self.version = version;
+        self
+    }
+    /// Trust the built-in root certificates of the platform, enabled by default.
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    pub fn tls_built_in_roots(mut self, enable: bool) -> Self {
+        self.tls.built_in_roots = enable;
+        self
+    }
+    /// Add a trusted root certificate.
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.tls.roots.push(certificate);
+        self
+    }
+    /// Set the client identity presented to the servers which require client authentication.
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.tls.identity = Some(identity);
+        self
+    }
+    /// A layer that rejects the requests whose [`http::Version`] can't be honored by the clients of this builder.
+    ///
+    /// Without it, such requests are either silently downgraded or fail with an opaque hyper error.
+    pub fn version_check(&self) -> VersionCheckLayer {
+        VersionCheckLayer::new(self.version)
+    }
+    pub(crate) fn http_connector(&self) -> HyperHttpConnector {
+        HyperHttpConnector::new(HttpConnector::new_with_resolver(
+            self.resolver.clone().unwrap_or_default(),
+        ))
+    }
+    pub(crate) fn metadata_connector<C>(&self, connector: C) -> MetadataConnector<C> {
+        let connector = MetadataConnector::new(connector);
+        match self.connect_timeout {
+            Some(timeout) => connector.connect_timeout(
+                timeout,
+                self.timer.clone().unwrap_or_else(SharedTimer::tokio),
+            ),
+            None => connector,
+        }
+    }
+    fn hyper_builder(&self) -> Builder {
+        let executor = self.executor.clone().unwrap_or_else(SharedExecutor::tokio);
+        let mut builder = HyperClient::builder(executor);
+        if let Some(timer) = &self.timer {
+            builder.timer(timer.clone()).pool_timer(timer.clone());
+        }
+        builder.http2_only(self.version == HttpVersionMode::Http2PriorKnowledge);
+        builder
+    }
+    /// Build a client over any connector, for example one of another async runtime.
+    ///
+    /// Wrap it in a [`MetadataConnector`] to insert a
+    /// [`ConnectionInfo`](crate::client::metadata::ConnectionInfo) in the responses.
+    pub fn build_with_connector<C, B>(&self, connector: C) -> HyperClient<C, B>
+    where
+        C: Connect + Clone,
+        B: http_body::Body + Send,
+        B::Data: Send,
+    {
+        self.hyper_builder().build(connector)
+    }
+    /// Build a plain http client.
+    pub fn build_http<B>(&self) -> HyperHttpClient<B>
+    where
+        B: http_body::Body + Send,
+        B::Data: Send,
+    {
+        self.hyper_builder()
+            .build(self.metadata_connector(self.http_connector()))
+    }
+}
+
+/// Which http versions a hyper client may speak.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HttpVersionMode {
+    /// HTTP/1 over plain tcp, HTTP/1 or HTTP/2 negotiated by ALPN over tls.
+    #[default]
+    Auto,
+    /// Only speak HTTP/1, even if the server supports HTTP/2.
+    Http1Only,
+    /// Only speak HTTP/2, with prior knowledge (h2c) over plain tcp.
+    Http2PriorKnowledge,
+}
+
+impl HttpVersionMode {
+    /// Check if a request of `version` can be sent by a client in this mode.
+    ///
+    /// For cleartext HTTP/2, [`HttpVersionMode::Http2PriorKnowledge`] is required,
+    /// since the hyper client doesn't support the `Upgrade: h2c` dance.
+    pub fn check(self, version: Version, secure: bool) -> Result<(), UnsupportedVersionError> {
+        let supported = match version {
+            Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11 => true,
+            Version::HTTP_2 => match self {
+                HttpVersionMode::Auto => secure,
+                HttpVersionMode::Http1Only => false,
+                HttpVersionMode::Http2PriorKnowledge => true,
+            },
+            _ => false,
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(UnsupportedVersionError {
+                version,
+                mode: self,
+                secure,
+            })
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{version:?} can't be honored by a client in {mode:?} mode over {}",
+    if *secure { "tls" } else { "plain tcp" }
+)]
+pub struct UnsupportedVersionError {
+    pub version: Version,
+    pub mode: HttpVersionMode,
+    pub secure: bool,
+}
+
+/// Layer for [`VersionCheck`].
+#[derive(Debug, Clone, Copy)]
+pub struct VersionCheckLayer {
+    mode: HttpVersionMode,
+}
+
+impl VersionCheckLayer {
+    pub fn new(mode: HttpVersionMode) -> Self {
+        Self { mode }
+    }
+}
+
+impl<S> tower::Layer<S> for VersionCheckLayer {
+    type Service = VersionCheck<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        VersionCheck {
+            inner,
+            mode: self.mode,
+        }
+    }
+}
+
+/// Reject the requests whose version can't be honored by the inner client, with an [`UnsupportedVersionError`].
+#[derive(Debug, Clone)]
+pub struct VersionCheck<S> {
+    inner: S,
+    mode: HttpVersionMode,
+}
+
+impl<S, B> tower_service::Service<Request<B>> for VersionCheck<S>
+where
+    S: tower_service::Service<Request<B>>,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future =
+        Either<Ready<Result<S::Response, BoxError>>, MapErr<S::Future, fn(S::Error) -> BoxError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let secure = request.uri().scheme() == Some(&http::uri::Scheme::HTTPS);
+        if let Err(error) = self.mode.check(request.version(), secure) {
+            return Either::Left(futures_util::future::ready(Err(error.into())));
+        }
+        Either::Right(self.inner.call(request).map_err(Into::into as fn(_) -> _))
+    }
+}
+
+/// Whether the request failed before being sent: it failed to connect, or the connection timed out.
+#[cfg(any(
+    feature = "retry",
+    feature = "balance",
+    feature = "circuit-breaker",
+    feature = "tracing",
+    feature = "metrics"
+))]
+pub(crate) fn is_connect_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    use crate::client::timeout::{TimeoutError, TimeoutPhase};
+    if let Some(timeout) = TimeoutError::find(error) {
+        return timeout.phase == TimeoutPhase::Connect;
+    }
+    let mut error = Some(error);
+    while let Some(current) = error {
+        if let Some(error) = current.downcast_ref::<hyper_util::client::legacy::Error>() {
+            return error.is_connect();
+        }
+        error = current.source();
+    }
+    false
+}
+
+/// A low cardinality kind of a client error: `timeout`, `connect` or `_OTHER`.
+#[cfg(any(feature = "tracing", feature = "metrics"))]
+pub(crate) fn error_kind(error: &(dyn std::error::Error + 'static)) -> &'static str {
+    if crate::client::timeout::TimeoutError::find(error).is_some() {
+        "timeout"
+    } else if is_connect_error(error) {
+        "connect"
+    } else {
+        "_OTHER"
+    }
 }
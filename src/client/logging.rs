@@ -0,0 +1,247 @@
+//! Logging of the requests and the responses, without their secrets.
+//!
+//! The [`LoggingLayer`] emits [`tracing`] events at the `DEBUG` level:
+//! - `request`, with the method, the uri and the headers of the request.
+//! - `response`, with the status, the elapsed time and the headers of the response, or
+//!   `request failed` with the error.
+//!
+//! The headers are redacted as in [`crate::redact`], along with the headers denied by
+//! [`LoggingLayer::deny_header`].
+//!
+//! With [`LoggingLayer::body_preview`], the first bytes of the text bodies are logged in a
+//! `request body` or `response body` event once the body is streamed: the bodies are still
+//! streamed to the server and to the caller, and a body never read is never logged.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::logging::LoggingLayer;
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(
+//!         LoggingLayer::new()
+//!             .deny_header(http::HeaderName::from_static("x-session"))
+//!             .body_preview(1024),
+//!     )
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use bytes::{Buf, Bytes};
+use http::header::{HeaderName, CONTENT_TYPE};
+use http::{HeaderMap, Request, Response};
+use http_body_util::BodyExt;
+
+use crate::client::replay::attempt;
+use crate::client::ClientBody;
+use crate::error::BoxError;
+use crate::redact::{RedactedHeaders, RedactedUri};
+
+/// Whether the body of a message is text, from its `Content-Type` header.
+fn is_text(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let content_type = content_type.to_ascii_lowercase();
+    content_type.starts_with("text/")
+        || content_type.contains("json")
+        || content_type.contains("xml")
+        || content_type.starts_with("application/x-www-form-urlencoded")
+}
+
+/// The first bytes of a body.
+#[derive(Debug)]
+struct Preview {
+    kind: &'static str,
+    bytes: Vec<u8>,
+    max: usize,
+    truncated: bool,
+}
+
+impl Preview {
+    fn new(kind: &'static str, headers: &HeaderMap, max: usize) -> Option<Self> {
+        (max > 0 && is_text(headers)).then(|| Self {
+            kind,
+            bytes: Vec::new(),
+            max,
+            truncated: false,
+        })
+    }
+    fn push(&mut self, data: &[u8]) {
+        let len = data.len().min(self.max - self.bytes.len());
+        self.bytes.extend_from_slice(&data[..len]);
+        self.truncated |= len < data.len();
+    }
+    fn log(self) {
+        let preview = String::from_utf8_lossy(&self.bytes);
+        tracing::debug!(
+            preview = %preview,
+            truncated = self.truncated,
+            "{}",
+            self.kind
+        );
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A body logging a preview of its bytes once streamed.
+    pub struct LoggedBody<B> {
+        #[pin]
+        inner: B,
+        preview: Option<Preview>,
+    }
+}
+
+impl<B> fmt::Debug for LoggedBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoggedBody").finish_non_exhaustive()
+    }
+}
+
+impl<B> http_body::Body for LoggedBody<B>
+where
+    B: http_body::Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = std::task::ready!(this.inner.as_mut().poll_frame(cx));
+        if let (Some(preview), Some(Ok(frame))) = (this.preview.as_mut(), &frame) {
+            if let Some(data) = frame.data_ref() {
+                preview.push(data.chunk());
+            }
+        }
+        // the end of the body isn't always polled once it's known
+        if !matches!(frame, Some(Ok(_))) || this.inner.is_end_stream() {
+            if let Some(preview) = this.preview.take() {
+                preview.log();
+            }
+        }
+        Poll::Ready(frame)
+    }
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer for [`Logging`].
+#[derive(Debug, Clone, Default)]
+pub struct LoggingLayer {
+    denied: Arc<Vec<HeaderName>>,
+    body_preview: usize,
+}
+
+impl LoggingLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Redact the value of the header `name` too, besides [`crate::redact::DENIED_HEADERS`].
+    pub fn deny_header(mut self, name: HeaderName) -> Self {
+        Arc::make_mut(&mut self.denied).push(name);
+        self
+    }
+    /// Log the first `max` bytes of the text bodies, none by default.
+    pub fn body_preview(mut self, max: usize) -> Self {
+        self.body_preview = max;
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for LoggingLayer {
+    type Service = Logging<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Logging {
+            inner,
+            denied: self.denied.clone(),
+            body_preview: self.body_preview,
+        }
+    }
+}
+
+/// Log the requests and the responses, see [`crate::client::logging`].
+#[derive(Debug, Clone)]
+pub struct Logging<S> {
+    inner: S,
+    denied: Arc<Vec<HeaderName>>,
+    body_preview: usize,
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Logging<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+    RB: http_body::Body + Send + 'static,
+{
+    type Response = Response<LoggedBody<RB>>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        tracing::debug!(
+            method = %request.method(),
+            uri = %RedactedUri(request.uri()),
+            headers = ?RedactedHeaders::new(request.headers()).deny(&self.denied),
+            "request"
+        );
+        let preview = Preview::new("request body", request.headers(), self.body_preview);
+        let request = request.map(|body| {
+            BodyExt::boxed(
+                LoggedBody {
+                    inner: body,
+                    preview,
+                }
+                .map_err(Into::into),
+            )
+        });
+        let start = Instant::now();
+        let response = attempt(self.inner.call(request));
+        let denied = self.denied.clone();
+        let body_preview = self.body_preview;
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => {
+                    tracing::debug!(
+                        status = response.status().as_u16(),
+                        elapsed = ?start.elapsed(),
+                        headers = ?RedactedHeaders::new(response.headers()).deny(&denied),
+                        "response"
+                    );
+                    let preview = Preview::new("response body", response.headers(), body_preview);
+                    Ok(response.map(|body| LoggedBody {
+                        inner: body,
+                        preview,
+                    }))
+                }
+                Err(error) => {
+                    tracing::debug!(error = %error, elapsed = ?start.elapsed(), "request failed");
+                    Err(error)
+                }
+            }
+        })
+    }
+}
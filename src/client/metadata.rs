@@ -0,0 +1,512 @@
+//! Metadata of the connections and responses of the hyper clients.
+//!
+//! The connectors of the clients built by [`HyperClientBuilder`](crate::client::HyperClientBuilder)
+//! insert a [`ConnectionInfo`] in the extensions of the responses: the peer address, the TLS
+//! session and how long it took to connect. The [`ResponseInfoLayer`] adds a [`ResponseInfo`]:
+//! whether the connection was reused, the time to the first byte and the body duration.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::metadata::{ConnectionInfo, ResponseInfo, ResponseInfoLayer};
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(ResponseInfoLayer)
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! let connection = response.extensions().get::<ConnectionInfo>().cloned();
+//! let info = response.extensions().get::<ResponseInfo>().cloned();
+//! let text = response.text().await?;
+//! let body_duration = info.as_ref().and_then(ResponseInfo::body_duration);
+//! println!("{connection:?} {info:?} {body_duration:?} {}", text.body());
+//! # Ok(())
+//! # }
+//! ```
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::future::{select, Either};
+use futures_util::TryFutureExt;
+use http::{Request, Response};
+use hyper::rt::Timer;
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpInfo};
+use hyper_util::rt::TokioIo;
+
+use crate::client::rt::SharedTimer;
+use crate::client::timeout::{TimeoutError, TimeoutPhase};
+use crate::error::BoxError;
+
+/// The connection a response was received on.
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    pub remote_addr: Option<SocketAddr>,
+    pub local_addr: Option<SocketAddr>,
+    /// The TLS session, `None` over plain tcp.
+    pub tls: Option<Arc<TlsInfo>>,
+    pub timings: ConnectTimings,
+    responses: Arc<AtomicU64>,
+}
+
+/// How long it took to open a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectTimings {
+    /// The dns resolution, `None` when the host is an ip address.
+    pub dns: Option<Duration>,
+    /// The tcp connection, after the dns resolution.
+    pub connect: Duration,
+    /// The TLS handshake, after the tcp connection.
+    pub tls: Option<Duration>,
+    pub total: Duration,
+}
+
+/// The TLS session of a connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// The protocol version, not reported by native-tls.
+    pub version: Option<TlsVersion>,
+    /// The protocol negotiated by ALPN, e.g. `h2`.
+    pub alpn: Option<Bytes>,
+    /// The DER encoded certificates of the peer, starting with its own.
+    ///
+    /// native-tls only reports the certificate of the peer, without its chain.
+    pub peer_certificates: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    Tls1_0,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+/// The metadata which can be read from an io of a connector, see [`MetadataConnector`].
+///
+/// The addresses are also read from the [`HttpInfo`] reported by the connection.
+pub trait ConnectionMetadata {
+    /// The remote and local addresses.
+    fn addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        None
+    }
+    fn tls_info(&self) -> Option<TlsInfo> {
+        None
+    }
+}
+
+impl<T> ConnectionMetadata for TokioIo<T> {}
+
+#[cfg(feature = "client-hyper-rustls")]
+impl<T: ConnectionMetadata> ConnectionMetadata for hyper_rustls::MaybeHttpsStream<T> {
+    fn addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        match self {
+            Self::Http(io) => io.addrs(),
+            Self::Https(io) => io.inner().get_ref().0.inner().addrs(),
+        }
+    }
+    fn tls_info(&self) -> Option<TlsInfo> {
+        let Self::Https(io) = self else {
+            return None;
+        };
+        let session = io.inner().get_ref().1;
+        let version = session
+            .protocol_version()
+            .and_then(|version| match version {
+                rustls::ProtocolVersion::TLSv1_0 => Some(TlsVersion::Tls1_0),
+                rustls::ProtocolVersion::TLSv1_1 => Some(TlsVersion::Tls1_1),
+                rustls::ProtocolVersion::TLSv1_2 => Some(TlsVersion::Tls1_2),
+                rustls::ProtocolVersion::TLSv1_3 => Some(TlsVersion::Tls1_3),
+                _ => None,
+            });
+        Some(TlsInfo {
+            version,
+            alpn: session.alpn_protocol().map(Bytes::copy_from_slice),
+            peer_certificates: session
+                .peer_certificates()
+                .unwrap_or_default()
+                .iter()
+                .map(|certificate| Bytes::copy_from_slice(certificate))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(feature = "client-hyper-native-tls")]
+impl<T> ConnectionMetadata for hyper_tls::MaybeHttpsStream<T>
+where
+    T: ConnectionMetadata + hyper::rt::Read + hyper::rt::Write + Unpin,
+{
+    fn addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        match self {
+            Self::Http(io) => io.addrs(),
+            Self::Https(io) => io.inner().get_ref().get_ref().get_ref().inner().addrs(),
+        }
+    }
+    fn tls_info(&self) -> Option<TlsInfo> {
+        let Self::Https(io) = self else {
+            return None;
+        };
+        let session = io.inner().get_ref();
+        Some(TlsInfo {
+            version: None,
+            alpn: session.negotiated_alpn().ok().flatten().map(Bytes::from),
+            peer_certificates: session
+                .peer_certificate()
+                .ok()
+                .flatten()
+                .and_then(|certificate| certificate.to_der().ok())
+                .map(Bytes::from)
+                .into_iter()
+                .collect(),
+        })
+    }
+}
+
+/// The instants recorded while a connector is connecting.
+#[derive(Debug, Clone, Copy, Default)]
+struct Recorder {
+    dns: Option<Instant>,
+    transport: Option<Instant>,
+}
+
+thread_local! {
+    static RECORDER: Cell<Option<Recorder>> = const { Cell::new(None) };
+}
+
+fn record(f: impl FnOnce(&mut Recorder)) {
+    RECORDER.with(|current| {
+        if let Some(mut recorder) = current.get() {
+            f(&mut recorder);
+            current.set(Some(recorder));
+        }
+    })
+}
+
+/// Record the end of the dns resolution of the connection being opened.
+pub(crate) fn record_dns() {
+    record(|recorder| {
+        recorder.dns.get_or_insert_with(Instant::now);
+    })
+}
+
+/// Record the end of the tcp connection of the connection being opened.
+pub(crate) fn record_transport() {
+    record(|recorder| {
+        recorder.transport.get_or_insert_with(Instant::now);
+    })
+}
+
+pin_project_lite::pin_project! {
+    /// Make the recorder current while polling the connecting future.
+    struct Recording<F> {
+        recorder: Recorder,
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F: Future> Future for Recording<F> {
+    type Output = (F::Output, Recorder);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        /// Put the outer recorder back, even if the inner future panics.
+        struct Restore<'a> {
+            slot: &'a mut Recorder,
+            outer: Option<Recorder>,
+        }
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                *self.slot = RECORDER.replace(self.outer).unwrap_or_default();
+            }
+        }
+        let this = self.project();
+        let outer = RECORDER.replace(Some(*this.recorder));
+        let restore = Restore {
+            slot: this.recorder,
+            outer,
+        };
+        let output = this.future.poll(cx);
+        drop(restore);
+        output.map(|output| (output, *this.recorder))
+    }
+}
+
+/// A connector inserting a [`ConnectionInfo`] in the extensions of the responses.
+///
+/// It also enforces the connect timeout, see [`crate::client::timeout`].
+#[derive(Debug, Clone)]
+pub struct MetadataConnector<C> {
+    inner: C,
+    timeout: Option<(Duration, SharedTimer)>,
+}
+
+impl<C> MetadataConnector<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            timeout: None,
+        }
+    }
+    /// Fail with a [`TimeoutError`] when connecting takes longer than `timeout`.
+    pub fn connect_timeout(mut self, timeout: Duration, timer: SharedTimer) -> Self {
+        self.timeout = Some((timeout, timer));
+        self
+    }
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+}
+
+impl<C> tower_service::Service<http::Uri> for MetadataConnector<C>
+where
+    C: tower_service::Service<http::Uri>,
+    C::Response: Connection + ConnectionMetadata,
+    C::Future: Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = MetadataIo<C::Response>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: http::Uri) -> Self::Future {
+        let start = Instant::now();
+        let connecting = Recording {
+            recorder: Recorder::default(),
+            future: self.inner.call(uri),
+        };
+        let timeout = self.timeout.clone();
+        Box::pin(async move {
+            let (io, recorder) = match timeout {
+                Some((timeout, timer)) => {
+                    match select(pin!(connecting), timer.sleep(timeout)).await {
+                        Either::Left((connected, _)) => connected,
+                        Either::Right(_) => {
+                            return Err(TimeoutError::new(TimeoutPhase::Connect, timeout).into())
+                        }
+                    }
+                }
+                None => connecting.await,
+            };
+            let io = io.map_err(Into::into)?;
+            let end = Instant::now();
+            let tls = io.tls_info().map(Arc::new);
+            let transport = recorder.transport.unwrap_or(end);
+            let timings = ConnectTimings {
+                dns: recorder.dns.map(|dns| dns - start),
+                connect: transport - recorder.dns.unwrap_or(start),
+                tls: tls.as_ref().map(|_| end - transport),
+                total: end - start,
+            };
+            let addrs = io.addrs().or_else(|| {
+                let mut extensions = http::Extensions::new();
+                io.connected().get_extras(&mut extensions);
+                let info = extensions.get::<HttpInfo>()?;
+                Some((info.remote_addr(), info.local_addr()))
+            });
+            let info = ConnectionInfo {
+                remote_addr: addrs.map(|(remote, _)| remote),
+                local_addr: addrs.map(|(_, local)| local),
+                tls,
+                timings,
+                responses: Arc::default(),
+            };
+            Ok(MetadataIo { inner: io, info })
+        })
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A connection opened by a [`MetadataConnector`].
+    pub struct MetadataIo<T> {
+        #[pin]
+        inner: T,
+        info: ConnectionInfo,
+    }
+}
+
+impl<T> fmt::Debug for MetadataIo<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataIo")
+            .field("info", &self.info)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> MetadataIo<T> {
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+}
+
+impl<T: Connection> Connection for MetadataIo<T> {
+    fn connected(&self) -> Connected {
+        self.inner.connected().extra(self.info.clone())
+    }
+}
+
+impl<T: hyper::rt::Read> hyper::rt::Read for MetadataIo<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<T: hyper::rt::Write> hyper::rt::Write for MetadataIo<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+}
+
+/// A response, as seen by the [`ResponseInfoLayer`].
+#[derive(Debug, Clone)]
+pub struct ResponseInfo {
+    /// Whether an earlier response was received on the same connection, `None` when the
+    /// connection has no [`ConnectionInfo`].
+    pub reused: Option<bool>,
+    /// From the call of the service to the response head, including the time to connect.
+    pub ttfb: Duration,
+    body: Arc<OnceLock<Duration>>,
+}
+
+impl ResponseInfo {
+    /// From the response head to the end of the body, once the body has been read.
+    pub fn body_duration(&self) -> Option<Duration> {
+        self.body.get().copied()
+    }
+}
+
+/// Layer for [`ResponseInfoService`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResponseInfoLayer;
+
+impl<S> tower::Layer<S> for ResponseInfoLayer {
+    type Service = ResponseInfoService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseInfoService { inner }
+    }
+}
+
+/// Insert a [`ResponseInfo`] in the extensions of the responses.
+#[derive(Debug, Clone)]
+pub struct ResponseInfoService<S> {
+    inner: S,
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for ResponseInfoService<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>>,
+    RB: http_body::Body,
+{
+    type Response = Response<TimedBody<RB>>;
+    type Error = S::Error;
+    type Future = futures_util::future::MapOk<
+        S::Future,
+        Box<dyn FnOnce(Response<RB>) -> Response<TimedBody<RB>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let start = Instant::now();
+        self.inner
+            .call(request)
+            .map_ok(Box::new(move |mut response| {
+                let head = Instant::now();
+                let reused = response
+                    .extensions()
+                    .get::<ConnectionInfo>()
+                    .map(|info| info.responses.fetch_add(1, Ordering::Relaxed) > 0);
+                let body = Arc::new(OnceLock::new());
+                if response.body().is_end_stream() {
+                    let _ = body.set(Duration::ZERO);
+                }
+                response.extensions_mut().insert(ResponseInfo {
+                    reused,
+                    ttfb: head - start,
+                    body: body.clone(),
+                });
+                response.map(|inner| TimedBody { inner, head, body })
+            }))
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A response body recording its duration in its [`ResponseInfo`].
+    pub struct TimedBody<B> {
+        #[pin]
+        inner: B,
+        head: Instant,
+        body: Arc<OnceLock<Duration>>,
+    }
+}
+
+impl<B> fmt::Debug for TimedBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimedBody")
+            .field("duration", &self.body.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: http_body::Body> http_body::Body for TimedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = this.inner.poll_frame(cx);
+        if let Poll::Ready(None) = frame {
+            let _ = this.body.set(this.head.elapsed());
+        }
+        frame
+    }
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
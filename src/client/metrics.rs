@@ -0,0 +1,235 @@
+//! Metrics of the requests, with the [`metrics`] facade.
+//!
+//! The [`MetricsLayer`] records, labeled by `method` and `host`:
+//! - `http_client_request_duration_seconds`, a histogram of the time to the response headers,
+//!   also labeled by `status_class`, like `2xx`, or `error` when the request failed.
+//! - `http_client_active_requests`, a gauge of the requests whose response body isn't read yet.
+//! - `http_client_request_bytes_total` and `http_client_response_bytes_total`, counters of the
+//!   bytes of the bodies, counted as they are streamed.
+//! - `http_client_errors_total`, a counter of the failed requests, also labeled by `error_kind`:
+//!   `timeout`, `connect`, `body` for an error while streaming the response body, or `_OTHER`.
+//!
+//! Install a recorder, like the one of `metrics-exporter-prometheus`, to export them.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::metrics::MetricsLayer;
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(MetricsLayer::new())
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use ::metrics::{counter, gauge, histogram, Counter, Gauge, Label};
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use http_body_util::BodyExt;
+
+use crate::client::hyper::error_kind;
+use crate::client::replay::attempt;
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+pub const REQUEST_DURATION: &str = "http_client_request_duration_seconds";
+pub const ACTIVE_REQUESTS: &str = "http_client_active_requests";
+pub const REQUEST_BYTES: &str = "http_client_request_bytes_total";
+pub const RESPONSE_BYTES: &str = "http_client_response_bytes_total";
+pub const ERRORS: &str = "http_client_errors_total";
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Counts a request as active until dropped.
+struct Active(Gauge);
+
+impl Active {
+    fn new(gauge: Gauge) -> Self {
+        gauge.increment(1.0);
+        Self(gauge)
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.decrement(1.0);
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A body counting its bytes as they are streamed.
+    pub struct MeteredBody<B> {
+        #[pin]
+        inner: B,
+        bytes: Counter,
+        // the labels of the errors of a response body, and the active request until its end
+        errors: Option<Vec<Label>>,
+        active: Option<Active>,
+    }
+}
+
+impl<B> fmt::Debug for MeteredBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeteredBody").finish_non_exhaustive()
+    }
+}
+
+impl<B> http_body::Body for MeteredBody<B>
+where
+    B: http_body::Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        use bytes::Buf;
+        let this = self.project();
+        let frame = std::task::ready!(this.inner.poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    this.bytes.increment(data.remaining() as u64);
+                }
+            }
+            Some(Err(_)) => {
+                if let Some(labels) = this.errors.take() {
+                    counter!(ERRORS, labels).increment(1);
+                }
+                this.active.take();
+            }
+            None => {
+                this.active.take();
+            }
+        }
+        Poll::Ready(frame)
+    }
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Layer for [`Metrics`].
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer {
+    labels: Vec<Label>,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a label to all the metrics, for example the name of the client.
+    pub fn label(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.labels.push(Label::new(key, value.into()));
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for MetricsLayer {
+    type Service = Metrics<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Metrics {
+            inner,
+            labels: self.labels.clone(),
+        }
+    }
+}
+
+/// Record the metrics of the requests, see [`crate::client::metrics`].
+#[derive(Debug, Clone)]
+pub struct Metrics<S> {
+    inner: S,
+    labels: Vec<Label>,
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Metrics<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+    RB: http_body::Body + Send + 'static,
+{
+    type Response = Response<MeteredBody<RB>>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let mut labels = self.labels.clone();
+        labels.push(Label::new("method", request.method().to_string()));
+        labels.push(Label::new(
+            "host",
+            request
+                .uri()
+                .host()
+                .unwrap_or_default()
+                .to_ascii_lowercase(),
+        ));
+        let active = Active::new(gauge!(ACTIVE_REQUESTS, labels.clone()));
+        let request = request.map(|body| {
+            BodyExt::boxed(
+                MeteredBody {
+                    inner: body,
+                    bytes: counter!(REQUEST_BYTES, labels.clone()),
+                    errors: None,
+                    active: None,
+                }
+                .map_err(Into::into),
+            )
+        });
+        let start = Instant::now();
+        let response = attempt(self.inner.call(request));
+        let response_bytes = counter!(RESPONSE_BYTES, labels.clone());
+        let with = move |key: &'static str, value: &'static str| {
+            let mut labels = labels.clone();
+            labels.push(Label::from_static_parts(key, value));
+            labels
+        };
+        Box::pin(async move {
+            match response.await {
+                Ok(response) => {
+                    let class = status_class(response.status());
+                    histogram!(REQUEST_DURATION, with("status_class", class))
+                        .record(start.elapsed());
+                    Ok(response.map(|body| MeteredBody {
+                        inner: body,
+                        bytes: response_bytes,
+                        errors: Some(with("error_kind", "body")),
+                        active: Some(active),
+                    }))
+                }
+                Err(error) => {
+                    histogram!(REQUEST_DURATION, with("status_class", "error"))
+                        .record(start.elapsed());
+                    counter!(ERRORS, with("error_kind", error_kind(&*error))).increment(1);
+                    Err(error)
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,651 @@
+//! Introspection of the connection pool of the hyper clients, and preconnection.
+//!
+//! The connectors of the clients built by
+//! [`HyperClientBuilder::build_http_tracked`](crate::client::HyperClientBuilder::build_http_tracked)
+//! and `build_https_tracked` report the connections they open to a [`PoolTracker`]. Requests and in-flight responses are
+//! counted by the [`PoolTrackingLayer`], which must wrap the client:
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::pool::PoolTracker;
+//! use client_util::prelude::*;
+//! let tracker = PoolTracker::new();
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(tracker.layer())
+//!     .service(HyperClientBuilder::new().build_http_tracked(&tracker));
+//! tracker.preconnect("http://example.com".parse().unwrap()).await.expect("failed to preconnect");
+//! RequestBuilder::get("http://example.com/")?.empty().send(client).await?;
+//! for (origin, stats) in tracker.snapshot() {
+//!     println!("{origin}: {} idle, {} active", stats.idle, stats.active);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::TryFutureExt;
+use http::{Request, Response, Uri, Version};
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use tower::ServiceExt;
+
+use crate::client::hyper::{HyperClientBuilder, HyperHttpConnector};
+use crate::client::metadata::MetadataConnector;
+use crate::error::BoxError;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Identifies a connection opened by a tracked connector.
+///
+/// It's inserted in the extensions of the responses received on this connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(u64);
+
+/// The state of one connection.
+#[derive(Debug, Clone)]
+pub struct ConnectionStats {
+    pub id: ConnectionId,
+    /// The negotiated version, known after the first response, or the ALPN negotiation of h2.
+    pub version: Option<Version>,
+    /// How many responses were received on this connection.
+    pub requests: u64,
+    /// How many responses are being received on this connection.
+    pub in_flight: usize,
+    pub age: Duration,
+    /// Whether the connection was opened by [`PoolTracker::preconnect`].
+    pub preconnected: bool,
+}
+
+/// The connections to one origin.
+#[derive(Debug, Clone, Default)]
+pub struct OriginStats {
+    pub idle: usize,
+    pub active: usize,
+    pub connections: Vec<ConnectionStats>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    origin: String,
+    version: Option<Version>,
+    requests: u64,
+    in_flight: usize,
+    opened: Instant,
+    preconnected: bool,
+}
+
+/// How long and how many preconnected connections are kept.
+#[derive(Debug, Clone, Copy)]
+struct WarmLimits {
+    timeout: Duration,
+    max: usize,
+}
+
+trait Preconnect: Send + Sync {
+    fn preconnect(self: Arc<Self>, uri: Uri, limits: WarmLimits)
+        -> BoxFuture<Result<(), BoxError>>;
+}
+
+#[derive(Default)]
+struct Inner {
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<ConnectionId, Entry>>,
+    connectors: Mutex<Vec<Weak<dyn Preconnect>>>,
+}
+
+/// Collects the state of the connections opened by the connectors it's attached to.
+#[derive(Clone)]
+pub struct PoolTracker {
+    inner: Arc<Inner>,
+    warm: WarmLimits,
+}
+
+impl Default for PoolTracker {
+    fn default() -> Self {
+        Self {
+            inner: Arc::default(),
+            warm: WarmLimits {
+                timeout: Duration::from_secs(90),
+                max: 4,
+            },
+        }
+    }
+}
+
+impl fmt::Debug for PoolTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoolTracker")
+            .field("connections", &self.connections().len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The origin of a uri, with the default port of the scheme when it has none.
+pub fn origin(uri: &Uri) -> String {
+    let scheme = uri.scheme_str().unwrap_or("http");
+    let host = uri.host().unwrap_or_default();
+    let port = uri
+        .port_u16()
+        .unwrap_or(if scheme == "https" { 443 } else { 80 });
+    format!("{scheme}://{host}:{port}")
+}
+
+impl PoolTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// How long a preconnected connection is kept unused before it's closed, `90s` by default,
+    /// like the idle connections of the hyper pool.
+    ///
+    /// Keep it below the idle timeout of the server, a connection closed by the server is only
+    /// noticed when a request is sent on it.
+    pub fn warm_timeout(mut self, timeout: Duration) -> Self {
+        self.warm.timeout = timeout;
+        self
+    }
+    /// The most preconnected connections kept unused by origin and by client, `4` by default.
+    pub fn max_warm(mut self, max: usize) -> Self {
+        self.warm.max = max;
+        self
+    }
+    fn connections(&self) -> std::sync::MutexGuard<'_, HashMap<ConnectionId, Entry>> {
+        self.inner
+            .connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    fn register(&self, origin: String, preconnected: bool) -> ConnectionId {
+        let id = ConnectionId(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
+        self.connections().insert(
+            id,
+            Entry {
+                origin,
+                version: None,
+                requests: 0,
+                in_flight: 0,
+                opened: Instant::now(),
+                preconnected,
+            },
+        );
+        id
+    }
+    fn update(&self, id: ConnectionId, f: impl FnOnce(&mut Entry)) {
+        if let Some(entry) = self.connections().get_mut(&id) {
+            f(entry)
+        }
+    }
+    /// The open connections, grouped by origin.
+    pub fn snapshot(&self) -> BTreeMap<String, OriginStats> {
+        let now = Instant::now();
+        let mut origins = BTreeMap::<String, OriginStats>::new();
+        for (id, entry) in self.connections().iter() {
+            let stats = origins.entry(entry.origin.clone()).or_default();
+            if entry.in_flight == 0 {
+                stats.idle += 1;
+            } else {
+                stats.active += 1;
+            }
+            stats.connections.push(ConnectionStats {
+                id: *id,
+                version: entry.version,
+                requests: entry.requests,
+                in_flight: entry.in_flight,
+                age: now - entry.opened,
+                preconnected: entry.preconnected,
+            });
+        }
+        for stats in origins.values_mut() {
+            stats.connections.sort_by_key(|connection| connection.id);
+        }
+        origins
+    }
+    /// The open connections to the origin of `uri`.
+    pub fn origin_stats(&self, uri: &Uri) -> OriginStats {
+        self.snapshot().remove(&origin(uri)).unwrap_or_default()
+    }
+    /// A layer counting the requests and in-flight responses of each connection.
+    pub fn layer(&self) -> PoolTrackingLayer {
+        PoolTrackingLayer {
+            tracker: self.clone(),
+        }
+    }
+    /// Open a connection to the origin of `uri` with every client attached to this tracker.
+    ///
+    /// The tcp connection and the TLS handshake, including the ALPN negotiation, are done now. The
+    /// connection is kept until a request to this origin needs a new connection, the HTTP/2
+    /// handshake is done at that time, or until [`PoolTracker::warm_timeout`]. Nothing is opened
+    /// when [`PoolTracker::max_warm`] connections are already kept for this origin.
+    pub async fn preconnect(&self, uri: Uri) -> Result<(), BoxError> {
+        let connectors = {
+            let mut connectors = self
+                .inner
+                .connectors
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            connectors.retain(|connector| connector.strong_count() > 0);
+            connectors
+                .iter()
+                .filter_map(Weak::upgrade)
+                .collect::<Vec<_>>()
+        };
+        if connectors.is_empty() {
+            return Err(io::Error::other("no client is attached to this pool tracker").into());
+        }
+        for connector in connectors {
+            connector.preconnect(uri.clone(), self.warm).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes the connection from the tracker when dropped.
+struct Registration {
+    id: ConnectionId,
+    tracker: PoolTracker,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.tracker.connections().remove(&self.id);
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A connection reported to a [`PoolTracker`].
+    pub struct TrackedIo<T> {
+        #[pin]
+        inner: T,
+        registration: Registration,
+    }
+}
+
+impl<T> fmt::Debug for TrackedIo<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrackedIo")
+            .field("id", &self.registration.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> TrackedIo<T> {
+    pub fn id(&self) -> ConnectionId {
+        self.registration.id
+    }
+}
+
+impl<T: Connection> Connection for TrackedIo<T> {
+    fn connected(&self) -> Connected {
+        let connected = self.inner.connected();
+        if connected.is_negotiated_h2() {
+            self.registration
+                .tracker
+                .update(self.registration.id, |entry| {
+                    entry.version = Some(Version::HTTP_2)
+                });
+        }
+        connected.extra(self.registration.id)
+    }
+}
+
+impl<T: hyper::rt::Read> hyper::rt::Read for TrackedIo<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<T: hyper::rt::Write> hyper::rt::Write for TrackedIo<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+}
+
+/// A preconnected connection, as a `TrackedIo<C::Response>`.
+struct Warm {
+    io: Box<dyn Any + Send>,
+    deadline: Instant,
+}
+
+struct Shared<C> {
+    connector: C,
+    tracker: PoolTracker,
+    /// Preconnected connections by origin.
+    warm: Mutex<HashMap<String, Vec<Warm>>>,
+}
+
+impl<C> Shared<C> {
+    /// The unexpired preconnected connections to `origin`, the expired ones are closed.
+    fn warm(&self, origin: &str, f: impl FnOnce(&mut Vec<Warm>)) {
+        let mut warm = self.warm.lock().unwrap_or_else(PoisonError::into_inner);
+        let connections = warm.entry(origin.to_owned()).or_default();
+        let now = Instant::now();
+        connections.retain(|connection| connection.deadline > now);
+        f(connections);
+        if connections.is_empty() {
+            warm.remove(origin);
+        }
+    }
+    fn take_warm(&self, origin: &str) -> Option<Box<dyn Any + Send>> {
+        let mut taken = None;
+        self.warm(origin, |connections| {
+            taken = connections.pop().map(|connection| connection.io);
+        });
+        taken
+    }
+}
+
+/// A connector reporting its connections to a [`PoolTracker`].
+pub struct TrackedConnector<C> {
+    shared: Arc<Shared<C>>,
+}
+
+impl<C> Clone for TrackedConnector<C> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for TrackedConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrackedConnector")
+            .field("tracker", &self.shared.tracker)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> TrackedConnector<C>
+where
+    C: tower_service::Service<Uri> + Clone + Send + Sync + 'static,
+    C::Response: Send + 'static,
+    C::Future: Send,
+    C::Error: Into<BoxError>,
+{
+    pub fn new(connector: C, tracker: PoolTracker) -> Self {
+        let shared = Arc::new(Shared {
+            connector,
+            tracker: tracker.clone(),
+            warm: Mutex::default(),
+        });
+        let weak: Weak<dyn Preconnect> = Arc::downgrade(&(shared.clone() as Arc<dyn Preconnect>));
+        tracker
+            .inner
+            .connectors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(weak);
+        Self { shared }
+    }
+    pub fn tracker(&self) -> &PoolTracker {
+        &self.shared.tracker
+    }
+    fn connect(
+        &self,
+        uri: Uri,
+        preconnected: bool,
+    ) -> BoxFuture<Result<TrackedIo<C::Response>, BoxError>> {
+        let tracker = self.shared.tracker.clone();
+        let origin = origin(&uri);
+        let connecting = self.shared.connector.clone().oneshot(uri);
+        Box::pin(async move {
+            let io = connecting.await.map_err(Into::into)?;
+            let id = tracker.register(origin, preconnected);
+            Ok(TrackedIo {
+                inner: io,
+                registration: Registration { id, tracker },
+            })
+        })
+    }
+}
+
+impl<C> Preconnect for Shared<C>
+where
+    C: tower_service::Service<Uri> + Clone + Send + Sync + 'static,
+    C::Response: Send + 'static,
+    C::Future: Send,
+    C::Error: Into<BoxError>,
+{
+    fn preconnect(
+        self: Arc<Self>,
+        uri: Uri,
+        limits: WarmLimits,
+    ) -> BoxFuture<Result<(), BoxError>> {
+        let connector = TrackedConnector { shared: self };
+        Box::pin(async move {
+            let key = origin(&uri);
+            let mut full = false;
+            connector
+                .shared
+                .warm(&key, |connections| full = connections.len() >= limits.max);
+            if full {
+                return Ok(());
+            }
+            let io = connector.connect(uri, true).await?;
+            let deadline = Instant::now() + limits.timeout;
+            connector.shared.warm(&key, |connections| {
+                // another preconnection may have filled the origin meanwhile
+                if connections.len() < limits.max {
+                    connections.push(Warm {
+                        io: Box::new(io),
+                        deadline,
+                    });
+                }
+            });
+            Ok(())
+        })
+    }
+}
+
+impl<C> tower_service::Service<Uri> for TrackedConnector<C>
+where
+    C: tower_service::Service<Uri> + Clone + Send + Sync + 'static,
+    C::Response: Send + 'static,
+    C::Future: Send,
+    C::Error: Into<BoxError>,
+{
+    type Response = TrackedIo<C::Response>;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        if let Some(io) = self.shared.take_warm(&origin(&uri)) {
+            if let Ok(io) = io.downcast::<TrackedIo<C::Response>>() {
+                return Box::pin(futures_util::future::ready(Ok(*io)));
+            }
+        }
+        self.connect(uri, false)
+    }
+}
+
+/// Layer for [`PoolTracking`].
+#[derive(Debug, Clone)]
+pub struct PoolTrackingLayer {
+    tracker: PoolTracker,
+}
+
+impl<S> tower::Layer<S> for PoolTrackingLayer {
+    type Service = PoolTracking<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        PoolTracking {
+            inner,
+            tracker: self.tracker.clone(),
+        }
+    }
+}
+
+/// Count the requests and the in-flight responses of the connections reported to a [`PoolTracker`].
+#[derive(Debug, Clone)]
+pub struct PoolTracking<S> {
+    inner: S,
+    tracker: PoolTracker,
+}
+
+/// Marks a response as in flight on its connection until dropped.
+struct InFlight {
+    id: ConnectionId,
+    tracker: PoolTracker,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.tracker.update(self.id, |entry| {
+            entry.in_flight = entry.in_flight.saturating_sub(1)
+        });
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A response body, in flight on its connection until it ends or is dropped.
+    pub struct TrackedBody<B> {
+        #[pin]
+        inner: B,
+        in_flight: Option<InFlight>,
+    }
+}
+
+impl<B> fmt::Debug for TrackedBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrackedBody")
+            .field("in_flight", &self.in_flight.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: http_body::Body> http_body::Body for TrackedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = this.inner.poll_frame(cx);
+        if let Poll::Ready(None) = frame {
+            this.in_flight.take();
+        }
+        frame
+    }
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for PoolTracking<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>>,
+    S::Future: Send + 'static,
+    RB: http_body::Body,
+{
+    type Response = Response<TrackedBody<RB>>;
+    type Error = S::Error;
+    type Future = futures_util::future::MapOk<
+        S::Future,
+        Box<dyn FnOnce(Response<RB>) -> Response<TrackedBody<RB>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let tracker = self.tracker.clone();
+        self.inner.call(request).map_ok(Box::new(move |response| {
+            let in_flight = response.extensions().get::<ConnectionId>().map(|&id| {
+                let version = response.version();
+                tracker.update(id, |entry| {
+                    entry.version = Some(version);
+                    entry.requests += 1;
+                    entry.in_flight += 1;
+                });
+                InFlight { id, tracker }
+            });
+            let in_flight = if response.body().is_end_stream() {
+                None
+            } else {
+                in_flight
+            };
+            response.map(|inner| TrackedBody { inner, in_flight })
+        }))
+    }
+}
+
+/// A hyper client whose connections are reported to a [`PoolTracker`].
+pub type TrackedHyperClient<C, B> = hyper_util::client::legacy::Client<TrackedConnector<C>, B>;
+
+impl HyperClientBuilder {
+    /// Build a plain http client reporting its connections to `tracker`.
+    pub fn build_http_tracked<B>(
+        &self,
+        tracker: &PoolTracker,
+    ) -> TrackedHyperClient<MetadataConnector<HyperHttpConnector>, B>
+    where
+        B: http_body::Body + Send,
+        B::Data: Send,
+    {
+        self.build_with_connector(TrackedConnector::new(
+            self.metadata_connector(self.http_connector()),
+            tracker.clone(),
+        ))
+    }
+    /// Build a client which supports both http and https, with the default TLS backend, reporting
+    /// its connections to `tracker`.
+    #[cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "client-hyper-rustls", feature = "client-hyper-native-tls")))
+    )]
+    pub fn build_https_tracked<B>(
+        &self,
+        tracker: &PoolTracker,
+    ) -> io::Result<TrackedHyperClient<MetadataConnector<crate::client::hyper::HttpsConnector>, B>>
+    where
+        B: http_body::Body + Send,
+        B::Data: Send,
+    {
+        let connector = TrackedConnector::new(
+            self.metadata_connector(self.https_connector()?),
+            tracker.clone(),
+        );
+        Ok(self.build_with_connector(connector))
+    }
+}
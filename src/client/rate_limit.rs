@@ -0,0 +1,426 @@
+//! Client side rate limiting, by host.
+//!
+//! The [`RateLimitLayer`] delays the requests with a token bucket for each key, the host of the
+//! request by default, see [`RateLimiter::key`].
+//!
+//! The limiter also adapts to the quota announced by the server:
+//! - `Retry-After` holds the requests to the key until the delay elapsed.
+//! - `RateLimit-Remaining` and `RateLimit-Reset`, also with the `X-` prefix or as the fields of
+//!   a `RateLimit` header, spread the remaining requests until the reset, and hold the requests
+//!   until the reset when none remains.
+//!
+//! The time spent waiting is in the [`RateLimitWait`] extension of the responses, and summed in
+//! the [`RateLimitStats`] of the limiter.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use std::time::Duration;
+//! use client_util::client::rate_limit::{Quota, RateLimitLayer, RateLimiter};
+//! use client_util::prelude::*;
+//! let limiter = RateLimiter::new(Quota::per_second(10).burst(20))
+//!     .quota_for("api.example.com", Quota::new(100, Duration::from_secs(60)));
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(RateLimitLayer::new(limiter.clone()))
+//!     .service(build_https_client().expect("failed to build client"));
+//! RequestBuilder::get("https://api.example.com/")?.empty().send(client).await?;
+//! println!("waited {:?}", limiter.stats().waited);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+
+use http::{HeaderMap, Request, Response, StatusCode, Uri};
+use hyper::rt::Timer;
+
+use crate::client::replay::attempt;
+use crate::client::rt::SharedTimer;
+use crate::error::BoxError;
+
+/// A number of requests by period, with a burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    requests: u32,
+    period: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// `requests` by `period`, as a burst too.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let requests = requests.max(1);
+        Self {
+            requests,
+            period,
+            burst: requests,
+        }
+    }
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+    pub fn per_minute(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(60))
+    }
+    /// The number of requests sent at once after an idle time.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+    /// The time to get a token back.
+    fn interval(&self) -> Duration {
+        self.period / self.requests
+    }
+}
+
+/// The time a request waited for the rate limit, inserted in the extensions of its response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitWait(pub Duration);
+
+/// The requests delayed by a limiter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitStats {
+    pub requests: u64,
+    /// The requests which waited.
+    pub delayed: u64,
+    /// The sum of the waits.
+    pub waited: Duration,
+}
+
+impl RateLimitStats {
+    fn record(&mut self, wait: Duration) {
+        self.requests += 1;
+        if !wait.is_zero() {
+            self.delayed += 1;
+            self.waited += wait;
+        }
+    }
+}
+
+/// The state of a key, the bucket is a virtual schedule: the time the next token is available.
+#[derive(Debug)]
+struct Bucket {
+    quota: Quota,
+    /// When the bucket is full, the next request waits for `next - burst * interval` otherwise.
+    next: Instant,
+    /// Held by the server until then.
+    held_until: Option<Instant>,
+    /// The spacing of the requests to the server quota, until its reset.
+    pace: Option<(Duration, Instant)>,
+    /// The time of the last request, for the pace.
+    last: Option<Instant>,
+    stats: RateLimitStats,
+}
+
+impl Bucket {
+    fn new(quota: Quota, now: Instant) -> Self {
+        Self {
+            quota,
+            next: now,
+            held_until: None,
+            pace: None,
+            last: None,
+            stats: RateLimitStats::default(),
+        }
+    }
+    /// Reserve a token, returning the wait before sending.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let interval = self.quota.interval();
+        let burst = interval * self.quota.burst;
+        // a full bucket doesn't accumulate more tokens
+        let next = self.next.max(now);
+        let mut at = (next + interval).checked_sub(burst).unwrap_or(now).max(now);
+        if let Some(until) = self.held_until.filter(|until| *until > now) {
+            at = at.max(until);
+        }
+        if let (Some((spacing, reset)), Some(last)) = (self.pace, self.last) {
+            if reset > now {
+                at = at.max(last + spacing);
+            }
+        }
+        self.next = next.max(at) + interval;
+        self.last = Some(at);
+        let wait = at - now;
+        self.stats.record(wait);
+        wait
+    }
+    /// Adapt to the rate limit headers of a response.
+    fn adapt(&mut self, status: StatusCode, headers: &HeaderMap, now: Instant) {
+        let retry_after = match status {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                crate::util::retry_after(headers)
+            }
+            _ => None,
+        };
+        if let Some(delay) = retry_after {
+            self.held_until = Some(now + delay);
+        }
+        let Some(ServerQuota { remaining, reset }) = ServerQuota::parse(headers) else {
+            return;
+        };
+        let reset = now + reset;
+        if remaining == 0 {
+            self.held_until = Some(self.held_until.map_or(reset, |until| until.max(reset)));
+            self.pace = None;
+        } else {
+            self.pace = Some(((reset - now) / remaining, reset));
+        }
+    }
+}
+
+/// The quota announced by the server.
+struct ServerQuota {
+    remaining: u32,
+    reset: Duration,
+}
+
+impl ServerQuota {
+    fn parse(headers: &HeaderMap) -> Option<Self> {
+        let header = |names: [&str; 2]| {
+            names
+                .into_iter()
+                .find_map(|name| headers.get(name)?.to_str().ok()?.trim().parse::<u64>().ok())
+        };
+        // the fields of the structured `RateLimit` header
+        let field = |name: &str| {
+            let value = headers.get("ratelimit")?.to_str().ok()?;
+            value.split([',', ';']).find_map(|item| {
+                let (key, value) = item.split_once('=')?;
+                (key.trim().eq_ignore_ascii_case(name))
+                    .then(|| value.trim().parse::<u64>().ok())
+                    .flatten()
+            })
+        };
+        let remaining = header(["ratelimit-remaining", "x-ratelimit-remaining"])
+            .or_else(|| field("remaining").or_else(|| field("r")))?;
+        let reset = header(["ratelimit-reset", "x-ratelimit-reset"])
+            .or_else(|| field("reset").or_else(|| field("t")))?;
+        // some servers send the time of the reset rather than the delay
+        let reset = match SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(reset)) {
+            Some(time) if reset > 1_000_000_000 => {
+                time.duration_since(SystemTime::now()).unwrap_or_default()
+            }
+            _ => Duration::from_secs(reset),
+        };
+        Some(Self {
+            remaining: u32::try_from(remaining).unwrap_or(u32::MAX),
+            reset,
+        })
+    }
+}
+
+type KeyFn = dyn Fn(&Uri, &HeaderMap) -> String + Send + Sync;
+
+struct Limits {
+    default: Quota,
+    quotas: HashMap<String, Quota>,
+    key: Arc<KeyFn>,
+    adaptive: bool,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// A copy of the settings, with its own buckets.
+impl Clone for Limits {
+    fn clone(&self) -> Self {
+        Self {
+            default: self.default,
+            quotas: self.quotas.clone(),
+            key: self.key.clone(),
+            adaptive: self.adaptive,
+            buckets: Mutex::default(),
+        }
+    }
+}
+
+/// The shared state of the rate limit, a bucket for each key.
+///
+/// The clones of a limiter share its buckets, but a clone configured with the builder methods is a
+/// new limiter.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limits: Arc<Limits>,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("default", &self.limits.default)
+            .field("quotas", &self.limits.quotas)
+            .field("adaptive", &self.limits.adaptive)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter {
+    /// Limit each host to `quota`.
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            limits: Arc::new(Limits {
+                default: quota,
+                quotas: HashMap::new(),
+                key: Arc::new(|uri, _| uri.host().unwrap_or_default().to_ascii_lowercase()),
+                adaptive: true,
+                buckets: Mutex::default(),
+            }),
+        }
+    }
+    /// Configure this limiter, a limiter configured once cloned gets its own buckets.
+    fn limits(mut self, f: impl FnOnce(&mut Limits)) -> Self {
+        f(Arc::make_mut(&mut self.limits));
+        self
+    }
+    /// Limit the requests of `key` to `quota`, rather than the default quota.
+    pub fn quota_for(self, key: impl Into<String>, quota: Quota) -> Self {
+        self.limits(|limits| {
+            limits.quotas.insert(key.into(), quota);
+        })
+    }
+    /// The key of the bucket of a request, by default its host.
+    ///
+    /// For example the API key of the request, for a quota by account.
+    pub fn key<F>(self, key: F) -> Self
+    where
+        F: Fn(&Uri, &HeaderMap) -> String + Send + Sync + 'static,
+    {
+        self.limits(|limits| limits.key = Arc::new(key))
+    }
+    /// Adapt to the rate limit headers of the responses, enabled by default.
+    pub fn adaptive(self, enable: bool) -> Self {
+        self.limits(|limits| limits.adaptive = enable)
+    }
+    fn buckets(&self) -> std::sync::MutexGuard<'_, HashMap<String, Bucket>> {
+        self.limits
+            .buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    fn reserve(&self, key: &str) -> Duration {
+        let now = Instant::now();
+        let quota = self
+            .limits
+            .quotas
+            .get(key)
+            .copied()
+            .unwrap_or(self.limits.default);
+        self.buckets()
+            .entry(key.to_owned())
+            .or_insert_with(|| Bucket::new(quota, now))
+            .reserve(now)
+    }
+    fn adapt(&self, key: &str, status: StatusCode, headers: &HeaderMap) {
+        if !self.limits.adaptive {
+            return;
+        }
+        if let Some(bucket) = self.buckets().get_mut(key) {
+            bucket.adapt(status, headers, Instant::now());
+        }
+    }
+    /// The requests delayed for all the keys.
+    pub fn stats(&self) -> RateLimitStats {
+        self.buckets()
+            .values()
+            .fold(RateLimitStats::default(), |mut total, bucket| {
+                total.requests += bucket.stats.requests;
+                total.delayed += bucket.stats.delayed;
+                total.waited += bucket.stats.waited;
+                total
+            })
+    }
+    /// The requests delayed for `key`.
+    pub fn key_stats(&self, key: &str) -> RateLimitStats {
+        self.buckets()
+            .get(key)
+            .map(|bucket| bucket.stats)
+            .unwrap_or_default()
+    }
+}
+
+/// Layer for [`RateLimit`].
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+    timer: SharedTimer,
+}
+
+impl RateLimitLayer {
+    /// Limit the requests with `limiter`, waiting with the tokio timer.
+    pub fn new(limiter: RateLimiter) -> Self {
+        Self {
+            limiter,
+            timer: SharedTimer::tokio(),
+        }
+    }
+    /// Set the timer the requests wait on for their quota, for another runtime than tokio.
+    pub fn timer<T>(mut self, timer: T) -> Self
+    where
+        T: Timer + Send + Sync + 'static,
+    {
+        self.timer = SharedTimer::new(timer);
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+            timer: self.timer.clone(),
+        }
+    }
+}
+
+/// Delay the requests to their rate limit, see [`crate::client::rate_limit`].
+#[derive(Debug, Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: RateLimiter,
+    timer: SharedTimer,
+}
+
+impl<S> RateLimit<S> {
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for RateLimit<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // the ready service waits with the request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let timer = self.timer.clone();
+        let key = (limiter.limits.key)(request.uri(), request.headers());
+        let wait = limiter.reserve(&key);
+        Box::pin(async move {
+            if !wait.is_zero() {
+                timer.sleep(wait).await;
+            }
+            let mut response = attempt(inner.call(request)).await?;
+            limiter.adapt(&key, response.status(), response.headers());
+            response.extensions_mut().insert(RateLimitWait(wait));
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,387 @@
+//! Following of the redirects.
+//!
+//! The [`RedirectLayer`] follows the `301`, `302`, `303`, `307` and `308` responses with a
+//! `Location` header:
+//! - `301` and `302` turn a `POST` into a `GET`, `303` turns any method but `HEAD` into a `GET`,
+//!   without the body. `307` and `308` send the method and the body again.
+//! - a hop to another origin drops the `Authorization`, `Proxy-Authorization` and `Cookie` headers,
+//!   and the headers marked as sensitive, like the ones of
+//!   [`RequestBuilder::basic_auth`](crate::request::RequestBuilder::basic_auth) and
+//!   [`RequestBuilder::bearer_auth`](crate::request::RequestBuilder::bearer_auth).
+//! - a hop from `https` to `http` is refused, unless [`RedirectPolicy::allow_downgrade`].
+//!
+//! A `307` or `308` response to a request whose body is longer than
+//! [`RedirectPolicy::max_replay_body`] is returned as is.
+//!
+//! The final URI and the redirects are recorded in the [`RedirectHistory`] of the response, a
+//! redirect which can't be followed fails with a [`RedirectError`].
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::redirect::{RedirectHistory, RedirectLayer, RedirectPolicy};
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(RedirectLayer::new(RedirectPolicy::new().max_redirects(5)))
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! let history = response.extensions().get::<RedirectHistory>();
+//! # Ok(())
+//! # }
+//! ```
+use http::header::HeaderValue;
+use http::{StatusCode, Uri};
+
+/// Which redirects to follow.
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+    #[cfg_attr(not(feature = "client-hyper"), allow(dead_code))]
+    follow: bool,
+    max_redirects: usize,
+    allow_downgrade: bool,
+    /// The limit of [`ReplayBody::read`](crate::client::replay::ReplayBody::read).
+    max_replay_body: usize,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            follow: true,
+            max_redirects: 10,
+            allow_downgrade: false,
+            max_replay_body: 64 * 1024,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Don't follow the redirects, the responses are returned as is.
+    pub fn none() -> Self {
+        Self {
+            follow: false,
+            ..Self::default()
+        }
+    }
+    /// The maximum number of hops, `10` by default, more fail with
+    /// [`RedirectError::TooManyRedirects`].
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+    /// Follow the redirects from `https` to `http`, refused by default.
+    pub fn allow_downgrade(mut self, allow: bool) -> Self {
+        self.allow_downgrade = allow;
+        self
+    }
+    /// The longest request body which is buffered to be sent again, 64KiB by default.
+    pub fn max_replay_body(mut self, max: usize) -> Self {
+        self.max_replay_body = max;
+        self
+    }
+}
+
+/// A response which redirected the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectHop {
+    /// The requested uri.
+    pub uri: Uri,
+    pub status: StatusCode,
+}
+
+/// The redirects followed before the response, inserted in its extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectHistory {
+    /// The uri of the final request.
+    pub uri: Uri,
+    /// The redirects, in the order they were followed.
+    pub hops: Vec<RedirectHop>,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum RedirectError {
+    #[error("too many redirects, the maximum is {max}")]
+    TooManyRedirects { max: usize, hops: Vec<RedirectHop> },
+    #[error("refused redirect to {to}, https isn't downgraded to http")]
+    Downgrade { to: Uri },
+    #[error("invalid redirect location {location:?}")]
+    InvalidLocation { location: HeaderValue },
+}
+
+/// Resolve a `Location` header against the uri of the request, as a reference of RFC 3986.
+///
+/// The fragment is dropped, since it isn't sent.
+pub fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    let location = location.split('#').next().unwrap_or_default();
+    let scheme = base.scheme_str().unwrap_or("http");
+    let authority = base.authority()?.as_str();
+    if location.starts_with("//") {
+        return format!("{scheme}:{location}").parse().ok();
+    }
+    if let Ok(uri) = location.parse::<Uri>() {
+        if uri.scheme().is_some() {
+            return matches!(uri.scheme_str(), Some("http" | "https"))
+                .then_some(uri)
+                .filter(|uri| uri.host().is_some());
+        }
+    }
+    let base_path = base.path();
+    let (path, query) = match location.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (location, None),
+    };
+    let path = if path.is_empty() {
+        base_path.to_owned()
+    } else if path.starts_with('/') {
+        remove_dot_segments(path)
+    } else {
+        let directory = &base_path[..base_path.rfind('/').map_or(0, |i| i + 1)];
+        remove_dot_segments(&format!("{directory}{path}"))
+    };
+    let query = match query {
+        Some(query) => Some(query),
+        None if location.is_empty() => base.query(),
+        None => None,
+    };
+    let path_and_query = match query {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    };
+    format!("{scheme}://{authority}{path_and_query}")
+        .parse()
+        .ok()
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+    while let Some(segment) = parts.next() {
+        let last = parts.peek().is_none();
+        match segment {
+            "." if last => segments.push(""),
+            "." => {}
+            ".." => {
+                segments.pop();
+                if last {
+                    segments.push("");
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub use self::layer::*;
+
+#[cfg(feature = "client-hyper")]
+mod layer {
+    use std::fmt;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use bytes::Bytes;
+    use http::header::{
+        HeaderName, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_LENGTH,
+        CONTENT_LOCATION, CONTENT_TYPE, COOKIE, HOST, LOCATION, PROXY_AUTHORIZATION,
+        TRANSFER_ENCODING,
+    };
+    use http::uri::Scheme;
+    use http::{HeaderMap, Method, Request, Response};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::client::replay::{self, attempt, ReplayBody};
+    use crate::client::ClientBody;
+    use crate::error::BoxError;
+
+    /// The method of the next hop, `None` when the body is sent again.
+    fn rewrite_method(status: StatusCode, method: &Method) -> Option<Method> {
+        match status {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if *method == Method::POST => {
+                Some(Method::GET)
+            }
+            StatusCode::SEE_OTHER if *method != Method::HEAD => Some(Method::GET),
+            _ => None,
+        }
+    }
+
+    fn is_redirect(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT
+        )
+    }
+
+    /// Drop the credentials of the request, before sending it to another origin.
+    fn remove_credentials(headers: &mut HeaderMap) {
+        let sensitive: Vec<HeaderName> = headers
+            .iter()
+            .filter(|(_, value)| value.is_sensitive())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, HOST]
+            .into_iter()
+            .chain(sensitive)
+        {
+            headers.remove(name);
+        }
+    }
+
+    fn remove_content_headers(headers: &mut HeaderMap) {
+        for name in [
+            CONTENT_TYPE,
+            CONTENT_LENGTH,
+            CONTENT_ENCODING,
+            CONTENT_LANGUAGE,
+            CONTENT_LOCATION,
+            TRANSFER_ENCODING,
+        ] {
+            headers.remove(name);
+        }
+    }
+
+    fn is_downgrade(from: &Uri, to: &Uri) -> bool {
+        from.scheme() == Some(&Scheme::HTTPS) && to.scheme() == Some(&Scheme::HTTP)
+    }
+
+    /// Layer for [`Redirect`].
+    #[derive(Debug, Clone, Default)]
+    pub struct RedirectLayer {
+        policy: RedirectPolicy,
+    }
+
+    impl RedirectLayer {
+        pub fn new(policy: RedirectPolicy) -> Self {
+            Self { policy }
+        }
+    }
+
+    impl<S> tower::Layer<S> for RedirectLayer {
+        type Service = Redirect<S>;
+        fn layer(&self, inner: S) -> Self::Service {
+            Redirect {
+                inner,
+                policy: self.policy.clone(),
+            }
+        }
+    }
+
+    /// Follow the redirects, see [`crate::client::redirect`].
+    #[derive(Clone)]
+    pub struct Redirect<S> {
+        inner: S,
+        policy: RedirectPolicy,
+    }
+
+    impl<S: fmt::Debug> fmt::Debug for Redirect<S> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Redirect")
+                .field("inner", &self.inner)
+                .field("policy", &self.policy)
+                .finish()
+        }
+    }
+
+    impl<S, B, RB> tower_service::Service<Request<B>> for Redirect<S>
+    where
+        S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>
+            + Clone
+            + Send
+            + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+        B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<BoxError>,
+        RB: Send + 'static,
+    {
+        type Response = Response<RB>;
+        type Error = BoxError;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx).map_err(Into::into)
+        }
+
+        fn call(&mut self, request: Request<B>) -> Self::Future {
+            let mut service = replay::take_ready(&mut self.inner);
+            let policy = self.policy.clone();
+            Box::pin(async move {
+                let (mut parts, body) = request.into_parts();
+                let body = body.map_err(Into::into).boxed();
+                let mut body = if policy.follow {
+                    ReplayBody::read(body, policy.max_replay_body).await?
+                } else {
+                    ReplayBody::Streaming(body)
+                };
+                let mut hops = Vec::new();
+                loop {
+                    let request = replay::request(&parts, body.next());
+                    let mut response = if hops.is_empty() {
+                        attempt(service.call(request)).await?
+                    } else {
+                        attempt(service.clone().oneshot(request)).await?
+                    };
+                    let status = response.status();
+                    let location = match response.headers().get(LOCATION) {
+                        Some(location) if policy.follow && is_redirect(status) => location,
+                        _ => {
+                            response.extensions_mut().insert(RedirectHistory {
+                                uri: parts.uri,
+                                hops,
+                            });
+                            return Ok(response);
+                        }
+                    };
+                    let next = location
+                        .to_str()
+                        .ok()
+                        .and_then(|location| resolve(&parts.uri, location))
+                        .ok_or_else(|| RedirectError::InvalidLocation {
+                            location: location.clone(),
+                        })?;
+                    if hops.len() >= policy.max_redirects {
+                        return Err(RedirectError::TooManyRedirects {
+                            max: policy.max_redirects,
+                            hops,
+                        }
+                        .into());
+                    }
+                    if !policy.allow_downgrade && is_downgrade(&parts.uri, &next) {
+                        return Err(RedirectError::Downgrade { to: next }.into());
+                    }
+                    match rewrite_method(status, &parts.method) {
+                        Some(method) => {
+                            parts.method = method;
+                            body = ReplayBody::empty();
+                            remove_content_headers(&mut parts.headers);
+                        }
+                        None if !body.replayable() => {
+                            response.extensions_mut().insert(RedirectHistory {
+                                uri: parts.uri,
+                                hops,
+                            });
+                            return Ok(response);
+                        }
+                        None => {}
+                    }
+                    if !crate::util::same_origin(&parts.uri, &next) {
+                        remove_credentials(&mut parts.headers);
+                    }
+                    drop(response);
+                    let uri = std::mem::replace(&mut parts.uri, next);
+                    hops.push(RedirectHop { uri, status });
+                }
+            })
+        }
+    }
+}
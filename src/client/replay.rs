@@ -0,0 +1,120 @@
+//! Replay of a request, for the layers sending it more than once.
+use std::future::Future;
+use std::pin::Pin;
+
+use bytes::{Bytes, BytesMut};
+use http::request::Parts;
+use http::{HeaderMap, Request};
+use http_body::Body;
+use http_body_util::{BodyExt, BodyStream, Full, StreamBody};
+
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+/// A request body, read ahead to be replayed.
+///
+/// The body is read before the first attempt, up to the `max_replay_body` of the layer: a body
+/// which fits is buffered and sent again with each attempt. The frames of a longer body are sent
+/// first and the rest of it is streamed, so the request can't be sent again.
+pub(crate) enum ReplayBody {
+    Buffered {
+        data: Bytes,
+        trailers: Option<HeaderMap>,
+    },
+    /// Too long to be buffered, the frames already read are sent first.
+    Streaming(ClientBody),
+}
+
+impl ReplayBody {
+    pub(crate) async fn read(mut body: ClientBody, max: usize) -> Result<Self, BoxError> {
+        if body.size_hint().lower() > max as u64 {
+            return Ok(ReplayBody::Streaming(body));
+        }
+        let mut data = BytesMut::new();
+        loop {
+            let Some(frame) = body.frame().await.transpose()? else {
+                return Ok(ReplayBody::Buffered {
+                    data: data.freeze(),
+                    trailers: None,
+                });
+            };
+            let frame = match frame.into_data() {
+                Ok(chunk) => {
+                    data.extend_from_slice(&chunk);
+                    if data.len() <= max {
+                        continue;
+                    }
+                    http_body::Frame::data(data.freeze())
+                }
+                Err(frame) => match frame.into_trailers() {
+                    Ok(trailers) if body.is_end_stream() => {
+                        return Ok(ReplayBody::Buffered {
+                            data: data.freeze(),
+                            trailers: Some(trailers),
+                        })
+                    }
+                    Ok(trailers) => http_body::Frame::trailers(trailers),
+                    Err(_) => continue,
+                },
+            };
+            let read = futures_util::stream::iter([Ok(frame)]);
+            let rest = BodyStream::new(body);
+            return Ok(ReplayBody::Streaming(BodyExt::boxed(StreamBody::new(
+                futures_util::StreamExt::chain(read, rest),
+            ))));
+        }
+    }
+    pub(crate) fn empty() -> Self {
+        ReplayBody::Buffered {
+            data: Bytes::new(),
+            trailers: None,
+        }
+    }
+    pub(crate) fn replayable(&self) -> bool {
+        matches!(self, ReplayBody::Buffered { .. })
+    }
+    /// A body for the next attempt, a streaming body is only sent once.
+    pub(crate) fn next(&mut self) -> ClientBody {
+        match self {
+            ReplayBody::Buffered { data, trailers } => {
+                let body = Full::new(data.clone()).map_err(|never| match never {});
+                match trailers.clone() {
+                    Some(trailers) => {
+                        BodyExt::boxed(body.with_trailers(async move { Some(Ok(trailers)) }))
+                    }
+                    None => BodyExt::boxed(body),
+                }
+            }
+            ReplayBody::Streaming(body) => std::mem::take(body),
+        }
+    }
+}
+
+/// Take the service polled ready for the first attempt, leaving a clone in its place to be polled
+/// for the next request.
+pub(crate) fn take_ready<S: Clone>(inner: &mut S) -> S {
+    let clone = inner.clone();
+    std::mem::replace(inner, clone)
+}
+
+pub(crate) type Attempt<R> = Pin<Box<dyn Future<Output = Result<R, BoxError>> + Send>>;
+
+// boxing the attempt names its type, the async block can't prove it `Send` otherwise
+pub(crate) fn attempt<F, R, E>(future: F) -> Attempt<R>
+where
+    F: Future<Output = Result<R, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    Box::pin(async move { future.await.map_err(Into::into) })
+}
+
+/// A copy of the request `parts` with `body`.
+pub(crate) fn request(parts: &Parts, body: ClientBody) -> Request<ClientBody> {
+    let mut request = Request::new(body);
+    *request.method_mut() = parts.method.clone();
+    *request.uri_mut() = parts.uri.clone();
+    *request.version_mut() = parts.version;
+    *request.headers_mut() = parts.headers.clone();
+    *request.extensions_mut() = parts.extensions.clone();
+    request
+}
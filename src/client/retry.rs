@@ -0,0 +1,299 @@
+//! Retry of the failed requests, with exponential backoff.
+//!
+//! The [`RetryLayer`] retries:
+//! - the requests which failed to connect, whatever their method, since nothing was sent.
+//! - the requests which timed out, see [`crate::client::timeout`], and the responses whose status
+//!   is retryable (`429` and `503` by default), when the request is idempotent: its method is
+//!   idempotent, or it has the [`Idempotent`] extension.
+//!
+//! The delay before a retry grows exponentially with jitter, a `Retry-After` header of the
+//! response takes precedence: when it's longer than [`RetryPolicy::max_delay`], the response is
+//! returned without retrying. The retries stop when the deadline of the budget would be exceeded.
+//!
+//! A request whose body is longer than [`RetryPolicy::max_replay_body`] isn't retried.
+//!
+//! Put the layer outside of the [`TimeoutLayer`](crate::client::timeout::TimeoutLayer), so the
+//! timeouts apply to each attempt:
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use std::time::Duration;
+//! use client_util::client::retry::{RetryLayer, RetryPolicy};
+//! use client_util::client::timeout::{TimeoutLayer, Timeouts};
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(RetryLayer::new(RetryPolicy::new().max_retries(5)))
+//!     .layer(TimeoutLayer::new(Timeouts::new().response(Duration::from_secs(10))))
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use http_body_util::BodyExt;
+use hyper::rt::Timer;
+use tower::ServiceExt;
+
+use crate::client::hyper::is_connect_error;
+use crate::client::replay::{self, attempt, ReplayBody};
+use crate::client::rt::SharedTimer;
+use crate::client::timeout::TimeoutError;
+use crate::client::ClientBody;
+use crate::error::BoxError;
+
+/// Marks a request as idempotent, so it's retried whatever its method.
+///
+/// For example a `POST` request with an idempotency key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Idempotent;
+
+/// The number of retries before the response, inserted in its extensions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retries(pub u32);
+
+/// When and how often to retry.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    budget: Option<Duration>,
+    statuses: Vec<StatusCode>,
+    /// The limit of [`ReplayBody::read`].
+    max_replay_body: usize,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            budget: None,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::SERVICE_UNAVAILABLE,
+            ],
+            max_replay_body: 64 * 1024,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The maximum number of retries, `3` by default.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+    /// The delay before the first retry, doubled for each retry, `100ms` by default.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+    /// The maximum delay between two attempts, `10s` by default.
+    ///
+    /// A response whose `Retry-After` is longer isn't retried.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+    /// Pick a random delay up to the exponential delay, enabled by default.
+    pub fn jitter(mut self, enable: bool) -> Self {
+        self.jitter = enable;
+        self
+    }
+    /// Don't retry when the deadline, counted from the first attempt, would be exceeded.
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+    /// The response statuses which are retried, `429` and `503` by default.
+    pub fn statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+    /// The longest request body which is buffered to be replayed, 64KiB by default.
+    pub fn max_replay_body(mut self, max: usize) -> Self {
+        self.max_replay_body = max;
+        self
+    }
+    /// The delay before the retry `retry`, starting at `0`.
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << retry.min(31))
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(fastrand::f64())
+        } else {
+            delay
+        }
+    }
+}
+
+/// Whether a method is idempotent, as defined by RFC 9110.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Parse a `Retry-After` header, delay-seconds or an http date.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    crate::util::retry_after(headers)
+}
+
+/// Layer for [`Retry`].
+#[derive(Debug, Clone)]
+pub struct RetryLayer {
+    policy: RetryPolicy,
+    timer: SharedTimer,
+}
+
+impl RetryLayer {
+    /// Retry with `policy`, waiting with the tokio timer.
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            timer: SharedTimer::tokio(),
+        }
+    }
+    /// Set the timer of the backoff between the attempts, for another runtime than tokio.
+    pub fn timer<T>(mut self, timer: T) -> Self
+    where
+        T: Timer + Send + Sync + 'static,
+    {
+        self.timer = SharedTimer::new(timer);
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry {
+            inner,
+            policy: self.policy.clone(),
+            timer: self.timer.clone(),
+        }
+    }
+}
+
+/// Retry the failed requests, see [`crate::client::retry`].
+#[derive(Clone)]
+pub struct Retry<S> {
+    inner: S,
+    policy: RetryPolicy,
+    timer: SharedTimer,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Retry<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+enum Outcome {
+    Done,
+    Retry { after: Option<Duration> },
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Retry<S>
+where
+    S: tower_service::Service<Request<ClientBody>, Response = Response<RB>>
+        + Clone
+        + Send
+        + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let mut service = replay::take_ready(&mut self.inner);
+        let policy = self.policy.clone();
+        let timer = self.timer.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let (parts, body) = request.into_parts();
+            let idempotent =
+                is_idempotent(&parts.method) || parts.extensions.get::<Idempotent>().is_some();
+            let mut body =
+                ReplayBody::read(body.map_err(Into::into).boxed(), policy.max_replay_body).await?;
+            let mut retries = 0;
+            loop {
+                let request = replay::request(&parts, body.next());
+                let result = if retries == 0 {
+                    attempt(service.call(request)).await
+                } else {
+                    attempt(service.clone().oneshot(request)).await
+                };
+                let outcome = match &result {
+                    Err(error) if is_connect_error(&**error) => Outcome::Retry { after: None },
+                    Err(error) if idempotent && TimeoutError::find(&**error).is_some() => {
+                        Outcome::Retry { after: None }
+                    }
+                    Ok(response) if idempotent && policy.statuses.contains(&response.status()) => {
+                        Outcome::Retry {
+                            after: retry_after(response.headers()),
+                        }
+                    }
+                    _ => Outcome::Done,
+                };
+                let delay = match outcome {
+                    Outcome::Retry { after }
+                        if retries < policy.max_retries && body.replayable() =>
+                    {
+                        match after {
+                            // retrying sooner than the server asked wouldn't succeed
+                            Some(after) if after > policy.max_delay => None,
+                            Some(after) => Some(after),
+                            None => Some(policy.backoff(retries)),
+                        }
+                    }
+                    _ => None,
+                };
+                let elapsed = start.elapsed();
+                let delay = delay.filter(|delay| {
+                    policy
+                        .budget
+                        .is_none_or(|budget| elapsed + *delay <= budget)
+                });
+                let Some(delay) = delay else {
+                    return result.map(|mut response| {
+                        response.extensions_mut().insert(Retries(retries));
+                        response
+                    });
+                };
+                drop(result);
+                timer.sleep(delay).await;
+                retries += 1;
+            }
+        })
+    }
+}
@@ -0,0 +1,284 @@
+//! Async runtime support for the hyper clients.
+//!
+//! The hyper clients need an executor to drive their connections, and optionally a timer for the
+//! pool and protocol timeouts. [`HyperClientBuilder`](crate::client::HyperClientBuilder) uses tokio
+//! by default, set [`HyperClientBuilder::executor`](crate::client::HyperClientBuilder::executor) and
+//! [`HyperClientBuilder::timer`](crate::client::HyperClientBuilder::timer) to use another runtime,
+//! and a connector for that runtime with
+//! [`HyperClientBuilder::build_with_connector`](crate::client::HyperClientBuilder::build_with_connector).
+//!
+//! With the `runtime-smol` feature, the smol executor, timer and connector are provided.
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::rt::{Executor, Sleep, Timer};
+
+/// The futures spawned by the hyper clients.
+pub type BoxSendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A type erased executor.
+#[derive(Clone)]
+pub struct SharedExecutor {
+    inner: Arc<dyn Executor<BoxSendFuture> + Send + Sync>,
+}
+
+impl fmt::Debug for SharedExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedExecutor").finish_non_exhaustive()
+    }
+}
+
+impl SharedExecutor {
+    pub fn new<E>(executor: E) -> Self
+    where
+        E: Executor<BoxSendFuture> + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(executor),
+        }
+    }
+    /// An executor which spawns with `spawn`, e.g. `SharedExecutor::from_fn(|future| { my_runtime::spawn(future); })`.
+    pub fn from_fn<F>(spawn: F) -> Self
+    where
+        F: Fn(BoxSendFuture) + Send + Sync + 'static,
+    {
+        struct FnExecutor<F>(F);
+        impl<F> Executor<BoxSendFuture> for FnExecutor<F>
+        where
+            F: Fn(BoxSendFuture),
+        {
+            fn execute(&self, future: BoxSendFuture) {
+                (self.0)(future)
+            }
+        }
+        Self::new(FnExecutor(spawn))
+    }
+    /// Spawn on the current tokio runtime.
+    pub fn tokio() -> Self {
+        Self::new(hyper_util::rt::TokioExecutor::new())
+    }
+}
+
+impl Executor<BoxSendFuture> for SharedExecutor {
+    fn execute(&self, future: BoxSendFuture) {
+        self.inner.execute(future)
+    }
+}
+
+/// A type erased timer.
+#[derive(Clone)]
+pub struct SharedTimer {
+    inner: Arc<dyn Timer + Send + Sync>,
+}
+
+impl fmt::Debug for SharedTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedTimer").finish_non_exhaustive()
+    }
+}
+
+impl SharedTimer {
+    pub fn new<T>(timer: T) -> Self
+    where
+        T: Timer + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(timer),
+        }
+    }
+    /// The tokio timer, it requires a tokio runtime with the time driver enabled.
+    pub fn tokio() -> Self {
+        Self::new(hyper_util::rt::TokioTimer::new())
+    }
+}
+
+impl Timer for SharedTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Sleep>> {
+        self.inner.sleep(duration)
+    }
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Sleep>> {
+        self.inner.sleep_until(deadline)
+    }
+    fn now(&self) -> Instant {
+        self.inner.now()
+    }
+    fn reset(&self, sleep: &mut Pin<Box<dyn Sleep>>, new_deadline: Instant) {
+        self.inner.reset(sleep, new_deadline)
+    }
+}
+
+#[cfg(feature = "runtime-smol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "runtime-smol")))]
+pub use self::smol::*;
+
+#[cfg(feature = "runtime-smol")]
+mod smol {
+    use std::future::Future;
+    use std::io;
+    use std::net::{SocketAddr, ToSocketAddrs};
+    use std::pin::Pin;
+    use std::str::FromStr;
+    use std::task::{Context, Poll};
+
+    use http::Uri;
+    use hyper::rt::Executor;
+    use hyper_util::client::legacy::connect::{Connected, Connection};
+    use smol_hyper::rt::FuturesIo;
+    use tower_service::Service;
+
+    use super::BoxSendFuture;
+    use crate::client::dns::{Addrs, DnsResolver, Name, Resolve, Resolving};
+    use crate::client::metadata::{record_transport, ConnectionMetadata};
+
+    pub use smol_hyper::rt::SmolTimer;
+
+    /// Spawn on the global smol executor.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct SmolExecutor;
+
+    impl Executor<BoxSendFuture> for SmolExecutor {
+        fn execute(&self, future: BoxSendFuture) {
+            ::smol::spawn(future).detach();
+        }
+    }
+
+    /// The system resolver (`getaddrinfo`), run on the smol blocking thread pool.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct SmolResolver;
+
+    impl Resolve for SmolResolver {
+        fn resolve(&self, name: Name) -> Resolving {
+            Box::pin(async move {
+                let addrs = ::smol::unblock(move || (name.as_str(), 0).to_socket_addrs()).await?;
+                Ok(Box::new(addrs) as Addrs)
+            })
+        }
+    }
+
+    pin_project_lite::pin_project! {
+        /// A tcp stream of the smol runtime.
+        #[derive(Debug)]
+        pub struct SmolTcpStream {
+            #[pin]
+            inner: FuturesIo<::smol::net::TcpStream>,
+        }
+    }
+
+    impl Connection for SmolTcpStream {
+        fn connected(&self) -> Connected {
+            Connected::new()
+        }
+    }
+
+    impl ConnectionMetadata for SmolTcpStream {
+        fn addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+            let stream = self.inner.get_ref();
+            Some((stream.peer_addr().ok()?, stream.local_addr().ok()?))
+        }
+    }
+
+    impl hyper::rt::Read for SmolTcpStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: hyper::rt::ReadBufCursor<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.project().inner.poll_read(cx, buf)
+        }
+    }
+
+    impl hyper::rt::Write for SmolTcpStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.project().inner.poll_write(cx, buf)
+        }
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_flush(cx)
+        }
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_shutdown(cx)
+        }
+    }
+
+    /// A tcp connector for the smol runtime, resolving hosts with a [`DnsResolver`].
+    #[derive(Debug, Clone)]
+    pub struct SmolConnector {
+        resolver: DnsResolver,
+    }
+
+    impl Default for SmolConnector {
+        fn default() -> Self {
+            Self::new(DnsResolver::new(SmolResolver))
+        }
+    }
+
+    impl SmolConnector {
+        pub fn new(resolver: DnsResolver) -> Self {
+            Self { resolver }
+        }
+    }
+
+    impl Service<Uri> for SmolConnector {
+        type Response = SmolTcpStream;
+        type Error = io::Error;
+        type Future = Pin<Box<dyn Future<Output = io::Result<SmolTcpStream>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, uri: Uri) -> Self::Future {
+            let mut resolver = self.resolver.clone();
+            Box::pin(async move {
+                let host = uri
+                    .host()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing host"))?;
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                let default_port = if uri.scheme() == Some(&http::uri::Scheme::HTTPS) {
+                    443
+                } else {
+                    80
+                };
+                let addrs: Vec<SocketAddr> = match host.parse() {
+                    Ok(ip) => vec![SocketAddr::new(ip, 0)],
+                    Err(_) => {
+                        let name = Name::from_str(host)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                        resolver
+                            .call(name)
+                            .await
+                            .map_err(io::Error::other)?
+                            .collect()
+                    }
+                };
+                let mut last_error = None;
+                for mut addr in addrs {
+                    match uri.port_u16() {
+                        Some(port) => addr.set_port(port),
+                        None if addr.port() == 0 => addr.set_port(default_port),
+                        None => {}
+                    }
+                    match ::smol::net::TcpStream::connect(addr).await {
+                        Ok(stream) => {
+                            record_transport();
+                            stream.set_nodelay(true)?;
+                            return Ok(SmolTcpStream {
+                                inner: FuturesIo::new(stream),
+                            });
+                        }
+                        Err(error) => last_error = Some(error),
+                    }
+                }
+                Err(last_error.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no address to connect to")
+                }))
+            })
+        }
+    }
+}
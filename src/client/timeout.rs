@@ -0,0 +1,294 @@
+//! Timeouts of the requests, by phase.
+//!
+//! - the connect timeout bounds the dns resolution, the tcp connection and the TLS handshake, it's
+//!   set on the client with [`HyperClientBuilder::connect_timeout`](crate::client::HyperClientBuilder::connect_timeout),
+//!   since a connection isn't opened for one request.
+//! - the response timeout bounds the time to the response head.
+//! - the read timeout bounds the time between two frames of the response body.
+//! - the total timeout bounds the whole exchange, including the download of the body.
+//!
+//! The [`TimeoutLayer`] enforces the other timeouts, the defaults of the layer are overridden by
+//! the [`Timeouts`] in the request extensions, see [`RequestBuilder::timeout`](crate::request::RequestBuilder::timeout).
+//!
+//! A timeout is reported as a [`TimeoutError`], check it with [`Error::is_timeout`](crate::Error::is_timeout).
+use std::fmt;
+use std::time::Duration;
+
+/// The timeouts of a request, inserted in its extensions.
+///
+/// The timeouts which are `None` fall back to the defaults of the [`TimeoutLayer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    pub response: Option<Duration>,
+    pub read: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl Timeouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Bound the time to the response head.
+    pub fn response(mut self, timeout: Duration) -> Self {
+        self.response = Some(timeout);
+        self
+    }
+    /// Bound the time between two frames of the response body.
+    pub fn read(mut self, timeout: Duration) -> Self {
+        self.read = Some(timeout);
+        self
+    }
+    /// Bound the whole exchange, including the download of the body.
+    pub fn total(mut self, timeout: Duration) -> Self {
+        self.total = Some(timeout);
+        self
+    }
+    /// These timeouts, falling back to `defaults`.
+    pub fn or(self, defaults: Timeouts) -> Self {
+        Self {
+            response: self.response.or(defaults.response),
+            read: self.read.or(defaults.read),
+            total: self.total.or(defaults.total),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutPhase {
+    Connect,
+    Response,
+    Read,
+    Total,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimeoutPhase::Connect => "connect",
+            TimeoutPhase::Response => "response",
+            TimeoutPhase::Read => "read",
+            TimeoutPhase::Total => "total",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("{phase} timeout of {timeout:?} elapsed")]
+pub struct TimeoutError {
+    pub phase: TimeoutPhase,
+    pub timeout: Duration,
+}
+
+impl TimeoutError {
+    pub fn new(phase: TimeoutPhase, timeout: Duration) -> Self {
+        Self { phase, timeout }
+    }
+    /// Find a timeout error in the chain of sources of `error`.
+    pub fn find(error: &(dyn std::error::Error + 'static)) -> Option<TimeoutError> {
+        let mut error = Some(error);
+        while let Some(current) = error {
+            if let Some(timeout) = current.downcast_ref::<TimeoutError>() {
+                return Some(*timeout);
+            }
+            error = current.source();
+        }
+        None
+    }
+}
+
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub use self::layer::*;
+
+#[cfg(feature = "client-hyper")]
+mod layer {
+    use std::fmt;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+    use std::time::Duration;
+
+    use futures_util::future::{select, Either};
+    use http::{Request, Response};
+    use hyper::rt::{Sleep, Timer};
+
+    use super::{TimeoutError, TimeoutPhase, Timeouts};
+    use crate::client::rt::SharedTimer;
+    use crate::error::BoxError;
+
+    /// Layer for [`Timeout`].
+    #[derive(Debug, Clone)]
+    pub struct TimeoutLayer {
+        defaults: Timeouts,
+        timer: SharedTimer,
+    }
+
+    impl TimeoutLayer {
+        /// Enforce the timeouts of the requests, falling back to `defaults`, with the tokio timer.
+        pub fn new(defaults: Timeouts) -> Self {
+            Self {
+                defaults,
+                timer: SharedTimer::tokio(),
+            }
+        }
+        /// Set the timer, for another runtime than tokio.
+        pub fn timer<T>(mut self, timer: T) -> Self
+        where
+            T: Timer + Send + Sync + 'static,
+        {
+            self.timer = SharedTimer::new(timer);
+            self
+        }
+    }
+
+    impl<S> tower::Layer<S> for TimeoutLayer {
+        type Service = Timeout<S>;
+        fn layer(&self, inner: S) -> Self::Service {
+            Timeout {
+                inner,
+                defaults: self.defaults,
+                timer: self.timer.clone(),
+            }
+        }
+    }
+
+    /// Enforce the response, read and total timeouts of the requests.
+    #[derive(Debug, Clone)]
+    pub struct Timeout<S> {
+        inner: S,
+        defaults: Timeouts,
+        timer: SharedTimer,
+    }
+
+    impl<S, B, RB> tower_service::Service<Request<B>> for Timeout<S>
+    where
+        S: tower_service::Service<Request<B>, Response = Response<RB>>,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        type Response = Response<TimeoutBody<RB>>;
+        type Error = BoxError;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx).map_err(Into::into)
+        }
+
+        fn call(&mut self, request: Request<B>) -> Self::Future {
+            let timeouts = request
+                .extensions()
+                .get::<Timeouts>()
+                .copied()
+                .unwrap_or_default()
+                .or(self.defaults);
+            let timer = self.timer.clone();
+            let start = timer.now();
+            let total = timeouts.total.map(|timeout| (start + timeout, timeout));
+            let head = [
+                timeouts
+                    .response
+                    .map(|timeout| (start + timeout, TimeoutPhase::Response, timeout)),
+                total.map(|(deadline, timeout)| (deadline, TimeoutPhase::Total, timeout)),
+            ]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(deadline, ..)| *deadline);
+            let responding = self.inner.call(request);
+            Box::pin(async move {
+                let response = match head {
+                    Some((deadline, phase, timeout)) => {
+                        match select(Box::pin(responding), timer.sleep_until(deadline)).await {
+                            Either::Left((response, _)) => response.map_err(Into::into)?,
+                            Either::Right(_) => {
+                                return Err(TimeoutError::new(phase, timeout).into())
+                            }
+                        }
+                    }
+                    None => responding.await.map_err(Into::into)?,
+                };
+                Ok(response.map(|inner| TimeoutBody {
+                    inner,
+                    read: timeouts.read.map(|timeout| (None, timeout)),
+                    total: total.map(|(deadline, timeout)| (timer.sleep_until(deadline), timeout)),
+                    timer,
+                }))
+            })
+        }
+    }
+
+    pin_project_lite::pin_project! {
+        /// A response body enforcing the read and total timeouts.
+        pub struct TimeoutBody<B> {
+            #[pin]
+            inner: B,
+            read: Option<(Option<Pin<Box<dyn Sleep>>>, Duration)>,
+            total: Option<(Pin<Box<dyn Sleep>>, Duration)>,
+            timer: SharedTimer,
+        }
+    }
+
+    impl<B> fmt::Debug for TimeoutBody<B> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TimeoutBody")
+                .field("read", &self.read.as_ref().map(|(_, timeout)| timeout))
+                .field("total", &self.total.as_ref().map(|(_, timeout)| timeout))
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl<B> http_body::Body for TimeoutBody<B>
+    where
+        B: http_body::Body,
+        B::Error: Into<BoxError>,
+    {
+        type Data = B::Data;
+        type Error = BoxError;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+            let this = self.project();
+            if let Some((sleep, timeout)) = this.total {
+                if sleep.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Some(Err(TimeoutError::new(
+                        TimeoutPhase::Total,
+                        *timeout,
+                    )
+                    .into())));
+                }
+            }
+            match this.inner.poll_frame(cx) {
+                Poll::Ready(frame) => {
+                    if let Some((sleep, timeout)) = this.read {
+                        // the next read starts now
+                        let deadline = this.timer.now() + *timeout;
+                        match sleep {
+                            Some(sleep) => this.timer.reset(sleep, deadline),
+                            None => *sleep = Some(this.timer.sleep_until(deadline)),
+                        }
+                    }
+                    Poll::Ready(frame.map(|frame| frame.map_err(Into::into)))
+                }
+                Poll::Pending => {
+                    if let Some((sleep, timeout)) = this.read {
+                        let sleep = sleep.get_or_insert_with(|| this.timer.sleep(*timeout));
+                        ready!(sleep.as_mut().poll(cx));
+                        return Poll::Ready(Some(Err(TimeoutError::new(
+                            TimeoutPhase::Read,
+                            *timeout,
+                        )
+                        .into())));
+                    }
+                    Poll::Pending
+                }
+            }
+        }
+        fn is_end_stream(&self) -> bool {
+            self.inner.is_end_stream()
+        }
+        fn size_hint(&self) -> http_body::SizeHint {
+            self.inner.size_hint()
+        }
+    }
+}
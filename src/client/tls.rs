@@ -0,0 +1,127 @@
+//! Backend independent TLS configuration.
+//!
+//! Those types are accepted by [`HyperClientBuilder`](crate::client::HyperClientBuilder), and are
+//! converted for the enabled TLS backend (rustls or native-tls) when the client is built.
+use std::fmt;
+
+#[derive(Clone, PartialEq, Eq)]
+enum Encoded {
+    Der(Vec<u8>),
+    Pem(Vec<u8>),
+}
+
+/// A trusted root certificate.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Certificate {
+    encoded: Encoded,
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoding = match self.encoded {
+            Encoded::Der(_) => "der",
+            Encoded::Pem(_) => "pem",
+        };
+        f.debug_struct("Certificate")
+            .field("encoding", &encoding)
+            .finish()
+    }
+}
+
+impl Certificate {
+    /// A DER encoded X.509 certificate.
+    pub fn from_der(der: impl Into<Vec<u8>>) -> Self {
+        Self {
+            encoded: Encoded::Der(der.into()),
+        }
+    }
+    /// A PEM encoded X.509 certificate.
+    pub fn from_pem(pem: impl Into<Vec<u8>>) -> Self {
+        Self {
+            encoded: Encoded::Pem(pem.into()),
+        }
+    }
+    #[cfg(feature = "client-hyper-rustls")]
+    pub(crate) fn to_rustls(
+        &self,
+    ) -> std::io::Result<Vec<rustls::pki_types::CertificateDer<'static>>> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::CertificateDer;
+        match &self.encoded {
+            Encoded::Der(der) => Ok(vec![CertificateDer::from(der.clone())]),
+            Encoded::Pem(pem) => CertificateDer::pem_slice_iter(pem)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(std::io::Error::other),
+        }
+    }
+    #[cfg(feature = "client-hyper-native-tls")]
+    pub(crate) fn to_native_tls(&self) -> std::io::Result<native_tls::Certificate> {
+        match &self.encoded {
+            Encoded::Der(der) => native_tls::Certificate::from_der(der),
+            Encoded::Pem(pem) => native_tls::Certificate::from_pem(pem),
+        }
+        .map_err(std::io::Error::other)
+    }
+}
+
+/// A client identity, for mutual TLS.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Identity {
+    cert_chain_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity").finish_non_exhaustive()
+    }
+}
+
+impl Identity {
+    /// A PEM encoded certificate chain, starting with the client certificate, and a PEM encoded PKCS #8 private key.
+    pub fn from_pem(cert_chain_pem: impl Into<Vec<u8>>, key_pem: impl Into<Vec<u8>>) -> Self {
+        Self {
+            cert_chain_pem: cert_chain_pem.into(),
+            key_pem: key_pem.into(),
+        }
+    }
+    #[cfg(feature = "client-hyper-rustls")]
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn to_rustls(
+        &self,
+    ) -> std::io::Result<(
+        Vec<rustls::pki_types::CertificateDer<'static>>,
+        rustls::pki_types::PrivateKeyDer<'static>,
+    )> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        let chain = CertificateDer::pem_slice_iter(&self.cert_chain_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(std::io::Error::other)?;
+        let key = PrivateKeyDer::from_pem_slice(&self.key_pem).map_err(std::io::Error::other)?;
+        Ok((chain, key))
+    }
+    #[cfg(feature = "client-hyper-native-tls")]
+    pub(crate) fn to_native_tls(&self) -> std::io::Result<native_tls::Identity> {
+        native_tls::Identity::from_pkcs8(&self.cert_chain_pem, &self.key_pem)
+            .map_err(std::io::Error::other)
+    }
+}
+
+/// The TLS settings of a client.
+#[derive(Debug, Clone)]
+pub(crate) struct TlsOptions {
+    pub(crate) built_in_roots: bool,
+    pub(crate) roots: Vec<Certificate>,
+    pub(crate) identity: Option<Identity>,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            built_in_roots: true,
+            roots: Vec::new(),
+            identity: None,
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Tracing of the requests, with the OpenTelemetry conventions.
+//!
+//! The [`TraceLayer`] sends each request in a client span whose fields follow the OpenTelemetry
+//! semantic conventions for HTTP clients:
+//! - `http.request.method`, `url.full`, `server.address`, `server.port` and
+//!   `http.request.body.size` when the request is sent.
+//! - `http.response.status_code`, `http.response.body.size`, `network.protocol.version`,
+//!   `network.peer.address` and `network.peer.port` when the response is received, the body sizes
+//!   are known from the `Content-Length` header or the exact size of the body.
+//! - `error.type` and `otel.status_code` when the request failed or the status is `4xx` or
+//!   `5xx`. An error is also recorded as an event of the span.
+//!
+//! The user info of `url.full` is redacted as in [`crate::redact`], and so are the values of the
+//! secret query parameters, see [`TraceLayer::redact_query_param`].
+//!
+//! When the spans are exported with [`tracing_opentelemetry`], the context of the span is injected
+//! in the W3C `traceparent` and `tracestate` headers of the request, so the trace continues on
+//! the server.
+//!
+//! ```no_run
+//! # async fn example() -> client_util::Result<()> {
+//! use client_util::client::trace::TraceLayer;
+//! use client_util::prelude::*;
+//! let client = tower::ServiceBuilder::new()
+//!     .layer(TraceLayer::new().redact_query_param("session"))
+//!     .service(build_https_client().expect("failed to build client"));
+//! let response = RequestBuilder::get("https://example.com/")?.empty().send(client).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{HeaderName, HeaderValue, CONTENT_LENGTH};
+use http::{HeaderMap, Request, Response, Version};
+use opentelemetry::trace::TraceContextExt;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::client::hyper::error_kind;
+use crate::client::metadata::ConnectionInfo;
+use crate::client::replay::attempt;
+use crate::error::BoxError;
+use crate::redact::RedactedUrl;
+
+/// The query parameters redacted by default, the credentials and the signatures of presigned urls.
+const REDACTED_QUERY_PARAMS: [&str; 15] = [
+    "access_token",
+    "api_key",
+    "apikey",
+    "AWSAccessKeyId",
+    "client_secret",
+    "code",
+    "key",
+    "password",
+    "secret",
+    "sig",
+    "Signature",
+    "token",
+    "X-Amz-Credential",
+    "X-Amz-Signature",
+    "X-Goog-Signature",
+];
+
+static TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+static TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+
+/// Layer for [`Trace`].
+#[derive(Debug, Clone)]
+pub struct TraceLayer {
+    redacted: Arc<Vec<String>>,
+}
+
+impl Default for TraceLayer {
+    fn default() -> Self {
+        Self {
+            redacted: Arc::new(REDACTED_QUERY_PARAMS.map(str::to_owned).to_vec()),
+        }
+    }
+}
+
+impl TraceLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Redact the value of the query parameter `name` in `url.full`, compared case insensitively.
+    ///
+    /// The `token`, `key`, `secret` and `password` parameters and the like are redacted by
+    /// default.
+    pub fn redact_query_param(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.redacted).push(name.into());
+        self
+    }
+}
+
+impl<S> tower::Layer<S> for TraceLayer {
+    type Service = Trace<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        Trace {
+            inner,
+            redacted: self.redacted.clone(),
+        }
+    }
+}
+
+/// Send the requests in a span, see [`crate::client::trace`].
+#[derive(Debug, Clone)]
+pub struct Trace<S> {
+    inner: S,
+    redacted: Arc<Vec<String>>,
+}
+
+/// The size of a body, from its `Content-Length` header or its exact size.
+fn body_size<B: http_body::Body>(headers: &HeaderMap, body: &B) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse().ok())
+        .or_else(|| body.size_hint().exact())
+}
+
+fn protocol_version(version: Version) -> Option<&'static str> {
+    match version {
+        Version::HTTP_09 => Some("0.9"),
+        Version::HTTP_10 => Some("1.0"),
+        Version::HTTP_11 => Some("1.1"),
+        Version::HTTP_2 => Some("2"),
+        Version::HTTP_3 => Some("3"),
+        _ => None,
+    }
+}
+
+/// Inject the context of `span` in the W3C trace context headers.
+fn inject(span: &Span, headers: &mut HeaderMap) {
+    let context = span.context();
+    let span = context.span();
+    let span_context = span.span_context();
+    if !span_context.is_valid() {
+        return;
+    }
+    let traceparent = format!(
+        "00-{}-{}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags().to_u8()
+    );
+    if let Ok(traceparent) = HeaderValue::from_str(&traceparent) {
+        headers.insert(&TRACEPARENT, traceparent);
+    }
+    headers.remove(&TRACESTATE);
+    let tracestate = span_context.trace_state().header();
+    if let Ok(tracestate) = HeaderValue::from_str(&tracestate) {
+        if !tracestate.is_empty() {
+            headers.insert(&TRACESTATE, tracestate);
+        }
+    }
+}
+
+fn record_response<B: http_body::Body>(span: &Span, response: &Response<B>) {
+    let status = response.status();
+    span.record("http.response.status_code", status.as_u16());
+    if let Some(size) = body_size(response.headers(), response.body()) {
+        span.record("http.response.body.size", size);
+    }
+    if let Some(version) = protocol_version(response.version()) {
+        span.record("network.protocol.version", version);
+    }
+    let peer = response
+        .extensions()
+        .get::<ConnectionInfo>()
+        .and_then(|connection| connection.remote_addr);
+    if let Some(peer) = peer {
+        span.record("network.peer.address", peer.ip().to_string());
+        span.record("network.peer.port", peer.port());
+    }
+    if status.is_client_error() || status.is_server_error() {
+        span.record("error.type", status.as_str());
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+fn record_error(span: &Span, error: &BoxError) {
+    span.record("error.type", error_kind(&**error));
+    span.record("otel.status_code", "ERROR");
+    tracing::error!(error = %error, "request failed");
+}
+
+impl<S, B, RB> tower_service::Service<Request<B>> for Trace<S>
+where
+    S: tower_service::Service<Request<B>, Response = Response<RB>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    B: http_body::Body,
+    RB: http_body::Body + Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let uri = request.uri();
+        let method = request.method().as_str();
+        let port = uri.port_u16().or(match uri.scheme_str() {
+            Some("https") => Some(443),
+            Some("http") => Some(80),
+            _ => None,
+        });
+        let span = tracing::info_span!(
+            "HTTP",
+            otel.name = method,
+            otel.kind = "client",
+            otel.status_code = Empty,
+            http.request.method = method,
+            url.full = %RedactedUrl::new(uri, &self.redacted),
+            server.address = uri.host(),
+            server.port = port,
+            http.request.body.size = body_size(request.headers(), request.body()),
+            http.response.status_code = Empty,
+            http.response.body.size = Empty,
+            network.protocol.version = Empty,
+            network.peer.address = Empty,
+            network.peer.port = Empty,
+            error.type = Empty,
+        );
+        inject(&span, request.headers_mut());
+        let response = span.in_scope(|| attempt(self.inner.call(request)));
+        let recorded = span.clone();
+        Box::pin(
+            async move {
+                let result = response.await;
+                match &result {
+                    Ok(response) => record_response(&recorded, response),
+                    Err(error) => record_error(&recorded, error),
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
@@ -0,0 +1,131 @@
+//! Unix domain socket transport.
+//!
+//! The socket path is carried in the authority of the request uri, hex encoded, under the `unix` scheme:
+//! `unix://<hex encoded socket path>/path?query`. Use [`unix_uri`] to build such an uri.
+//!
+//! ```rust,no_run
+//! use client_util::prelude::*;
+//! # async fn docker() -> client_util::Result<()> {
+//! let mut client = build_unix_client();
+//! let containers = RequestBuilder::get(unix_uri("/var/run/docker.sock", "/containers/json")?)?
+//!     .empty()
+//!     .send(&mut client)
+//!     .await?
+//!     .json::<serde_json::Value>()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::future::Future;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::uri::{Authority, PathAndQuery, Scheme};
+use http::Uri;
+use hyper_util::client::legacy::Client as HyperClient;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::net::UnixStream;
+
+use crate::request::BuildRequestError;
+
+/// The uri scheme used for unix domain socket requests.
+pub const UNIX_SCHEME: &str = "unix";
+
+pub type HyperUnixClient<B> = HyperClient<UnixConnector, B>;
+
+/// A connector that opens a [`UnixStream`] for every connection.
+///
+/// By default the socket path is decoded from the request uri (see [`unix_uri`]),
+/// use [`UnixConnector::with_socket`] to always connect to the same socket.
+#[derive(Debug, Clone, Default)]
+pub struct UnixConnector {
+    socket: Option<Arc<Path>>,
+}
+
+impl UnixConnector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Always connect to `socket`, whatever the request uri is.
+    ///
+    /// In this mode, plain `http://localhost/...` uris can be used.
+    pub fn with_socket(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: Some(Arc::from(socket.into())),
+        }
+    }
+}
+
+impl tower_service::Service<Uri> for UnixConnector {
+    type Response = TokioIo<UnixStream>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let socket = match &self.socket {
+            Some(socket) => Ok(socket.to_path_buf()),
+            None => socket_path(&uri),
+        };
+        Box::pin(async move {
+            let stream = UnixStream::connect(socket?).await?;
+            Ok(TokioIo::new(stream))
+        })
+    }
+}
+
+/// Build an uri which points to `path_and_query` served on the unix socket `socket`.
+pub fn unix_uri(
+    socket: impl AsRef<Path>,
+    path_and_query: impl AsRef<str>,
+) -> Result<Uri, BuildRequestError> {
+    let socket = socket.as_ref().as_os_str().as_bytes();
+    let mut host = String::with_capacity(socket.len() * 2);
+    for byte in socket {
+        host.push_str(&format!("{byte:02x}"));
+    }
+    let mut parts = http::uri::Parts::default();
+    parts.scheme = Some(Scheme::try_from(UNIX_SCHEME)?);
+    parts.authority = Some(Authority::try_from(host)?);
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query.as_ref())?);
+    Ok(Uri::from_parts(parts).map_err(http::Error::from)?)
+}
+
+/// Decode the socket path from an uri built by [`unix_uri`].
+pub fn socket_path(uri: &Uri) -> io::Result<PathBuf> {
+    let invalid = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid unix socket uri {uri}: {reason}"),
+        )
+    };
+    if uri.scheme_str() != Some(UNIX_SCHEME) {
+        return Err(invalid("scheme should be `unix`"));
+    }
+    let host = uri.host().ok_or_else(|| invalid("missing socket path"))?;
+    if host.len() % 2 != 0 {
+        return Err(invalid("socket path is not hex encoded"));
+    }
+    let bytes = (0..host.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&host[index..index + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid("socket path is not hex encoded"))?;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+/// Build a client which sends requests over unix domain sockets.
+pub fn build_unix_client<B>() -> HyperUnixClient<B>
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+{
+    HyperClient::builder(TokioExecutor::default()).build(UnixConnector::new())
+}
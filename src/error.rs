@@ -1,3 +1,7 @@
+#[cfg(feature = "circuit-breaker")]
+use crate::client::circuit_breaker::CircuitOpenError;
+use crate::client::redirect::RedirectError;
+use crate::client::timeout::TimeoutError;
 use crate::request::BuildRequestError;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -16,4 +20,43 @@ pub enum Error {
     Response(#[from] crate::response::ResponseError),
     #[error("raw http error: {0}")]
     Http(#[from] http::Error),
+    #[error("timeout: {0}")]
+    Timeout(#[from] TimeoutError),
+    #[error("redirect error: {0}")]
+    Redirect(#[from] RedirectError),
+    #[cfg(feature = "circuit-breaker")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "circuit-breaker")))]
+    #[error("{0}")]
+    CircuitOpen(#[from] CircuitOpenError),
+}
+
+impl Error {
+    /// An error of the client, a [`TimeoutError`] in its sources becomes [`Error::Timeout`], a
+    /// [`RedirectError`] becomes [`Error::Redirect`] and a `CircuitOpenError` in its sources
+    /// becomes `Error::CircuitOpen`.
+    pub(crate) fn send_request(error: BoxError) -> Self {
+        if let Some(timeout) = TimeoutError::find(&*error) {
+            return Error::Timeout(timeout);
+        }
+        let error = match error.downcast::<RedirectError>() {
+            Ok(redirect) => return Error::Redirect(*redirect),
+            Err(error) => error,
+        };
+        #[cfg(feature = "circuit-breaker")]
+        if let Some(open) = CircuitOpenError::find(&*error) {
+            return Error::CircuitOpen(open);
+        }
+        Error::SendRequest(error)
+    }
+    /// Whether a timeout elapsed, while waiting for the response or reading its body.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout(_)) || TimeoutError::find(self).is_some()
+    }
+    /// Whether the request was rejected by an open circuit, see [`crate::client::circuit_breaker`],
+    /// also when the rejection is the source of the error of an outer layer.
+    #[cfg(feature = "circuit-breaker")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "circuit-breaker")))]
+    pub fn is_circuit_open(&self) -> bool {
+        matches!(self, Error::CircuitOpen(_)) || CircuitOpenError::find(self).is_some()
+    }
 }
@@ -4,7 +4,7 @@
     clippy::all,
     clippy::dbg_macro,
     clippy::todo,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::enum_glob_use,
     clippy::mem_forget,
     clippy::unused_self,
@@ -30,9 +30,13 @@
     future_incompatible,
     nonstandard_style
 )]
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub mod blocking;
 pub mod body;
 pub mod client;
 pub mod error;
+pub mod redact;
 pub mod request;
 pub mod response;
 
@@ -40,6 +44,9 @@ pub mod response;
 mod util;
 
 pub use body::{empty, full, Body};
+#[cfg(feature = "client-hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client-hyper")))]
+pub use client::global::get;
 pub use error::{Error, Result};
 
 // re-export
@@ -0,0 +1,125 @@
+//! Redaction of the secrets in the `Debug` output of the requests and in the logs.
+//!
+//! The values of the headers marked sensitive, like the ones of `RequestBuilder::basic_auth` and
+//! `RequestBuilder::bearer_auth`, and of the headers of [`DENIED_HEADERS`] are printed as
+//! `REDACTED`, so is the user info of the uris.
+use std::fmt;
+
+use http::header::{HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE};
+use http::{HeaderMap, Uri};
+
+pub(crate) const REDACTED: &str = "REDACTED";
+
+/// The headers always redacted, the credentials and the cookies.
+pub const DENIED_HEADERS: [HeaderName; 6] = [
+    AUTHORIZATION,
+    PROXY_AUTHORIZATION,
+    COOKIE,
+    SET_COOKIE,
+    HeaderName::from_static("x-api-key"),
+    HeaderName::from_static("x-auth-token"),
+];
+
+/// Print the headers without their secrets.
+#[derive(Clone, Copy)]
+pub struct RedactedHeaders<'a> {
+    headers: &'a HeaderMap,
+    denied: &'a [HeaderName],
+}
+
+impl<'a> RedactedHeaders<'a> {
+    pub fn new(headers: &'a HeaderMap) -> Self {
+        Self {
+            headers,
+            denied: &[],
+        }
+    }
+    /// Also redact the values of the `denied` headers.
+    pub fn deny(mut self, denied: &'a [HeaderName]) -> Self {
+        self.denied = denied;
+        self
+    }
+    fn is_redacted(&self, name: &HeaderName) -> bool {
+        DENIED_HEADERS.contains(name) || self.denied.contains(name)
+    }
+}
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.headers {
+            if value.is_sensitive() || self.is_redacted(name) {
+                map.entry(name, &REDACTED);
+            } else {
+                map.entry(name, value);
+            }
+        }
+        map.finish()
+    }
+}
+
+/// Print an uri without its user info.
+#[derive(Clone, Copy)]
+pub struct RedactedUri<'a>(pub &'a Uri);
+
+impl fmt::Display for RedactedUri<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&RedactedUrl::new(self.0, &[]), f)
+    }
+}
+
+impl fmt::Debug for RedactedUri<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Print an uri without its user info, nor the values of some query parameters.
+pub(crate) struct RedactedUrl<'a> {
+    uri: &'a Uri,
+    params: &'a [String],
+}
+
+impl<'a> RedactedUrl<'a> {
+    /// Also redact the values of the query parameters `params`, compared case insensitively.
+    pub(crate) fn new(uri: &'a Uri, params: &'a [String]) -> Self {
+        Self { uri, params }
+    }
+}
+
+impl fmt::Display for RedactedUrl<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uri = self.uri;
+        if let Some(scheme) = uri.scheme_str() {
+            write!(f, "{scheme}://")?;
+        }
+        if let Some(authority) = uri.authority() {
+            match authority.as_str().rsplit_once('@') {
+                Some((_, host)) => write!(f, "{REDACTED}@{host}")?,
+                None => write!(f, "{authority}")?,
+            }
+        }
+        f.write_str(uri.path())?;
+        let Some(query) = uri.query() else {
+            return Ok(());
+        };
+        f.write_str("?")?;
+        for (index, pair) in query.split('&').enumerate() {
+            if index > 0 {
+                f.write_str("&")?;
+            }
+            match pair.split_once('=') {
+                Some((name, _))
+                    if self
+                        .params
+                        .iter()
+                        .any(|param| param.eq_ignore_ascii_case(name)) =>
+                {
+                    write!(f, "{name}={REDACTED}")?;
+                }
+                _ => f.write_str(pair)?,
+            }
+        }
+        Ok(())
+    }
+}
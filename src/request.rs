@@ -3,11 +3,13 @@
 mod multipart;
 use bytes::Bytes;
 use futures_util::TryFutureExt;
+#[cfg(any(feature = "json", feature = "form", feature = "multipart"))]
+use http::header::CONTENT_TYPE;
 use http::uri::PathAndQuery;
 use http::HeaderValue;
 use http::Request;
 use http::Response;
-use http::{header::CONTENT_TYPE, Uri};
+use http::Uri;
 use http_body_util::combinators::BoxBody;
 use http_body_util::{Empty, Full};
 #[cfg(feature = "multipart")]
@@ -19,9 +21,12 @@ use std::future::Future;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::str::FromStr;
+use std::time::Duration;
 
 use crate::body::{empty, full};
-use crate::client::ClientBody;
+use crate::client::timeout::Timeouts;
+use crate::client::{ClientBody, IntoClient};
+use crate::redact::{RedactedHeaders, RedactedUri};
 
 #[derive(Debug, thiserror::Error)]
 pub enum BuildRequestError {
@@ -45,6 +50,8 @@ pub enum BuildRequestError {
     BuildQuery(#[from] BuildQueryError),
     #[error("invalid uri: {0}")]
     InvalidUri(#[from] http::uri::InvalidUri),
+    #[error("base uri must have a scheme and an authority: {0}")]
+    RelativeBaseUri(Uri),
     #[error("invalid header value: {0}")]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
     #[error("failed to build request: {0}")]
@@ -108,6 +115,18 @@ pub struct RequestBuilder {
     parts: http::request::Parts,
 }
 
+/// Print the request without its secrets, see [`crate::redact`].
+impl std::fmt::Debug for RequestBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestBuilder")
+            .field("method", &self.parts.method)
+            .field("uri", &RedactedUri(&self.parts.uri))
+            .field("version", &self.parts.version)
+            .field("headers", &RedactedHeaders::new(&self.parts.headers))
+            .finish_non_exhaustive()
+    }
+}
+
 impl Deref for RequestBuilder {
     type Target = http::request::Parts;
     fn deref(&self) -> &Self::Target {
@@ -169,6 +188,55 @@ impl RequestBuilder {
         self.parts.version = version;
         self
     }
+    /// Set the timeouts of this request, see [`crate::client::timeout`].
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.parts.extensions.insert(timeouts);
+        self
+    }
+    /// Bound the whole exchange, including the download of the body, see [`crate::client::timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        let timeouts = self.timeouts_or_default().total(timeout);
+        self.timeouts(timeouts)
+    }
+    /// Bound the time between two frames of the response body, see [`crate::client::timeout`].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        let timeouts = self.timeouts_or_default().read(timeout);
+        self.timeouts(timeouts)
+    }
+    /// Retry this request whatever its method, see [`crate::client::retry`].
+    #[cfg(feature = "retry")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "retry")))]
+    pub fn idempotent(mut self) -> Self {
+        self.parts
+            .extensions
+            .insert(crate::client::retry::Idempotent);
+        self
+    }
+    /// Hedge this request or not, whatever its method, see [`crate::client::hedge`].
+    #[cfg(feature = "hedge")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hedge")))]
+    pub fn hedge(mut self, enable: bool) -> Self {
+        self.parts
+            .extensions
+            .insert(crate::client::hedge::Hedgeable(enable));
+        self
+    }
+    /// The key of the endpoint of this request, see [`crate::client::balance`].
+    #[cfg(feature = "balance")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "balance")))]
+    pub fn balance_key(mut self, key: impl Into<String>) -> Self {
+        self.parts
+            .extensions
+            .insert(crate::client::balance::BalanceKey(key.into()));
+        self
+    }
+    fn timeouts_or_default(&mut self) -> Timeouts {
+        self.parts
+            .extensions
+            .get::<Timeouts>()
+            .copied()
+            .unwrap_or_default()
+    }
     pub fn body<B>(self, body: B) -> Result<Request<B>, BuildRequestError>
     where
         B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
@@ -322,10 +390,11 @@ pub trait RequestExt<B>: Sized {
         self.with_header(http::header::AUTHORIZATION, header_value)
     }
 
-    fn send<S, R>(self, client: S) -> impl Future<Output = crate::Result<S::Response>> + Send
+    fn send<C, S, R>(self, client: C) -> impl Future<Output = crate::Result<S::Response>> + Send
     where
         B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
         B::Error: Into<crate::error::BoxError>,
+        C: IntoClient<S>,
         S: tower_service::Service<Request<ClientBody>, Response = Response<R>> + Send + Sync,
         R: http_body::Body + Send + Sync + 'static,
         <S as tower_service::Service<Request<ClientBody>>>::Error: Into<crate::error::BoxError>,
@@ -379,24 +448,28 @@ where
         self
     }
 
-    /// Send the request to a service.
+    /// Send the request to a service, once the service is ready.
+    ///
+    /// The client can be a service, a `&mut` service, or a `&` reference to a cloneable service.
     ///
     /// If you enabled any decompression feature, the response body will be automatically decompressed.
-    #[allow(unused_mut)]
-    fn send<S, R>(self, mut client: S) -> impl Future<Output = crate::Result<S::Response>> + Send
+    fn send<C, S, R>(self, client: C) -> impl Future<Output = crate::Result<S::Response>> + Send
     where
         B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
         B::Error: Into<crate::error::BoxError>,
+        C: IntoClient<S>,
         S: tower_service::Service<Request<ClientBody>, Response = Response<R>> + Send + Sync,
         R: http_body::Body + Send + Sync + 'static,
         <S as tower_service::Service<Request<ClientBody>>>::Error: Into<crate::error::BoxError>,
         <S as tower_service::Service<Request<ClientBody>>>::Future: Send,
     {
         use http_body_util::BodyExt;
+        use tower::ServiceExt;
         let request = self.map(|b| BoxBody::new(b.map_err(|e| e.into())));
         client
-            .call(request)
-            .map_err(|e| crate::Error::SendRequest(e.into()))
+            .into_client()
+            .oneshot(request)
+            .map_err(|e| crate::Error::send_request(e.into()))
     }
 }
 
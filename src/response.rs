@@ -50,6 +50,12 @@ pub enum ResponseError {
         charset: String,
     },
 }
+impl ResponseError {
+    fn collect_body<E: Into<crate::error::BoxError>>(error: E) -> Self {
+        ResponseError::CollectBody(error.into())
+    }
+}
+
 /// A collection of text decoders.
 #[derive(Debug, Default, Clone)]
 pub struct Decoders {
@@ -64,11 +70,13 @@ impl Decoders {
     }
 }
 
+/// The error of the body converts into a [`BoxError`](crate::error::BoxError), so it must be
+/// `Send + Sync`, like the error of [`ClientBody`](crate::client::ClientBody).
 impl<B> ResponseExt<B> for Response<B>
 where
     B: http_body::Body + Send,
     B::Data: Send,
-    B::Error: std::error::Error + Send + 'static,
+    B::Error: Into<crate::error::BoxError>,
 {
     /// Deserialize the response body as json.
     #[cfg(feature = "json")]
@@ -79,7 +87,7 @@ where
         let body = body
             .collect()
             .await
-            .map_err(|e| ResponseError::CollectBody(Box::new(e)))?
+            .map_err(ResponseError::collect_body)?
             .aggregate();
         let body = serde_json::from_reader::<_, T>(body.reader())
             .map_err(ResponseError::JsonDeserialize)?;
@@ -97,7 +105,7 @@ where
         let body = body
             .collect()
             .await
-            .map_err(|e| ResponseError::CollectBody(Box::new(e)))?
+            .map_err(ResponseError::collect_body)?
             .to_bytes();
         let mut string_body: Option<String> = None;
         'decode: {
@@ -163,7 +171,7 @@ where
         let body = body
             .collect()
             .await
-            .map_err(|error| ResponseError::CollectBody(Box::new(error)))?
+            .map_err(ResponseError::collect_body)?
             .to_bytes();
         Ok(Response::from_parts(parts, body))
     }
@@ -176,7 +184,7 @@ where
         let body = body
             .collect()
             .await
-            .map_err(|error| ResponseError::CollectBody(Box::new(error)))?
+            .map_err(ResponseError::collect_body)?
             .aggregate();
         Ok(Response::from_parts(parts, body))
     }
@@ -189,6 +197,6 @@ where
     async fn hyper_upgrade(self) -> Result<hyper::upgrade::Upgraded, ResponseError> {
         hyper::upgrade::on(self)
             .await
-            .map_err(|error| ResponseError::CollectBody(Box::new(error)))
+            .map_err(ResponseError::collect_body)
     }
 }
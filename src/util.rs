@@ -50,6 +50,72 @@ where
     header
 }
 
+/// Parse a `Retry-After` header, delay-seconds or an http date.
+#[cfg(any(feature = "retry", feature = "rate-limit"))]
+pub(crate) fn retry_after(headers: &http::HeaderMap) -> Option<std::time::Duration> {
+    use std::time::{Duration, SystemTime};
+    let value = headers
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// The uri of a request on the `base` uri, the base path prefixes the path of the request.
+///
+/// An uri without a scheme, like `users?page=2`, is relative to the base path.
+pub(crate) fn rebase(
+    base: &http::Uri,
+    uri: &http::Uri,
+) -> Result<http::Uri, crate::request::BuildPathError> {
+    let relative = match uri.scheme() {
+        Some(_) => uri
+            .path_and_query()
+            .map_or("/", |path| path.as_str())
+            .to_owned(),
+        None => uri.to_string(),
+    };
+    let prefix = base.path().trim_end_matches('/');
+    let path_and_query = if relative.is_empty() || relative.starts_with(['/', '?']) {
+        format!("{prefix}{relative}")
+    } else {
+        format!("{prefix}/{relative}")
+    };
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(http::uri::PathAndQuery::from_maybe_shared(path_and_query)?);
+    Ok(http::Uri::from_parts(parts)?)
+}
+
+/// Whether two uris have the same scheme, host and port.
+pub(crate) fn same_origin(a: &http::Uri, b: &http::Uri) -> bool {
+    fn port(uri: &http::Uri) -> Option<u16> {
+        uri.port_u16().or(match uri.scheme_str() {
+            Some("https") => Some(443),
+            Some("http") => Some(80),
+            _ => None,
+        })
+    }
+    a.scheme() == b.scheme()
+        && a.host().map(str::to_ascii_lowercase) == b.host().map(str::to_ascii_lowercase)
+        && port(a) == port(b)
+}
+
+/// FNV-1a of `bytes`, stable across builds and runs unlike the std hasher.
+#[cfg(any(feature = "cache", feature = "balance"))]
+pub(crate) fn fnv1a<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
 #[cfg(feature = "multipart")]
 #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
 pub(crate) fn fast_random() -> u64 {
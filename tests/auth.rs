@@ -0,0 +1,240 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use client_util::client::auth::{
+    AuthLayer, Authenticator, Challenge, Credential, HostCredentials, Netrc, RefreshingToken,
+};
+use client_util::prelude::*;
+use http::{HeaderMap, HeaderName, StatusCode, Uri};
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds with the `Authorization` and `X-Api-Key` headers and the query of the request, and
+/// challenges the requests without the `Bearer fresh` token on `/protected`, echoing their body.
+fn server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_owned())
+                .unwrap_or_default()
+        };
+        let authorization = header("authorization");
+        let mut response = http::Response::builder();
+        let body = if req.uri().path() == "/protected" {
+            if authorization != "Bearer fresh" {
+                response = response.status(401).header(
+                    "www-authenticate",
+                    r#"Bearer realm="api", error="invalid_token""#,
+                );
+            }
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            format!("{authorization} {}", String::from_utf8_lossy(&body))
+        } else {
+            format!(
+                "{authorization}|{}|{}",
+                header("x-api-key"),
+                req.uri().query().unwrap_or_default()
+            )
+        };
+        response
+            .body(
+                http_body_util::Full::new(body.into())
+                    .map_err(Into::into)
+                    .boxed(),
+            )
+            .unwrap()
+    })
+}
+
+async fn send(
+    layer: AuthLayer,
+    request: http::Request<http_body_util::Full<::hyper::body::Bytes>>,
+) -> client_util::Result<(StatusCode, String)> {
+    let client = ServiceBuilder::new()
+        .layer(layer)
+        .service(build_http_client());
+    let response = request.send(client).await?;
+    let status = response.status();
+    Ok((status, response.text().await?.into_body()))
+}
+
+#[tokio::test]
+async fn applies_static_credentials() -> client_util::Result<()> {
+    let server = server();
+    let uri = format!("http://{}/?page=2", server.addr());
+    let get = || RequestBuilder::get(&uri).map(|builder| builder.plain_text(""));
+
+    let basic = AuthLayer::new(Credential::basic("user", Some("pass")));
+    let response = send(basic, get()?).await?;
+    assert_eq!(response.1, "Basic dXNlcjpwYXNz||page=2");
+
+    let api_key = Credential::header(HeaderName::from_static("x-api-key"), "secret");
+    let response = send(AuthLayer::new(api_key), get()?).await?;
+    assert_eq!(response.1, "|secret|page=2");
+
+    let query = AuthLayer::new(Credential::query("api key", "s&cret"));
+    let response = send(query, get()?).await?;
+    assert_eq!(response.1, "||page=2&api%20key=s%26cret");
+
+    // the header of the request is kept
+    let bearer = AuthLayer::new(Credential::bearer("token"));
+    let request = RequestBuilder::get(&uri)?
+        .header("authorization", "Bearer mine")?
+        .plain_text("");
+    let response = send(bearer, request).await?;
+    assert_eq!(response.1, "Bearer mine||page=2");
+    Ok(())
+}
+
+#[tokio::test]
+async fn picks_the_credential_of_the_host() -> client_util::Result<()> {
+    let server = server();
+    let port = server.addr().port();
+    let credentials = HostCredentials::new()
+        .host("LOCALHOST", Credential::bearer("host"))
+        .host(format!("localhost:{port}"), Credential::bearer("port"));
+    let uri = format!("http://localhost:{port}/");
+    let response = send(
+        AuthLayer::new(credentials.clone()),
+        RequestBuilder::get(&uri)?.plain_text(""),
+    )
+    .await?;
+    assert_eq!(response.1, "Bearer port||");
+
+    let uri: Uri = "http://localhost:1/".parse().unwrap();
+    assert_eq!(
+        credentials.credential(&uri),
+        Some(Credential::bearer("host"))
+    );
+    let uri: Uri = "http://example.com/".parse().unwrap();
+    assert_eq!(credentials.credential(&uri), None);
+    Ok(())
+}
+
+#[test]
+fn reads_netrc() {
+    let netrc = Netrc::parse(
+        "machine api.example.com login user password pass\n\
+         macdef init\ncd /\n\n\
+         default login anonymous password guest\n",
+    );
+    let credential = |uri: &str| netrc.credential(&uri.parse().unwrap());
+    assert_eq!(
+        credential("https://API.example.com/"),
+        Some(Credential::basic("user", Some("pass")))
+    );
+    assert_eq!(
+        credential("https://example.com/"),
+        Some(Credential::basic("anonymous", Some("guest")))
+    );
+    assert_eq!(
+        Netrc::parse("").credential(&"https://example.com/".parse().unwrap()),
+        None
+    );
+}
+
+#[test]
+fn parses_challenges() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        "www-authenticate",
+        r#"Basic realm="a \"quoted\", realm", Bearer error=invalid_token, scope="read write""#
+            .parse()
+            .unwrap(),
+    );
+    headers.append("www-authenticate", "Negotiate abc==".parse().unwrap());
+    headers.append(
+        "www-authenticate",
+        r#"Negotiate abc, Digest realm="d""#.parse().unwrap(),
+    );
+    let challenges = Challenge::parse(&headers);
+    let schemes: Vec<_> = challenges.iter().map(|c| c.scheme.as_str()).collect();
+    assert_eq!(
+        schemes,
+        ["Basic", "Bearer", "Negotiate", "Negotiate", "Digest"]
+    );
+    assert_eq!(challenges[4].param("realm"), Some("d"));
+    assert_eq!(challenges[0].param("REALM"), Some(r#"a "quoted", realm"#));
+    assert_eq!(challenges[1].param("error"), Some("invalid_token"));
+    assert_eq!(challenges[1].param("scope"), Some("read write"));
+}
+
+#[tokio::test]
+async fn replays_with_a_refreshed_credential() -> client_util::Result<()> {
+    let server = server();
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let token = RefreshingToken::new(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        async { Ok("fresh".to_owned()) }
+    })
+    .token("stale");
+    let layer = AuthLayer::new(token);
+    let uri = format!("http://{}/protected", server.addr());
+    let response = send(
+        layer.clone(),
+        RequestBuilder::post(&uri)?.plain_text("body"),
+    )
+    .await?;
+    assert_eq!(response, (StatusCode::OK, "Bearer fresh body".into()));
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // the refreshed token is used next
+    let response = send(
+        layer.clone(),
+        RequestBuilder::post(&uri)?.plain_text("body"),
+    )
+    .await?;
+    assert_eq!(response, (StatusCode::OK, "Bearer fresh body".into()));
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // a body too long to be replayed isn't sent again
+    let token = RefreshingToken::new(|| async { Ok("fresh".to_owned()) }).token("stale");
+    let layer = AuthLayer::new(token).max_replay_body(2);
+    let response = send(layer, RequestBuilder::post(&uri)?.plain_text("body")).await?;
+    assert_eq!(
+        response,
+        (StatusCode::UNAUTHORIZED, "Bearer stale body".into())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn refreshes_a_rejected_token_once() -> std::result::Result<(), client_util::error::BoxError>
+{
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let token = RefreshingToken::new(move || {
+        let fetch = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            Ok(format!("fresh{fetch}"))
+        }
+    })
+    .token("stale");
+    let uri: Uri = "http://example.com/".parse().unwrap();
+    let challenges = || {
+        vec![Challenge {
+            scheme: "Bearer".into(),
+            params: Vec::new(),
+        }]
+    };
+    let refresh =
+        |rejected: &str| token.refresh(&uri, Some(Credential::bearer(rejected)), challenges());
+    let fresh = Some(Credential::bearer("fresh0"));
+
+    // the requests rejected together share the fetch
+    let (first, second) = tokio::join!(refresh("stale"), refresh("stale"));
+    assert_eq!((first?, second?), (fresh.clone(), fresh.clone()));
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // a token already replaced isn't fetched again
+    assert_eq!(refresh("stale").await?, fresh);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    assert_eq!(refresh("fresh0").await?, Some(Credential::bearer("fresh1")));
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use client_util::client::balance::{Balance, BalanceLayer, BalanceStrategy, Balancer, Endpoint};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds with its name, the path and query, and the body, after `delay`.
+fn server(name: &'static str, delay: Duration) -> support::server::Server {
+    support::server::http(move |req| async move {
+        tokio::time::sleep(delay).await;
+        let uri = req.uri().clone();
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        let body = format!("{name} {uri} {}", String::from_utf8_lossy(&body));
+        http::Response::builder()
+            .body(
+                http_body_util::Full::new(body.into())
+                    .map_err(Into::into)
+                    .boxed(),
+            )
+            .unwrap()
+    })
+}
+
+fn base(server: &support::server::Server) -> http::Uri {
+    format!("http://{}", server.addr()).parse().unwrap()
+}
+
+type Client = Balance<client_util::client::HyperHttpClient<ClientBody>>;
+
+fn client(balancer: Balancer) -> Client {
+    ServiceBuilder::new()
+        .layer(BalanceLayer::new(balancer))
+        .service(build_http_client())
+}
+
+async fn get(client: &Client, key: Option<&str>) -> client_util::Result<(http::Uri, String)> {
+    let mut builder = RequestBuilder::get("/")?
+        .path("/items")?
+        .query(&[("page", 2)])?;
+    if let Some(key) = key {
+        builder = builder.balance_key(key);
+    }
+    let response = builder.empty().send(client.clone()).await?;
+    let endpoint = response.extensions().get::<Endpoint>().unwrap().0.clone();
+    Ok((endpoint, response.text().await?.into_body()))
+}
+
+#[tokio::test]
+async fn round_robin() -> client_util::Result<()> {
+    let servers = [
+        server("a", Duration::ZERO),
+        server("b", Duration::ZERO),
+        server("c", Duration::ZERO),
+    ];
+    let client = client(Balancer::new(servers.iter().map(base)).unwrap());
+    let mut names = Vec::new();
+    for _ in 0..6 {
+        let (endpoint, body) = get(&client, None).await?;
+        let (name, rest) = body.split_once(' ').unwrap();
+        assert_eq!(rest, "/items?page=2 ");
+        assert_eq!(endpoint, base(&servers[names.len() % 3]));
+        names.push(name.to_owned());
+    }
+    assert_eq!(names, ["a", "b", "c", "a", "b", "c"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn least_outstanding() -> client_util::Result<()> {
+    let slow = server("slow", Duration::from_millis(300));
+    let fast = server("fast", Duration::ZERO);
+    let client = client(
+        Balancer::new([base(&slow), base(&fast)])
+            .unwrap()
+            .strategy(BalanceStrategy::LeastOutstanding),
+    );
+    let pending = tokio::spawn({
+        let client = client.clone();
+        async move { get(&client, None).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    for _ in 0..3 {
+        assert_eq!(get(&client, None).await?.0, base(&fast));
+    }
+    assert_eq!(pending.await.unwrap()?.0, base(&slow));
+    Ok(())
+}
+
+#[tokio::test]
+async fn consistent_hash() -> client_util::Result<()> {
+    let servers = [
+        server("a", Duration::ZERO),
+        server("b", Duration::ZERO),
+        server("c", Duration::ZERO),
+    ];
+    let client = client(
+        Balancer::new(servers.iter().map(base))
+            .unwrap()
+            .strategy(BalanceStrategy::ConsistentHash),
+    );
+    for key in ["alice", "bob", "carol"] {
+        let (endpoint, _) = get(&client, Some(key)).await?;
+        for _ in 0..3 {
+            assert_eq!(get(&client, Some(key)).await?.0, endpoint);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn fails_over_on_connect_errors() -> client_util::Result<()> {
+    let down = base(&server("down", Duration::ZERO));
+    let up = server("up", Duration::ZERO);
+    let client = client(Balancer::new([down.clone(), base(&up)]).unwrap());
+    let response = RequestBuilder::post(format!("{down}echo"))?
+        .plain_text("payload")
+        .send(client.clone())
+        .await?;
+    assert_eq!(response.extensions().get(), Some(&Endpoint(base(&up))));
+    assert_eq!(response.text().await?.into_body(), "up /echo payload");
+
+    // the endpoint is ejected
+    assert_eq!(client.balancer().healthy(), [base(&up)]);
+    for _ in 0..3 {
+        assert_eq!(get(&client, None).await?.0, base(&up));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn configures_a_cloned_balancer() -> client_util::Result<()> {
+    let down = base(&server("down", Duration::ZERO));
+    let server = server("up", Duration::ZERO);
+    let up = base(&server);
+    let client = client(Balancer::new([down.clone(), up.clone()]).unwrap());
+    assert_eq!(get(&client, None).await?.0, up);
+    assert_eq!(client.balancer().healthy(), std::slice::from_ref(&up));
+
+    // the configured clone tracks the health on its own
+    let balancer = client.balancer().clone().max_failures(3);
+    assert_eq!(balancer.healthy(), [down, up]);
+    Ok(())
+}
+
+#[test]
+fn rejects_invalid_bases() {
+    use client_util::client::balance::BalancerError;
+    assert!(matches!(Balancer::new([]), Err(BalancerError::NoBaseUri)));
+    let relative = http::Uri::from_static("/api");
+    assert!(matches!(
+        Balancer::new([relative]),
+        Err(BalancerError::RelativeBaseUri(uri)) if uri == "/api"
+    ));
+}
@@ -0,0 +1,79 @@
+use std::io::Read;
+
+use client_util::blocking::{Client, RequestBlockingExt};
+use client_util::client::global::SharedClient;
+use client_util::prelude::*;
+use http::StatusCode;
+use http_body_util::BodyExt;
+mod support;
+
+fn client() -> Client {
+    Client::with_client(SharedClient::new(build_http_client::<ClientBody>())).unwrap()
+}
+
+// The test functions are not async, and no runtime is running on their thread.
+#[test]
+fn blocking_text_and_json() -> client_util::Result<()> {
+    let server = support::server::http(|req| async move {
+        match req.uri().path() {
+            "/json" => http::Response::new(boxed_full(r#"{"hello":"world"}"#)),
+            path => http::Response::new(boxed_full(format!("hello {path}"))),
+        }
+    });
+    let client = client();
+
+    let response = RequestBuilder::get(format!("http://{}/text", server.addr()))?
+        .empty()
+        .send_blocking(&client)?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text()?.body(), "hello /text");
+
+    let response = client
+        .send(RequestBuilder::get(format!("http://{}/json", server.addr()))?.empty())?
+        .json::<serde_json::Value>()?;
+    assert_eq!(response.body()["hello"], "world");
+
+    let response = RequestBuilder::post(format!("http://{}/bytes", server.addr()))?
+        .plain_text("ignored")
+        .send_blocking(&client)?
+        .bytes()?;
+    assert_eq!(response.body().as_ref(), b"hello /bytes");
+    Ok(())
+}
+
+#[test]
+fn blocking_streaming_reader() -> client_util::Result<()> {
+    let server = support::server::http(|_req| async move {
+        let chunks = (0..64).map(|i| {
+            Ok::<_, std::convert::Infallible>(http_body::Frame::data(bytes::Bytes::from(format!(
+                "{i:04}"
+            ))))
+        });
+        let body = http_body_util::StreamBody::new(futures_util::stream::iter(chunks));
+        http::Response::new(body.map_err(Into::into).boxed())
+    });
+    let client = client();
+    let (parts, mut reader) = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send_blocking(&client)?
+        .reader()
+        .into_parts();
+    assert_eq!(parts.status, StatusCode::OK);
+
+    let mut first = [0; 6];
+    reader.read_exact(&mut first).unwrap();
+    assert_eq!(&first, b"000000");
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest.len(), 64 * 4 - 6);
+    assert!(rest.ends_with("0063"));
+    Ok(())
+}
+
+#[test]
+fn client_can_be_dropped_and_restarted() {
+    for _ in 0..3 {
+        let client = client();
+        drop(client.clone());
+    }
+}
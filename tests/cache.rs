@@ -0,0 +1,266 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use client_util::client::cache::{Cache, CacheLayer, CacheStatus, DiskStore};
+use client_util::prelude::*;
+use http::header::{ACCEPT_LANGUAGE, CACHE_CONTROL, CONTENT_LANGUAGE, ETAG, IF_NONE_MATCH, VARY};
+use http::StatusCode;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Counts the requests, the path selects the caching headers of the response.
+fn server() -> (support::server::Server, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let server = support::server::http(move |req| {
+        let hit = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            let mut response = http::Response::builder();
+            let mut body = format!("hit {hit}");
+            match req.uri().path() {
+                "/fresh" => response = response.header(CACHE_CONTROL, "max-age=60"),
+                "/no-store" => response = response.header(CACHE_CONTROL, "no-store, max-age=60"),
+                "/etag" => {
+                    response = response
+                        .header(CACHE_CONTROL, "no-cache")
+                        .header(ETAG, "\"v1\"");
+                    if req
+                        .headers()
+                        .get(IF_NONE_MATCH)
+                        .is_some_and(|tag| tag == "\"v1\"")
+                    {
+                        response = response.status(StatusCode::NOT_MODIFIED);
+                        body.clear();
+                    }
+                }
+                "/vary" => {
+                    let language = req.headers().get(ACCEPT_LANGUAGE).cloned();
+                    response = response
+                        .header(CACHE_CONTROL, "max-age=60")
+                        .header(VARY, "accept-language");
+                    if let Some(language) = language {
+                        response = response.header(CONTENT_LANGUAGE, language);
+                    }
+                }
+                "/flaky" if hit > 0 => response = response.status(StatusCode::SERVICE_UNAVAILABLE),
+                "/flaky" => response = response.header(CACHE_CONTROL, "max-age=0"),
+                _ => {}
+            }
+            response
+                .body(
+                    http_body_util::Full::new(body.into())
+                        .map_err(Into::into)
+                        .boxed(),
+                )
+                .unwrap()
+        }
+    });
+    (server, hits)
+}
+
+type Client = Cache<client_util::client::HyperHttpClient<ClientBody>>;
+
+fn client(layer: CacheLayer) -> Client {
+    ServiceBuilder::new()
+        .layer(layer)
+        .service(build_http_client())
+}
+
+async fn get(
+    client: &Client,
+    uri: String,
+    headers: http::HeaderMap,
+) -> client_util::Result<(CacheStatus, StatusCode, String)> {
+    let response = RequestBuilder::get(uri)?
+        .headers(headers)
+        .empty()
+        .send(client.clone())
+        .await?;
+    let cache_status = *response.extensions().get::<CacheStatus>().unwrap();
+    let status = response.status();
+    let body = response.text().await?.into_body();
+    Ok((cache_status, status, body))
+}
+
+#[tokio::test]
+async fn serves_fresh_responses() -> client_util::Result<()> {
+    let (server, hits) = server();
+    let client = client(CacheLayer::memory());
+    let fresh = format!("http://{}/fresh", server.addr());
+    let none = http::HeaderMap::new;
+    assert_eq!(
+        get(&client, fresh.clone(), none()).await?,
+        (CacheStatus::Miss, StatusCode::OK, "hit 0".into())
+    );
+    assert_eq!(
+        get(&client, fresh.clone(), none()).await?,
+        (CacheStatus::Hit, StatusCode::OK, "hit 0".into())
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // the request asks for a response fetched now
+    let mut no_cache = none();
+    no_cache.insert(CACHE_CONTROL, "no-cache".parse().unwrap());
+    assert_eq!(
+        get(&client, fresh.clone(), no_cache).await?.0,
+        CacheStatus::Miss
+    );
+
+    let no_store = format!("http://{}/no-store", server.addr());
+    assert_eq!(
+        get(&client, no_store.clone(), none()).await?.0,
+        CacheStatus::Miss
+    );
+    assert_eq!(get(&client, no_store, none()).await?.0, CacheStatus::Miss);
+
+    // a successful unsafe request invalidates the stored response
+    let response = RequestBuilder::post(&fresh)?
+        .empty()
+        .send(client.clone())
+        .await?;
+    assert_eq!(response.extensions().get(), Some(&CacheStatus::Bypass));
+    assert_eq!(
+        get(&client, fresh.clone(), none()).await?.0,
+        CacheStatus::Miss
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn configures_a_cloned_layer() -> client_util::Result<()> {
+    let (server, hits) = server();
+    let layer = CacheLayer::memory();
+    let private = client(layer.clone());
+    let shared = client(layer.clone().shared(true).max_body(1024));
+    let fresh = format!("http://{}/fresh", server.addr());
+    let none = http::HeaderMap::new;
+    assert_eq!(
+        get(&private, fresh.clone(), none()).await?.0,
+        CacheStatus::Miss
+    );
+    // the clones keep the same store
+    assert_eq!(get(&shared, fresh, none()).await?.0, CacheStatus::Hit);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn revalidates_with_etag() -> client_util::Result<()> {
+    let (server, hits) = server();
+    let client = client(CacheLayer::memory());
+    let uri = format!("http://{}/etag", server.addr());
+    assert_eq!(
+        get(&client, uri.clone(), http::HeaderMap::new()).await?,
+        (CacheStatus::Miss, StatusCode::OK, "hit 0".into())
+    );
+    assert_eq!(
+        get(&client, uri.clone(), http::HeaderMap::new()).await?,
+        (CacheStatus::Revalidated, StatusCode::OK, "hit 0".into())
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn selects_by_vary() -> client_util::Result<()> {
+    let (server, hits) = server();
+    let client = client(CacheLayer::memory());
+    let uri = format!("http://{}/vary", server.addr());
+    let language = |language: &str| {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, language.parse().unwrap());
+        headers
+    };
+    assert_eq!(
+        get(&client, uri.clone(), language("en")).await?.0,
+        CacheStatus::Miss
+    );
+    assert_eq!(
+        get(&client, uri.clone(), language("fr")).await?.0,
+        CacheStatus::Miss
+    );
+    assert_eq!(
+        get(&client, uri.clone(), language("en")).await?,
+        (CacheStatus::Hit, StatusCode::OK, "hit 0".into())
+    );
+    assert_eq!(
+        get(&client, uri.clone(), language("fr")).await?,
+        (CacheStatus::Hit, StatusCode::OK, "hit 1".into())
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_if_error() -> client_util::Result<()> {
+    let (server, _) = server();
+    let uri = format!("http://{}/flaky", server.addr());
+    let client = client(CacheLayer::memory().stale_if_error(true));
+    assert_eq!(
+        get(&client, uri.clone(), http::HeaderMap::new()).await?.0,
+        CacheStatus::Miss
+    );
+    assert_eq!(
+        get(&client, uri.clone(), http::HeaderMap::new()).await?,
+        (CacheStatus::Stale, StatusCode::OK, "hit 0".into())
+    );
+    drop(server);
+    // offline
+    assert_eq!(
+        get(&client, uri.clone(), http::HeaderMap::new()).await?.0,
+        CacheStatus::Stale
+    );
+
+    let client = self::client(CacheLayer::memory());
+    let mut only_if_cached = http::HeaderMap::new();
+    only_if_cached.insert(CACHE_CONTROL, "only-if-cached".parse().unwrap());
+    assert_eq!(
+        get(&client, uri, only_if_cached).await?.1,
+        StatusCode::GATEWAY_TIMEOUT
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn disk_store_outlives_the_client() -> client_util::Result<()> {
+    let (server, hits) = server();
+    let directory =
+        std::env::temp_dir().join(format!("client-util-cache-{}", server.addr().port()));
+    let uri = format!("http://{}/fresh", server.addr());
+    for status in [CacheStatus::Miss, CacheStatus::Hit] {
+        let client = client(CacheLayer::new(DiskStore::new(&directory).unwrap()));
+        assert_eq!(
+            get(&client, uri.clone(), http::HeaderMap::new()).await?,
+            (status, StatusCode::OK, "hit 0".into())
+        );
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    std::fs::remove_dir_all(directory).unwrap();
+    Ok(())
+}
+
+#[tokio::test]
+async fn disk_store_writes_a_key_concurrently() -> client_util::Result<()> {
+    let (server, _) = server();
+    let directory =
+        std::env::temp_dir().join(format!("client-util-cache-{}", server.addr().port()));
+    let client = client(CacheLayer::new(DiskStore::new(&directory).unwrap()));
+    let uri = format!("http://{}/fresh", server.addr());
+    let gets = (0..8).map(|_| get(&client, uri.clone(), http::HeaderMap::new()));
+    for response in futures_util::future::join_all(gets).await {
+        assert_eq!(response?.1, StatusCode::OK);
+    }
+
+    // a single file is left, no write was interleaved with another one
+    let files: Vec<_> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files.len(), 1, "{files:?}");
+    let reopened = self::client(CacheLayer::new(DiskStore::new(&directory).unwrap()));
+    let response = get(&reopened, uri, http::HeaderMap::new()).await?;
+    assert_eq!(response.0, CacheStatus::Hit);
+    std::fs::remove_dir_all(directory).unwrap();
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use client_util::client::circuit_breaker::{
+    origin, CircuitBreak, CircuitBreaker, CircuitBreakerLayer, CircuitPolicy, CircuitState,
+};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds with the status set in `status`, after the delay in milliseconds of the path.
+fn server() -> (support::server::Server, Arc<AtomicU16>, Arc<AtomicUsize>) {
+    let status = Arc::new(AtomicU16::new(500));
+    let hits = Arc::new(AtomicUsize::new(0));
+    let (current, counter) = (status.clone(), hits.clone());
+    let server = support::server::http(move |req| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let status = current.load(Ordering::SeqCst);
+        async move {
+            let delay = req.uri().path()[1..].parse().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            http::Response::builder()
+                .status(status)
+                .body(http_body_util::Empty::new().map_err(Into::into).boxed())
+                .unwrap()
+        }
+    });
+    (server, status, hits)
+}
+
+type Client = CircuitBreak<client_util::client::HyperHttpClient<ClientBody>>;
+
+fn client(breaker: &CircuitBreaker) -> Client {
+    ServiceBuilder::new()
+        .layer(CircuitBreakerLayer::new(breaker.clone()))
+        .service(build_http_client())
+}
+
+async fn get(client: &Client, uri: &str) -> client_util::Result<http::StatusCode> {
+    let response = RequestBuilder::get(uri)?
+        .empty()
+        .send(client.clone())
+        .await?;
+    Ok(response.status())
+}
+
+#[tokio::test]
+async fn opens_after_consecutive_failures() -> client_util::Result<()> {
+    let (server, status, hits) = server();
+    let breaker = CircuitBreaker::new(
+        CircuitPolicy::new()
+            .failure_threshold(3)
+            .open_duration(Duration::from_secs(60)),
+    );
+    let client = client(&breaker);
+    let uri = format!("http://{}/", server.addr());
+    let key = origin(&uri.parse().unwrap());
+
+    // a success resets the count
+    get(&client, &uri).await?;
+    get(&client, &uri).await?;
+    status.store(200, Ordering::SeqCst);
+    get(&client, &uri).await?;
+    status.store(500, Ordering::SeqCst);
+    get(&client, &uri).await?;
+    get(&client, &uri).await?;
+    assert_eq!(breaker.state(&key), CircuitState::Closed);
+
+    get(&client, &uri).await?;
+    assert_eq!(breaker.state(&key), CircuitState::Open);
+    let error = get(&client, &uri).await.unwrap_err();
+    assert!(error.is_circuit_open());
+    let client_util::Error::CircuitOpen(open) = error else {
+        unreachable!()
+    };
+    assert_eq!(open.origin, key);
+    assert!(open.retry_in > Duration::from_secs(50));
+    assert_eq!(hits.load(Ordering::SeqCst), 6);
+
+    breaker.reset(&key);
+    assert_eq!(
+        get(&client, &uri).await?,
+        http::StatusCode::INTERNAL_SERVER_ERROR
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn probes_when_half_open() -> client_util::Result<()> {
+    let (server, status, hits) = server();
+    let breaker = CircuitBreaker::new(
+        CircuitPolicy::new()
+            .failure_threshold(1)
+            .open_duration(Duration::from_millis(100)),
+    );
+    let client = client(&breaker);
+    let uri = format!("http://{}/", server.addr());
+    let key = origin(&uri.parse().unwrap());
+
+    get(&client, &uri).await?;
+    assert_eq!(breaker.state(&key), CircuitState::Open);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(breaker.state(&key), CircuitState::HalfOpen);
+
+    // a failed probe opens the circuit again
+    get(&client, &uri).await?;
+    assert_eq!(breaker.state(&key), CircuitState::Open);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    // a single probe is sent at once
+    status.store(200, Ordering::SeqCst);
+    let probe = tokio::spawn({
+        let (client, uri) = (client.clone(), format!("{uri}200"));
+        async move { get(&client, &uri).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let error = get(&client, &uri).await.unwrap_err();
+    assert!(matches!(error, client_util::Error::CircuitOpen(open) if open.retry_in.is_zero()));
+    assert_eq!(probe.await.unwrap()?, http::StatusCode::OK);
+    assert_eq!(breaker.state(&key), CircuitState::Closed);
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn classifies_failures() -> client_util::Result<()> {
+    // nothing listens on the port of a dropped server
+    let (server, _, _) = server();
+    let refused = format!("http://{}/", server.addr());
+    drop(server);
+    let breaker = CircuitBreaker::new(CircuitPolicy::new().failure_threshold(2));
+    let client = client(&breaker);
+    for _ in 0..2 {
+        let error = get(&client, &refused).await.unwrap_err();
+        assert!(!error.is_circuit_open());
+    }
+    assert!(get(&client, &refused).await.unwrap_err().is_circuit_open());
+
+    let breaker = CircuitBreaker::new(
+        CircuitPolicy::new()
+            .failure_threshold(2)
+            .connect_errors(false)
+            .server_errors(false),
+    );
+    let client = self::client(&breaker);
+    for _ in 0..3 {
+        assert!(!get(&client, &refused).await.unwrap_err().is_circuit_open());
+    }
+    let (server, _, _) = self::server();
+    let uri = format!("http://{}/", server.addr());
+    for _ in 0..3 {
+        get(&client, &uri).await?;
+    }
+    assert_eq!(
+        breaker.state(&origin(&uri.parse().unwrap())),
+        CircuitState::Closed
+    );
+    Ok(())
+}
+
+/// Responds to one request with a `500` on `addr`, which refuses the connections afterwards.
+async fn fail_once(addr: std::net::SocketAddr) -> tokio::task::JoinHandle<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(
+                b"HTTP/1.1 500 Internal Server Error\r\n\
+                  content-length: 0\r\nconnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+    })
+}
+
+#[tokio::test]
+async fn ignored_failures_are_neutral() -> client_util::Result<()> {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let uri = format!("http://{addr}/");
+    let key = origin(&uri.parse().unwrap());
+    let breaker = CircuitBreaker::new(
+        CircuitPolicy::new()
+            .failure_threshold(3)
+            .connect_errors(false),
+    );
+    let client = client(&breaker);
+    for _ in 0..2 {
+        let server = fail_once(addr).await;
+        assert_eq!(
+            get(&client, &uri).await?,
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        );
+        server.await.unwrap();
+        // the ignored connect error doesn't reset the count of the failures
+        assert!(!get(&client, &uri).await.unwrap_err().is_circuit_open());
+        assert_eq!(breaker.state(&key), CircuitState::Closed);
+    }
+    let server = fail_once(addr).await;
+    assert_eq!(
+        get(&client, &uri).await?,
+        http::StatusCode::INTERNAL_SERVER_ERROR
+    );
+    server.await.unwrap();
+    assert_eq!(breaker.state(&key), CircuitState::Open);
+    Ok(())
+}
+
+/// The error of a layer outside of the circuit breaker, with the error of the inner one as its
+/// source.
+#[derive(Debug)]
+struct Outer(client_util::error::BoxError);
+
+impl std::fmt::Display for Outer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "outer layer: {}", self.0)
+    }
+}
+
+impl std::error::Error for Outer {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+#[tokio::test]
+async fn finds_a_wrapped_rejection() -> client_util::Result<()> {
+    let (server, _, _) = server();
+    let uri = format!("http://{}/", server.addr());
+    let breaker = CircuitBreaker::new(CircuitPolicy::new().failure_threshold(1));
+    let client = ServiceBuilder::new()
+        .map_err(Outer)
+        .layer(CircuitBreakerLayer::new(breaker.clone()))
+        .service(build_http_client());
+    let get = || RequestBuilder::get(&uri).map(|builder| builder.empty());
+    assert_eq!(get()?.send(client.clone()).await?.status(), 500);
+    let error = get()?.send(client).await.unwrap_err();
+    assert!(error.is_circuit_open());
+    assert!(matches!(error, client_util::Error::CircuitOpen(_)));
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use client_util::prelude::*;
+use http::header::{ACCEPT, AUTHORIZATION, COOKIE, USER_AGENT};
+use http::{StatusCode, Version};
+use http_body_util::BodyExt;
+mod support;
+
+/// Answers with the request line, the user agent and accept headers, and the request body.
+fn echo_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .map(|value: &http::HeaderValue| value.to_str().unwrap().to_owned())
+                .unwrap_or_default()
+        };
+        let head = format!(
+            "{} {} ua={} accept={}\n",
+            req.method(),
+            req.uri(),
+            header(USER_AGENT),
+            header(ACCEPT)
+        );
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        http::Response::new(boxed_full([head.as_bytes(), &body].concat()))
+    })
+}
+
+#[tokio::test]
+async fn base_uri_and_default_headers() -> client_util::Result<()> {
+    let server = echo_server();
+    let client = Client::new(build_http_client())
+        .base_uri(format!("http://{}/api/v1/", server.addr()))?
+        .user_agent("facade/1.0")?
+        .default_header(ACCEPT, "application/json")?;
+
+    let response = client
+        .get("/users?page=2")?
+        .empty()
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body(),
+        "GET /api/v1/users?page=2 ua=facade/1.0 accept=application/json\n"
+    );
+
+    let response = client
+        .post("items")?
+        .header(ACCEPT, "text/plain")?
+        .json(&serde_json::json!({ "id": 1 }))?
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert_eq!(
+        response.body(),
+        "POST /api/v1/items ua=facade/1.0 accept=text/plain\n{\"id\":1}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn absolute_uri_bypasses_base() -> client_util::Result<()> {
+    let server = echo_server();
+    let client = Client::new(build_http_client())
+        .base_uri("http://example.invalid/")?
+        .user_agent("facade/1.0")?;
+    let response = client
+        .get(format!("http://{}/direct", server.addr()))?
+        .plain_text("hi")
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "GET /direct ua=facade/1.0 accept=\nhi");
+    Ok(())
+}
+
+#[tokio::test]
+async fn defaults_apply_to_plain_requests() -> client_util::Result<()> {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Tag(&'static str);
+
+    let client = Client::new(tower::service_fn(|request: http::Request<ClientBody>| {
+        let tag = request.extensions().get::<Tag>().cloned();
+        async move {
+            assert_eq!(request.uri(), "https://example.com/base/path");
+            assert_eq!(request.version(), Version::HTTP_2);
+            assert_eq!(tag, Some(Tag("request")));
+            http::Response::builder().body(boxed_full(""))
+        }
+    }))
+    .base_uri("https://example.com/base")?
+    .default_version(Version::HTTP_2)
+    .default_extension(Tag("default"));
+
+    client
+        .get("/path")?
+        .extension(Tag("request"))
+        .empty()
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn credentials_stay_on_the_base_origin() -> client_util::Result<()> {
+    let mut api_key = http::HeaderValue::from_static("secret");
+    api_key.set_sensitive(true);
+    let client = Client::new(tower::service_fn(
+        |request: http::Request<ClientBody>| async move {
+            let same_origin = request
+                .uri()
+                .to_string()
+                .starts_with("https://example.com/");
+            for name in [AUTHORIZATION.as_str(), COOKIE.as_str(), "x-api-key"] {
+                assert_eq!(
+                    request.headers().contains_key(name),
+                    same_origin,
+                    "{name} {}",
+                    request.uri()
+                );
+            }
+            assert!(request.headers().contains_key(USER_AGENT));
+            http::Response::builder().body(boxed_full(""))
+        },
+    ))
+    .base_uri("https://example.com/base")?
+    .default_header(AUTHORIZATION, "Bearer token")?
+    .default_header(COOKIE, "session=1")?
+    .default_headers(http::HeaderMap::from_iter([(
+        http::HeaderName::from_static("x-api-key"),
+        api_key,
+    )]))
+    .user_agent("facade/1.0")?;
+
+    for uri in [
+        "/path",
+        "https://example.com/other",
+        "http://example.com/",
+        "https://example.com:8443/",
+        "https://other.example.com/",
+    ] {
+        client.get(uri)?.empty().send().await?;
+    }
+    Ok(())
+}
+
+#[test]
+fn relative_base_uri_is_rejected() {
+    let result = Client::new(build_http_client::<ClientBody>()).base_uri("/relative");
+    assert!(matches!(
+        result,
+        Err(BuildRequestError::RelativeBaseUri(uri)) if uri == "/relative"
+    ));
+}
@@ -0,0 +1,162 @@
+use client_util::client::cookie::{CookieJar, CookieLayer, PublicSuffixList};
+use client_util::prelude::*;
+use http::header::{COOKIE, SET_COOKIE};
+use http::Uri;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+fn uri(uri: &str) -> Uri {
+    uri.parse().unwrap()
+}
+
+fn header(jar: &CookieJar, to: &str) -> Option<String> {
+    jar.cookie_header(&uri(to))
+        .map(|header| header.to_str().unwrap().to_owned())
+}
+
+#[tokio::test]
+async fn layer_sends_stored_cookies() -> client_util::Result<()> {
+    let server = support::server::http(|req| async move {
+        let mut response = http::Response::builder();
+        if req.uri().path() == "/login" {
+            response = response
+                .header(SET_COOKIE, "session=abc; Path=/; HttpOnly")
+                .header(SET_COOKIE, "theme=dark; Path=/settings");
+        }
+        let cookie = req
+            .headers()
+            .get(COOKIE)
+            .map(|cookie| cookie.to_str().unwrap().to_owned())
+            .unwrap_or_default();
+        response
+            .body(
+                http_body_util::Full::new(cookie.into())
+                    .map_err(Into::into)
+                    .boxed(),
+            )
+            .unwrap()
+    });
+    let jar = CookieJar::new();
+    let client = ServiceBuilder::new()
+        .layer(CookieLayer::new(jar.clone()))
+        .service(build_http_client());
+    let base = format!("http://{}", server.addr());
+    let send = |path: &str| {
+        let request = RequestBuilder::get(format!("{base}{path}")).map(|builder| builder.empty());
+        let client = client.clone();
+        async move {
+            request?
+                .send(client)
+                .await?
+                .text()
+                .await
+                .map_err(client_util::Error::from)
+        }
+    };
+
+    assert_eq!(send("/login").await?.body(), "");
+    assert_eq!(send("/").await?.body(), "session=abc");
+    assert_eq!(send("/settings/a").await?.body(), "theme=dark; session=abc");
+    assert_eq!(jar.cookies().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn domain_and_path_matching() {
+    let jar = CookieJar::new();
+    let origin = uri("http://www.example.com/docs/page");
+    jar.store(&origin, "host=1");
+    jar.store(&origin, "domain=2; Domain=.Example.com; Path=/");
+    jar.store(&origin, "foreign=3; Domain=other.com");
+
+    // the default path is the directory of the request
+    assert_eq!(
+        header(&jar, "http://www.example.com/docs"),
+        Some("host=1; domain=2".into())
+    );
+    assert_eq!(
+        header(&jar, "http://www.example.com/docsx"),
+        Some("domain=2".into())
+    );
+    assert_eq!(
+        header(&jar, "http://api.example.com/docs"),
+        Some("domain=2".into())
+    );
+    assert_eq!(header(&jar, "http://example.com/"), Some("domain=2".into()));
+    assert_eq!(header(&jar, "http://notexample.com/"), None);
+    assert_eq!(header(&jar, "http://other.com/"), None);
+}
+
+#[test]
+fn secure_and_expiry() {
+    let jar = CookieJar::new();
+    let secure = uri("https://example.com/");
+    jar.store(&secure, "secure=1; Secure");
+    jar.store(&secure, "short=2; Max-Age=3600");
+    jar.store(&secure, "old=3; Expires=Wed, 21 Oct 2015 07:28:00 GMT");
+    // a secure cookie can't be set over http
+    jar.store(&uri("http://example.com/"), "insecure=4; Secure");
+
+    assert_eq!(
+        header(&jar, "https://example.com/"),
+        Some("secure=1; short=2".into())
+    );
+    assert_eq!(header(&jar, "http://example.com/"), Some("short=2".into()));
+
+    // an expired cookie deletes the stored one
+    jar.store(&secure, "short=; Max-Age=0");
+    assert_eq!(header(&jar, "http://example.com/"), None);
+    let short = jar
+        .cookies()
+        .into_iter()
+        .find(|cookie| cookie.name == "short");
+    assert!(short.is_none());
+}
+
+#[test]
+fn public_suffixes_are_rejected() {
+    let list: PublicSuffixList = "// ===BEGIN ICANN DOMAINS===\ncom\nco.uk\n"
+        .parse()
+        .unwrap();
+    let jar = CookieJar::new().public_suffix_list(list);
+    jar.store(&uri("http://www.example.co.uk/"), "wide=1; Domain=co.uk");
+    jar.store(
+        &uri("http://www.example.co.uk/"),
+        "site=2; Domain=example.co.uk",
+    );
+    assert_eq!(header(&jar, "http://other.co.uk/"), None);
+    assert_eq!(header(&jar, "http://example.co.uk/"), Some("site=2".into()));
+
+    // a public suffix is allowed as the domain of its own host, host-only
+    jar.store(&uri("http://co.uk/"), "own=3; Domain=co.uk");
+    assert_eq!(header(&jar, "http://co.uk/"), Some("own=3".into()));
+    assert_eq!(header(&jar, "http://www.co.uk/"), None);
+}
+
+#[test]
+fn json_round_trip() {
+    let jar = CookieJar::new();
+    let origin = uri("https://example.com/");
+    jar.store(&origin, "session=1");
+    jar.store(&origin, "remember=2; Max-Age=3600; Secure");
+    let mut json = Vec::new();
+    jar.save_json(&mut json).unwrap();
+
+    let loaded = CookieJar::load_json(json.as_slice()).unwrap();
+    let mut cookies = loaded.cookies();
+    cookies.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut expected = jar.cookies();
+    expected.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(cookies, expected);
+    assert_eq!(
+        header(&loaded, "https://example.com/"),
+        Some("session=1; remember=2".into())
+    );
+
+    loaded.clear_session_cookies();
+    assert_eq!(
+        header(&loaded, "https://example.com/"),
+        Some("remember=2".into())
+    );
+}
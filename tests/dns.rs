@@ -0,0 +1,130 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use client_util::client::dns::{Addrs, DnsResolver, IpFamily, Name, Resolve, Resolving};
+use client_util::prelude::*;
+use http::StatusCode;
+mod support;
+
+fn host_echo_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let host = req.headers()[http::header::HOST]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        http::Response::new(boxed_full(host))
+    })
+}
+
+#[tokio::test]
+async fn resolve_override_keeps_host() -> client_util::Result<()> {
+    let server = host_echo_server();
+    let resolver =
+        DnsResolver::system().with_override("Example.Test", [([127, 0, 0, 1], 0).into()]);
+    let mut client = HyperClientBuilder::new().resolver(resolver).build_http();
+
+    let url = format!("http://example.test:{}/", server.addr().port());
+    let response = RequestBuilder::get(url)?
+        .empty()
+        .send(&mut client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body(),
+        &format!("example.test:{}", server.addr().port())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn resolve_override_with_port() -> client_util::Result<()> {
+    let server = host_echo_server();
+    let resolver = DnsResolver::system().with_override("example.test", [server.addr()]);
+    let mut client = HyperClientBuilder::new().resolver(resolver).build_http();
+
+    let response = RequestBuilder::get("http://example.test/")?
+        .empty()
+        .send(&mut client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "example.test");
+    Ok(())
+}
+
+#[tokio::test]
+async fn ip_family_pinning() -> client_util::Result<()> {
+    let server = host_echo_server();
+    let v6: SocketAddr = "[::1]:0".parse().unwrap();
+    let v4: SocketAddr = ([127, 0, 0, 1], 0).into();
+    let url = format!("http://dual.test:{}/", server.addr().port());
+
+    let resolver = DnsResolver::system()
+        .with_override("dual.test", [v6, v4])
+        .with_ip_family(IpFamily::V4);
+    let mut client = HyperClientBuilder::new().resolver(resolver).build_http();
+    let response = RequestBuilder::get(&url)?.empty().send(&mut client).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let resolver = DnsResolver::system()
+        .with_override("dual.test", [v4])
+        .with_ip_family(IpFamily::V6);
+    let mut client = HyperClientBuilder::new().resolver(resolver).build_http();
+    let error = RequestBuilder::get(&url)?
+        .empty()
+        .send(&mut client)
+        .await
+        .expect_err("no ipv6 address to connect");
+    assert!(matches!(error, client_util::Error::SendRequest(_)));
+    Ok(())
+}
+
+#[derive(Default)]
+struct CountingResolver {
+    lookups: Arc<AtomicUsize>,
+}
+
+impl Resolve for CountingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(name.as_str(), "custom.test");
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = vec![([127, 0, 0, 1], 0).into()];
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[tokio::test]
+async fn custom_resolver() -> client_util::Result<()> {
+    let server = host_echo_server();
+    let resolver = CountingResolver::default();
+    let lookups = resolver.lookups.clone();
+    let mut client = HyperClientBuilder::new()
+        .resolver(DnsResolver::new(resolver))
+        .build_http();
+
+    let url = format!("http://custom.test:{}/", server.addr().port());
+    let response = RequestBuilder::get(url)?.empty().send(&mut client).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[cfg(feature = "hickory-dns")]
+#[tokio::test]
+async fn hickory_resolver_hosts_file() -> client_util::Result<()> {
+    let server = host_echo_server();
+    let resolver = DnsResolver::hickory()
+        .expect("system dns config")
+        .with_ip_family(IpFamily::V4);
+    let mut client = HyperClientBuilder::new().resolver(resolver).build_http();
+
+    let url = format!("http://localhost:{}/", server.addr().port());
+    let response = RequestBuilder::get(url)?.empty().send(&mut client).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    Ok(())
+}
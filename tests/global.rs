@@ -0,0 +1,63 @@
+use client_util::client::global::{
+    reset_global_client, set_global_client, with_client, SharedClient,
+};
+use client_util::prelude::*;
+mod support;
+
+fn tagged(tag: &'static str) -> SharedClient {
+    SharedClient::new(tower::service_fn(
+        move |request: http::Request<ClientBody>| {
+            let body = format!("{tag} {}", request.uri());
+            async move { http::Response::builder().body(boxed_full(body)) }
+        },
+    ))
+}
+
+async fn get_text(url: &str) -> client_util::Result<String> {
+    Ok(client_util::get(url).await?.text().await?.into_body())
+}
+
+// The global client is shared by the whole test binary, so it is only touched by this test.
+#[tokio::test]
+async fn global_client_can_be_replaced_and_reset() -> client_util::Result<()> {
+    let server = support::server::http(|_req| async { http::Response::new(boxed_full("real")) });
+    let url = format!("http://{}/", server.addr());
+
+    assert!(set_global_client(tagged("global")).is_none());
+    assert_eq!(get_text(&url).await?, format!("global {url}"));
+
+    let previous = reset_global_client();
+    assert!(previous.is_some());
+    assert_eq!(get_text(&url).await?, "real");
+    assert!(reset_global_client().is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn scoped_client_overrides_the_global_one() -> client_util::Result<()> {
+    let url = "http://scoped.invalid/path";
+    let text = with_client(tagged("outer"), async {
+        let outer = get_text(url).await?;
+        let inner = with_client(tagged("inner"), get_text(url)).await?;
+        let after = get_text(url).await?;
+        client_util::Result::Ok(format!("{outer}|{inner}|{after}"))
+    })
+    .await?;
+    assert_eq!(text, format!("outer {url}|inner {url}|outer {url}"));
+    Ok(())
+}
+
+#[allow(deprecated)]
+mod legacy_macro {
+    use client_util::prelude::*;
+    fn make_client() -> HyperHttpClient<ClientBody> {
+        build_http_client()
+    }
+    client_util::shared_client!(pub client: make_client -> HyperHttpClient<ClientBody>);
+
+    #[test]
+    fn shared_client_macro_still_works() {
+        let _client = client();
+        let _again = client();
+    }
+}
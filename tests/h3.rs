@@ -0,0 +1,219 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes, BytesMut};
+use client_util::client::dns::DnsResolver;
+use client_util::prelude::*;
+use http::{StatusCode, Version};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use tower::ServiceBuilder;
+
+const CERT: &[u8] = include_bytes!("support/server.cert");
+const KEY: &[u8] = include_bytes!("support/server.key");
+const SERVER_NAME: &str = "testserver.com";
+
+/// Trust only the test certificate.
+#[derive(Debug)]
+struct PinnedCert(Arc<rustls::crypto::CryptoProvider>);
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        assert_eq!(server_name.to_str(), SERVER_NAME);
+        if end_entity.as_ref() == CERT {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("unexpected certificate".into()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn h3_client() -> H3Client {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let tls = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCert(provider)))
+        .with_no_client_auth();
+    H3Client::new(tls).unwrap().with_resolver(
+        DnsResolver::system().with_override(SERVER_NAME, [([127, 0, 0, 1], 0).into()]),
+    )
+}
+
+/// An HTTP/3 server which answers with the request path followed by the request body, and counts
+/// the connections.
+fn h3_server() -> (SocketAddr, Arc<AtomicUsize>) {
+    let mut tls = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(CERT)],
+            PrivateKeyDer::try_from(KEY).unwrap(),
+        )
+        .unwrap();
+    tls.alpn_protocols = vec![ALPN_H3.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls).unwrap();
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    let endpoint = quinn::Endpoint::server(config, ([127, 0, 0, 1], 0).into()).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let connection = incoming.await.unwrap();
+                let mut connection = ::h3::server::Connection::<_, Bytes>::new(
+                    h3_quinn::Connection::new(connection),
+                )
+                .await
+                .unwrap();
+                while let Ok(Some(resolver)) = connection.accept().await {
+                    tokio::spawn(async move {
+                        let (request, mut stream) = resolver.resolve_request().await.unwrap();
+                        let mut body = BytesMut::from(request.uri().path().as_bytes());
+                        while let Some(mut data) = stream.recv_data().await.unwrap() {
+                            body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                        }
+                        stream.send_response(http::Response::new(())).await.unwrap();
+                        stream.send_data(body.freeze()).await.unwrap();
+                        stream.finish().await.unwrap();
+                    });
+                }
+            });
+        }
+    });
+    (addr, connections)
+}
+
+#[tokio::test]
+async fn h3_get() -> client_util::Result<()> {
+    let (addr, _) = h3_server();
+    let client = h3_client();
+    let url = format!("https://{SERVER_NAME}:{}/hello", addr.port());
+    for _ in 0..2 {
+        let response = RequestBuilder::get(&url)?
+            .empty()
+            .send(client.clone())
+            .await?
+            .text()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), Version::HTTP_3);
+        assert_eq!(response.body(), "/hello");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn h3_connects_once_per_origin() -> client_util::Result<()> {
+    let (addr, connections) = h3_server();
+    let client = h3_client();
+    let url = format!("https://{SERVER_NAME}:{}/hello", addr.port());
+    let requests: Vec<_> = (0..4)
+        .map(|_| {
+            let request = RequestBuilder::get(&url).map(|builder| builder.empty());
+            let client = client.clone();
+            tokio::spawn(async move {
+                client_util::Result::Ok(request?.send(client).await?.text().await?)
+            })
+        })
+        .collect();
+    for request in requests {
+        assert_eq!(request.await.unwrap()?.body(), "/hello");
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn h3_post_body() -> client_util::Result<()> {
+    let (addr, _) = h3_server();
+    let url = format!("https://{SERVER_NAME}:{}/echo", addr.port());
+    let response = RequestBuilder::post(url)?
+        .plain_text(" says hello")
+        .send(h3_client())
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "/echo says hello");
+    Ok(())
+}
+
+#[tokio::test]
+async fn alt_svc_upgrade() -> client_util::Result<()> {
+    let (addr, _) = h3_server();
+    let alt_svc = format!(r#"h3=":{}"; ma=60"#, addr.port());
+    let tcp = tower::service_fn(move |_request: http::Request<ClientBody>| {
+        let alt_svc = alt_svc.clone();
+        async move {
+            http::Response::builder()
+                .header(http::header::ALT_SVC, alt_svc)
+                .body(boxed_full("over tcp"))
+        }
+    });
+    let client = ServiceBuilder::new()
+        .layer(AltSvcLayer::new(h3_client()))
+        .service(tcp);
+
+    let url = format!("https://{SERVER_NAME}/upgrade");
+    let response = RequestBuilder::get(&url)?
+        .empty()
+        .send(client.clone())
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "over tcp");
+    assert!(client
+        .cache()
+        .contains(&http::uri::Authority::from_static(SERVER_NAME)));
+
+    let response = RequestBuilder::get(&url)?
+        .empty()
+        .send(client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.version(), Version::HTTP_3);
+    assert_eq!(response.body(), "/upgrade");
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use client_util::client::hedge::{Hedge, HedgeLayer, HedgePolicy, Hedged};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// The first request is answered after `first_delay`, the next ones at once, echoing the body
+/// after the name of the server.
+fn server(
+    name: &'static str,
+    first_delay: Duration,
+) -> (support::server::Server, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let server = support::server::http(move |req| {
+        let hit = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            if hit == 0 {
+                tokio::time::sleep(first_delay).await;
+            }
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            let body = format!("{name} {}", String::from_utf8_lossy(&body));
+            http::Response::builder()
+                .body(
+                    http_body_util::Full::new(body.into())
+                        .map_err(Into::into)
+                        .boxed(),
+                )
+                .unwrap()
+        }
+    });
+    (server, hits)
+}
+
+type Client = Hedge<client_util::client::HyperHttpClient<ClientBody>>;
+
+fn client(policy: HedgePolicy) -> Client {
+    ServiceBuilder::new()
+        .layer(HedgeLayer::new(policy))
+        .service(build_http_client())
+}
+
+async fn send<B>(client: &Client, request: http::Request<B>) -> client_util::Result<(bool, String)>
+where
+    B: ::hyper::body::Body<Data = ::hyper::body::Bytes, Error = std::convert::Infallible>
+        + Send
+        + Sync
+        + 'static,
+{
+    let response = request.send(client.clone()).await?;
+    let hedged = response.extensions().get::<Hedged>().unwrap().0;
+    Ok((hedged, response.text().await?.into_body()))
+}
+
+#[tokio::test]
+async fn hedges_slow_requests() -> client_util::Result<()> {
+    let (server, hits) = server("primary", Duration::from_secs(2));
+    let client = client(HedgePolicy::new().initial_delay(Duration::from_millis(50)));
+    let uri = format!("http://{}/", server.addr());
+    let start = Instant::now();
+    let response = send(&client, RequestBuilder::get(&uri)?.empty()).await?;
+    assert_eq!(response, (true, "primary ".into()));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    // fast responses aren't hedged
+    let response = send(&client, RequestBuilder::get(&uri)?.empty()).await?;
+    assert_eq!(response, (false, "primary ".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn hedges_read_only_requests() -> client_util::Result<()> {
+    let (server, hits) = server("primary", Duration::from_millis(300));
+    let client = client(HedgePolicy::new().initial_delay(Duration::from_millis(50)));
+    let uri = format!("http://{}/", server.addr());
+    let response = send(&client, RequestBuilder::post(&uri)?.plain_text("once")).await?;
+    assert_eq!(response, (false, "primary once".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    let (server, hits) = self::server("primary", Duration::from_secs(2));
+    let uri = format!("http://{}/", server.addr());
+    let response = send(
+        &client,
+        RequestBuilder::post(&uri)?.hedge(true).plain_text("twice"),
+    )
+    .await?;
+    assert_eq!(response, (true, "primary twice".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn hedges_to_the_alternate() -> client_util::Result<()> {
+    let (primary, _) = server("primary", Duration::from_secs(2));
+    let (alternate, hits) = server("alternate", Duration::ZERO);
+    let client = client(
+        HedgePolicy::new()
+            .initial_delay(Duration::from_millis(50))
+            .alternate(format!("http://{}", alternate.addr()).parse().unwrap()),
+    );
+    let uri = format!("http://{}/", primary.addr());
+    let response = send(&client, RequestBuilder::get(&uri)?.empty()).await?;
+    assert_eq!(response, (true, "alternate ".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn delay_follows_the_latencies() -> client_util::Result<()> {
+    let (server, _) = server("primary", Duration::ZERO);
+    let client = client(
+        HedgePolicy::new()
+            .initial_delay(Duration::from_secs(1))
+            .min_samples(5),
+    );
+    let uri = format!("http://{}/", server.addr());
+    for _ in 0..5 {
+        assert_eq!(client.delay(), Duration::from_secs(1));
+        send(&client, RequestBuilder::get(&uri)?.empty()).await?;
+    }
+    assert!(client.delay() < Duration::from_millis(500));
+    Ok(())
+}
+
+#[tokio::test]
+async fn hedges_within_the_budget() -> client_util::Result<()> {
+    let client = client(
+        HedgePolicy::new()
+            .initial_delay(Duration::from_millis(50))
+            .max_ratio(0.0),
+    );
+    let (server, hits) = server("primary", Duration::from_secs(2));
+    let response = send(
+        &client,
+        RequestBuilder::get(format!("http://{}/", server.addr()))?.empty(),
+    )
+    .await?;
+    assert_eq!(response, (true, "primary ".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    // the first copy spent the budget
+    let (server, hits) = self::server("primary", Duration::from_millis(300));
+    let response = send(
+        &client,
+        RequestBuilder::get(format!("http://{}/", server.addr()))?.empty(),
+    )
+    .await?;
+    assert_eq!(response, (false, "primary ".into()));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    Ok(())
+}
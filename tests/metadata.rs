@@ -0,0 +1,83 @@
+use client_util::client::metadata::{ConnectionInfo, ResponseInfo, ResponseInfoLayer};
+use client_util::prelude::*;
+use tower::ServiceBuilder;
+mod support;
+
+fn hello_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        http::Response::new(boxed_full(format!("hello {}", req.uri().path())))
+    })
+}
+
+#[tokio::test]
+async fn connection_and_response_info() -> client_util::Result<()> {
+    let server = hello_server();
+    let client = ServiceBuilder::new()
+        .layer(ResponseInfoLayer)
+        .service(build_http_client());
+    let url = format!("http://{}/info", server.addr());
+    let mut connections = Vec::new();
+    for reused in [false, true] {
+        let response = RequestBuilder::get(&url)?
+            .empty()
+            .send(client.clone())
+            .await?
+            .text()
+            .await?;
+        assert_eq!(response.body(), "hello /info");
+        let connection = response.extensions().get::<ConnectionInfo>().unwrap();
+        assert_eq!(connection.remote_addr, Some(server.addr()));
+        assert!(connection.local_addr.is_some());
+        assert!(connection.tls.is_none());
+        assert_eq!(connection.timings.dns, None);
+        assert_eq!(connection.timings.tls, None);
+        assert!(connection.timings.connect <= connection.timings.total);
+        connections.push(connection.timings);
+
+        let info = response.extensions().get::<ResponseInfo>().unwrap();
+        assert_eq!(info.reused, Some(reused));
+        assert!(info.body_duration().is_some());
+    }
+    // the timings are the ones of the connection, which is reused
+    assert_eq!(connections[0], connections[1]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn dns_resolution_is_timed() -> client_util::Result<()> {
+    let server = hello_server();
+    let url = format!("http://localhost:{}/dns", server.addr().port());
+    let response = RequestBuilder::get(url)?
+        .empty()
+        .send(build_http_client())
+        .await?;
+    let connection = response.extensions().get::<ConnectionInfo>().unwrap();
+    let dns = connection.timings.dns.expect("the host is resolved");
+    assert!(dns + connection.timings.connect <= connection.timings.total);
+    assert_eq!(
+        connection.remote_addr.map(|remote| remote.port()),
+        Some(server.addr().port())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn body_duration_is_unknown_until_read() -> client_util::Result<()> {
+    let server = hello_server();
+    let client = ServiceBuilder::new()
+        .layer(ResponseInfoLayer)
+        .service(build_http_client());
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client)
+        .await?;
+    let info = response
+        .extensions()
+        .get::<ResponseInfo>()
+        .cloned()
+        .unwrap();
+    assert_eq!(info.body_duration(), None);
+    response.text().await?;
+    assert!(info.body_duration().is_some());
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use ::metrics::{SharedString, Unit};
+use client_util::client::metrics::{
+    MetricsLayer, ACTIVE_REQUESTS, ERRORS, REQUEST_BYTES, REQUEST_DURATION, RESPONSE_BYTES,
+};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+use metrics_util::CompositeKey;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds with `size` bytes, the size is the path.
+fn server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let size = req.uri().path()[1..].parse().unwrap_or(0);
+        http::Response::builder()
+            .body(
+                http_body_util::Full::new(vec![b'a'; size].into())
+                    .map_err(Into::into)
+                    .boxed(),
+            )
+            .unwrap()
+    })
+}
+
+/// Run `test` with a recorder on the current thread.
+fn with_recorder<F: std::future::Future<Output = client_util::Result<()>>>(
+    test: impl FnOnce(Snapshotter) -> F,
+) -> client_util::Result<()> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    ::metrics::with_local_recorder(&recorder, || runtime.block_on(test(snapshotter)))
+}
+
+type Snapshot = Vec<(CompositeKey, Option<Unit>, Option<SharedString>, DebugValue)>;
+
+/// The value of the metric `name` with the label `key=value`.
+fn value<'a>(snapshot: &'a Snapshot, name: &str, label: (&str, &str)) -> Option<&'a DebugValue> {
+    snapshot
+        .iter()
+        .find(|(key, ..)| {
+            key.key().name() == name && key.key().labels().any(|l| (l.key(), l.value()) == label)
+        })
+        .map(|(.., value)| value)
+}
+
+#[test]
+fn records_requests() -> client_util::Result<()> {
+    with_recorder(|snapshotter| async move {
+        let server = server();
+        let client = ServiceBuilder::new()
+            .layer(MetricsLayer::new().label("client", "test"))
+            .service(build_http_client());
+        let uri = format!("http://{}/1000", server.addr());
+        let response = RequestBuilder::post(&uri)?
+            .plain_text("hello")
+            .send(client)
+            .await?;
+        assert_eq!(response.text().await?.into_body().len(), 1000);
+
+        // the values are reset by a snapshot
+        let snapshot = snapshotter.snapshot().into_vec();
+        let active = value(&snapshot, ACTIVE_REQUESTS, ("client", "test"));
+        assert_eq!(active, Some(&DebugValue::Gauge(0.0.into())));
+        let bytes = value(&snapshot, REQUEST_BYTES, ("host", "127.0.0.1"));
+        assert_eq!(bytes, Some(&DebugValue::Counter(5)));
+        let bytes = value(&snapshot, RESPONSE_BYTES, ("host", "127.0.0.1"));
+        assert_eq!(bytes, Some(&DebugValue::Counter(1000)));
+        let Some(DebugValue::Histogram(durations)) =
+            value(&snapshot, REQUEST_DURATION, ("status_class", "2xx"))
+        else {
+            panic!("no duration recorded")
+        };
+        assert_eq!(durations.len(), 1);
+        assert_eq!(value(&snapshot, ERRORS, ("method", "POST")), None);
+        Ok(())
+    })
+}
+
+#[test]
+fn records_errors() -> client_util::Result<()> {
+    with_recorder(|snapshotter| async move {
+        // nothing listens on the address of a dropped listener
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let client = ServiceBuilder::new()
+            .layer(MetricsLayer::new())
+            .service(build_http_client());
+        let result = RequestBuilder::get(format!("http://{addr}/"))?
+            .empty()
+            .send(client)
+            .await;
+        assert!(result.is_err());
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let errors = value(&snapshot, ERRORS, ("error_kind", "connect"));
+        assert_eq!(errors, Some(&DebugValue::Counter(1)));
+        let active = value(&snapshot, ACTIVE_REQUESTS, ("method", "GET"));
+        assert_eq!(active, Some(&DebugValue::Gauge(0.0.into())));
+        assert!(value(&snapshot, REQUEST_DURATION, ("status_class", "error")).is_some());
+        Ok(())
+    })
+}
@@ -0,0 +1,171 @@
+use client_util::client::pool::PoolTracker;
+use client_util::prelude::*;
+use futures_util::future::try_join_all;
+use http::{StatusCode, Version};
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+fn hello_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        http::Response::new(boxed_full(format!("hello {}", req.uri().path())))
+    })
+}
+
+#[tokio::test]
+async fn connections_are_counted_and_reused() -> client_util::Result<()> {
+    let server = hello_server();
+    let tracker = PoolTracker::new();
+    let client = ServiceBuilder::new()
+        .layer(tracker.layer())
+        .service(HyperClientBuilder::new().build_http_tracked(&tracker));
+    let url = format!("http://{}/reuse", server.addr());
+    for _ in 0..3 {
+        let response = RequestBuilder::get(&url)?
+            .empty()
+            .send(client.clone())
+            .await?
+            .text()
+            .await?;
+        assert_eq!(response.body(), "hello /reuse");
+    }
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!((stats.idle, stats.active), (1, 0));
+    assert_eq!(stats.connections.len(), 1);
+    assert_eq!(stats.connections[0].requests, 3);
+    assert_eq!(stats.connections[0].version, Some(Version::HTTP_11));
+    assert!(!stats.connections[0].preconnected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn unread_responses_are_active() -> client_util::Result<()> {
+    let server = hello_server();
+    let tracker = PoolTracker::new();
+    let client = ServiceBuilder::new()
+        .layer(tracker.layer())
+        .service(HyperClientBuilder::new().build_http_tracked(&tracker));
+    let url = format!("http://{}/active", server.addr());
+    let response = RequestBuilder::get(&url)?
+        .empty()
+        .send(client.clone())
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!((stats.idle, stats.active), (0, 1));
+    assert_eq!(stats.connections[0].in_flight, 1);
+
+    response.into_body().collect().await.unwrap();
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!((stats.idle, stats.active), (1, 0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn http2_requests_share_a_connection() -> client_util::Result<()> {
+    let server = hello_server();
+    let tracker = PoolTracker::new();
+    let client = ServiceBuilder::new().layer(tracker.layer()).service(
+        HyperClientBuilder::new()
+            .http_version(HttpVersionMode::Http2PriorKnowledge)
+            .build_http_tracked(&tracker),
+    );
+    let url = format!("http://{}/h2", server.addr());
+    let requests = (0..4).map(|_| {
+        let client = client.clone();
+        let url = url.clone();
+        async move {
+            RequestBuilder::get(url)?
+                .version(Version::HTTP_2)
+                .empty()
+                .send(client)
+                .await?
+                .text()
+                .await
+                .map_err(client_util::Error::from)
+        }
+    });
+    try_join_all(requests).await?;
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!(stats.connections.len(), 1);
+    assert_eq!(stats.connections[0].requests, 4);
+    assert_eq!(stats.connections[0].version, Some(Version::HTTP_2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn preconnected_connection_is_used() -> client_util::Result<()> {
+    let server = hello_server();
+    let tracker = PoolTracker::new();
+    let client = ServiceBuilder::new()
+        .layer(tracker.layer())
+        .service(HyperClientBuilder::new().build_http_tracked(&tracker));
+    let url = format!("http://{}/warm", server.addr());
+    tracker.preconnect(url.parse().unwrap()).await.unwrap();
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!(stats.connections.len(), 1);
+    assert!(stats.connections[0].preconnected);
+    assert_eq!(stats.connections[0].requests, 0);
+
+    RequestBuilder::get(&url)?
+        .empty()
+        .send(client)
+        .await?
+        .text()
+        .await?;
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!(stats.connections.len(), 1);
+    assert!(stats.connections[0].preconnected);
+    assert_eq!(stats.connections[0].requests, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn preconnect_needs_a_client() {
+    let tracker = PoolTracker::new();
+    assert!(tracker
+        .preconnect("http://localhost".parse().unwrap())
+        .await
+        .is_err());
+    let client = HyperClientBuilder::new().build_http_tracked::<ClientBody>(&tracker);
+    drop(client);
+    assert!(tracker
+        .preconnect("http://localhost".parse().unwrap())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn preconnected_connections_are_limited() -> client_util::Result<()> {
+    let server = hello_server();
+    let tracker = PoolTracker::new().max_warm(1);
+    let _client = HyperClientBuilder::new().build_http_tracked::<ClientBody>(&tracker);
+    let url = format!("http://{}/warm", server.addr());
+    for _ in 0..3 {
+        tracker.preconnect(url.parse().unwrap()).await.unwrap();
+    }
+    assert_eq!(
+        tracker
+            .origin_stats(&url.parse().unwrap())
+            .connections
+            .len(),
+        1
+    );
+
+    // an expired connection is closed instead of being used
+    let tracker = PoolTracker::new().warm_timeout(std::time::Duration::ZERO);
+    let client = ServiceBuilder::new()
+        .layer(tracker.layer())
+        .service(HyperClientBuilder::new().build_http_tracked(&tracker));
+    tracker.preconnect(url.parse().unwrap()).await.unwrap();
+    RequestBuilder::get(&url)?
+        .empty()
+        .send(client)
+        .await?
+        .text()
+        .await?;
+    let stats = tracker.origin_stats(&url.parse().unwrap());
+    assert_eq!(stats.connections.len(), 1);
+    assert!(!stats.connections[0].preconnected);
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use client_util::client::rate_limit::{
+    Quota, RateLimit, RateLimitLayer, RateLimitWait, RateLimiter,
+};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds with the headers in the query, `name=value` pairs with `=` and
+/// spaces percent-encoded in the value.
+fn server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let mut response = http::Response::builder();
+        for pair in req.uri().query().unwrap_or_default().split('&') {
+            if let Some((name, value)) = pair.split_once('=') {
+                let value = value.replace("%3D", "=").replace("%20", " ");
+                response = response.header(name, value);
+            }
+        }
+        if req.uri().path() == "/429" {
+            response = response.status(http::StatusCode::TOO_MANY_REQUESTS);
+        }
+        response
+            .body(http_body_util::Empty::new().map_err(Into::into).boxed())
+            .unwrap()
+    })
+}
+
+fn client(limiter: &RateLimiter) -> RateLimit<client_util::client::HyperHttpClient<ClientBody>> {
+    ServiceBuilder::new()
+        .layer(RateLimitLayer::new(limiter.clone()))
+        .service(build_http_client())
+}
+
+async fn wait(
+    client: &RateLimit<client_util::client::HyperHttpClient<ClientBody>>,
+    uri: String,
+) -> client_util::Result<Duration> {
+    let response = RequestBuilder::get(uri)?
+        .empty()
+        .send(client.clone())
+        .await?;
+    Ok(response.extensions().get::<RateLimitWait>().unwrap().0)
+}
+
+#[tokio::test]
+async fn token_bucket_by_host() -> client_util::Result<()> {
+    let server = server();
+    let limiter = RateLimiter::new(Quota::new(1, Duration::from_millis(100)).burst(2));
+    let client = client(&limiter);
+    let uri = format!("http://{}/", server.addr());
+    let mut waits = Vec::new();
+    for _ in 0..4 {
+        waits.push(wait(&client, uri.clone()).await?);
+    }
+    assert_eq!(&waits[..2], [Duration::ZERO; 2]);
+    assert!(waits[2..].iter().all(|wait| !wait.is_zero()), "{waits:?}");
+
+    let stats = limiter.key_stats("127.0.0.1");
+    assert_eq!((stats.requests, stats.delayed), (4, 2));
+    assert_eq!(stats.waited, waits.iter().sum::<Duration>());
+    assert_eq!(limiter.stats(), stats);
+
+    // a configured clone is a new limiter
+    let other = limiter
+        .clone()
+        .quota_for("example.com", Quota::per_second(1));
+    assert_eq!(other.stats(), Default::default());
+    assert_eq!(limiter.stats(), stats);
+    Ok(())
+}
+
+#[tokio::test]
+async fn custom_key() -> client_util::Result<()> {
+    let server = server();
+    let limiter = RateLimiter::new(Quota::per_minute(1))
+        .key(|_, headers| {
+            headers
+                .get("x-account")
+                .and_then(|account| account.to_str().ok())
+                .unwrap_or_default()
+                .to_owned()
+        })
+        .quota_for("unlimited", Quota::per_second(1000));
+    let client = client(&limiter);
+    let uri = format!("http://{}/", server.addr());
+    for account in ["a", "b", "unlimited", "unlimited"] {
+        let response = RequestBuilder::get(&uri)?
+            .header("x-account", account)?
+            .empty()
+            .send(client.clone())
+            .await?;
+        assert_eq!(
+            response.extensions().get(),
+            Some(&RateLimitWait(Duration::ZERO))
+        );
+    }
+    assert_eq!(limiter.key_stats("a").requests, 1);
+    assert_eq!(limiter.key_stats("unlimited").requests, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn holds_until_the_reset() -> client_util::Result<()> {
+    let server = server();
+    let limiter = RateLimiter::new(Quota::per_second(100));
+    let client = client(&limiter);
+    let base = format!("http://{}", server.addr());
+
+    wait(
+        &client,
+        format!("{base}/?RateLimit-Remaining=0&RateLimit-Reset=1"),
+    )
+    .await?;
+    let held = wait(&client, format!("{base}/")).await?;
+    assert!(held > Duration::from_millis(800), "{held:?}");
+
+    wait(&client, format!("{base}/429?Retry-After=1")).await?;
+    let held = wait(&client, format!("{base}/")).await?;
+    assert!(held > Duration::from_millis(800), "{held:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn paces_the_remaining_requests() -> client_util::Result<()> {
+    let server = server();
+    let base = format!("http://{}", server.addr());
+
+    // the structured header, 2 requests remaining in 1 second
+    let limiter = RateLimiter::new(Quota::per_second(100));
+    let client = client(&limiter);
+    wait(
+        &client,
+        format!("{base}/?RateLimit=limit%3D10,%20remaining%3D2,%20reset%3D1"),
+    )
+    .await?;
+    let paced = wait(&client, format!("{base}/")).await?;
+    assert!(paced > Duration::from_millis(300), "{paced:?}");
+
+    // the X- headers are ignored when not adaptive
+    let limiter = RateLimiter::new(Quota::per_second(100)).adaptive(false);
+    let client = self::client(&limiter);
+    wait(
+        &client,
+        format!("{base}/?X-RateLimit-Remaining=0&X-RateLimit-Reset=10"),
+    )
+    .await?;
+    assert_eq!(wait(&client, format!("{base}/")).await?, Duration::ZERO);
+    Ok(())
+}
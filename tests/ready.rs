@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use client_util::prelude::*;
+use futures_util::future::try_join_all;
+use http::StatusCode;
+use tower::ServiceBuilder;
+mod support;
+
+/// Counts the requests handled at the same time, and records the maximum.
+#[derive(Clone, Default)]
+struct InFlight {
+    current: Arc<AtomicUsize>,
+    max: Arc<AtomicUsize>,
+}
+
+fn slow_server(in_flight: InFlight) -> support::server::Server {
+    support::server::http(move |_req| {
+        let in_flight = in_flight.clone();
+        async move {
+            let current = in_flight.current.fetch_add(1, Ordering::SeqCst) + 1;
+            in_flight.max.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            in_flight.current.fetch_sub(1, Ordering::SeqCst);
+            http::Response::new(boxed_full("done"))
+        }
+    })
+}
+
+#[tokio::test]
+async fn concurrency_limit_is_respected() -> client_util::Result<()> {
+    let in_flight = InFlight::default();
+    let server = slow_server(in_flight.clone());
+    let client = ServiceBuilder::new()
+        .concurrency_limit(1)
+        .service(build_http_client());
+
+    let url = format!("http://{}/", server.addr());
+    let mut requests = Vec::new();
+    for _ in 0..4 {
+        requests.push(RequestBuilder::get(&url)?.empty().send(&client));
+    }
+    for response in try_join_all(requests).await? {
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert_eq!(in_flight.max.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn buffer_waits_for_capacity() -> client_util::Result<()> {
+    let in_flight = InFlight::default();
+    let server = slow_server(in_flight.clone());
+    let client = ServiceBuilder::new()
+        .buffer(1)
+        .concurrency_limit(2)
+        .service(build_http_client());
+
+    let url = format!("http://{}/", server.addr());
+    let mut requests = Vec::new();
+    for _ in 0..6 {
+        requests.push(RequestBuilder::get(&url)?.empty().send(&client));
+    }
+    for response in try_join_all(requests).await? {
+        assert_eq!(response.text().await?.body(), "done");
+    }
+    assert_eq!(in_flight.max.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limit_delays_requests() -> client_util::Result<()> {
+    let server = slow_server(InFlight::default());
+    let mut client = ServiceBuilder::new()
+        .rate_limit(1, Duration::from_millis(100))
+        .service(build_http_client());
+
+    let url = format!("http://{}/", server.addr());
+    let start = Instant::now();
+    for _ in 0..3 {
+        let response = RequestBuilder::get(&url)?.empty().send(&mut client).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use client_util::client::redirect::{
+    resolve, RedirectError, RedirectHistory, RedirectHop, RedirectLayer, RedirectPolicy,
+};
+use client_util::prelude::*;
+use http::header::{AUTHORIZATION, COOKIE, LOCATION};
+use http::{HeaderValue, StatusCode, Uri};
+use http_body_util::BodyExt;
+use tower::{Layer, ServiceBuilder};
+mod support;
+
+fn response(
+    status: StatusCode,
+    location: Option<&str>,
+    body: String,
+) -> http::Response<ClientBody> {
+    let mut builder = http::Response::builder().status(status);
+    if let Some(location) = location {
+        builder = builder.header(LOCATION, location);
+    }
+    builder
+        .body(
+            http_body_util::Full::new(body.into())
+                .map_err(Into::into)
+                .boxed(),
+        )
+        .unwrap()
+}
+
+/// Redirects `/{status}/{location}` with `status`, else echoes the method, the body and the
+/// credentials of the request.
+fn redirect_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let path = req.uri().path().to_owned();
+        let mut segments = path.trim_start_matches('/').splitn(2, '/');
+        let status = segments
+            .next()
+            .and_then(|status| status.parse::<u16>().ok());
+        if let (Some(status), Some(location)) = (status, segments.next()) {
+            let location = location.replacen("http:/", "http://", 1);
+            let location = if location.starts_with("http") {
+                location
+            } else {
+                format!("/{location}")
+            };
+            let status = StatusCode::from_u16(status).unwrap();
+            return response(status, Some(&location), String::new());
+        }
+        let credentials = [AUTHORIZATION.as_str(), COOKIE.as_str(), "x-api-key"]
+            .into_iter()
+            .filter(|name| req.headers().contains_key(*name))
+            .collect::<Vec<_>>()
+            .join(",");
+        let method = req.method().to_string();
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8_lossy(&body);
+        response(
+            StatusCode::OK,
+            None,
+            format!("{method} {body} [{credentials}]"),
+        )
+    })
+}
+
+fn client(
+    policy: RedirectPolicy,
+) -> client_util::client::redirect::Redirect<client_util::client::HyperHttpClient<ClientBody>> {
+    ServiceBuilder::new()
+        .layer(RedirectLayer::new(policy))
+        .service(build_http_client())
+}
+
+#[tokio::test]
+async fn follows_the_chain() -> client_util::Result<()> {
+    let server = redirect_server();
+    let base = format!("http://{}", server.addr());
+    let response = RequestBuilder::get(format!("{base}/302/301/end"))?
+        .empty()
+        .send(client(RedirectPolicy::new()))
+        .await?;
+    let history = response.extensions().get::<RedirectHistory>().cloned();
+    assert_eq!(response.text().await?.body(), "GET  []");
+    let history = history.expect("the history is recorded");
+    assert_eq!(history.uri, format!("{base}/end").parse::<Uri>().unwrap());
+    assert_eq!(
+        history.hops,
+        vec![
+            RedirectHop {
+                uri: format!("{base}/302/301/end").parse().unwrap(),
+                status: StatusCode::FOUND,
+            },
+            RedirectHop {
+                uri: format!("{base}/301/end").parse().unwrap(),
+                status: StatusCode::MOVED_PERMANENTLY,
+            },
+        ]
+    );
+
+    // not followed
+    let response = RequestBuilder::get(format!("{base}/302/end"))?
+        .empty()
+        .send(client(RedirectPolicy::none()))
+        .await?;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rewrites_the_method() -> client_util::Result<()> {
+    let server = redirect_server();
+    let base = format!("http://{}", server.addr());
+    for (status, expected) in [
+        (301, "GET  []"),
+        (302, "GET  []"),
+        (303, "GET  []"),
+        (307, "POST form []"),
+        (308, "POST form []"),
+    ] {
+        let response = RequestBuilder::post(format!("{base}/{status}/end"))?
+            .plain_text("form")
+            .send(client(RedirectPolicy::new()))
+            .await?
+            .text()
+            .await?;
+        assert_eq!(response.body(), expected, "{status}");
+    }
+    let response = RequestBuilder::put(format!("{base}/303/end"))?
+        .plain_text("data")
+        .send(client(RedirectPolicy::new()))
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "GET  []");
+    let response = RequestBuilder::put(format!("{base}/302/end"))?
+        .plain_text("data")
+        .send(client(RedirectPolicy::new()))
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "PUT data []");
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_credentials_across_origins() -> client_util::Result<()> {
+    let server = redirect_server();
+    let other = redirect_server();
+    let mut api_key = HeaderValue::from_static("secret");
+    api_key.set_sensitive(true);
+    let send = |uri: String| {
+        let api_key = api_key.clone();
+        async move {
+            RequestBuilder::get(uri)?
+                .header(AUTHORIZATION, "Bearer token")?
+                .header(COOKIE, "session=1")?
+                .header("x-api-key", api_key)?
+                .empty()
+                .send(client(RedirectPolicy::new()))
+                .await?
+                .text()
+                .await
+                .map_err(client_util::Error::from)
+        }
+    };
+    let same = send(format!("http://{}/307/end", server.addr())).await?;
+    assert_eq!(same.body(), "GET  [authorization,cookie,x-api-key]");
+    let cross = send(format!(
+        "http://{}/307/http:/{}/end",
+        server.addr(),
+        other.addr()
+    ))
+    .await?;
+    assert_eq!(cross.body(), "GET  []");
+    Ok(())
+}
+
+#[tokio::test]
+async fn too_many_redirects() -> client_util::Result<()> {
+    let server = redirect_server();
+    let error = RequestBuilder::get(format!("http://{}/302/302/302/end", server.addr()))?
+        .empty()
+        .send(client(RedirectPolicy::new().max_redirects(2)))
+        .await
+        .expect_err("too many redirects");
+    let client_util::Error::Redirect(RedirectError::TooManyRedirects { max, hops }) = error else {
+        panic!("unexpected error {error}");
+    };
+    assert_eq!(max, 2);
+    assert_eq!(hops.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_downgrade() -> client_util::Result<()> {
+    let insecure = tower::service_fn(|request: http::Request<ClientBody>| async move {
+        let location = match request.uri().scheme_str() {
+            Some("https") => Some("http://example.com/end"),
+            _ => None,
+        };
+        Ok::<_, std::convert::Infallible>(response(StatusCode::FOUND, location, String::new()))
+    });
+    let error = RequestBuilder::get("https://example.com/")?
+        .empty()
+        .send(RedirectLayer::new(RedirectPolicy::new()).layer(insecure))
+        .await
+        .expect_err("downgrade is refused");
+    assert!(matches!(
+        error,
+        client_util::Error::Redirect(RedirectError::Downgrade { .. })
+    ));
+    let response = RequestBuilder::get("https://example.com/")?
+        .empty()
+        .send(RedirectLayer::new(RedirectPolicy::new().allow_downgrade(true)).layer(insecure))
+        .await?;
+    let history = response.extensions().get::<RedirectHistory>().unwrap();
+    assert_eq!(history.uri, "http://example.com/end");
+    Ok(())
+}
+
+#[test]
+fn resolve_location() {
+    let base: Uri = "http://example.com/a/b/c?q=1".parse().unwrap();
+    for (location, expected) in [
+        ("https://other.com/x", "https://other.com/x"),
+        ("//other.com/x", "http://other.com/x"),
+        ("/x?y=2", "http://example.com/x?y=2"),
+        ("d", "http://example.com/a/b/d"),
+        ("../d#fragment", "http://example.com/a/d"),
+        ("./", "http://example.com/a/b/"),
+        ("?y=2", "http://example.com/a/b/c?y=2"),
+        ("", "http://example.com/a/b/c?q=1"),
+    ] {
+        assert_eq!(
+            resolve(&base, location),
+            Some(expected.parse().unwrap()),
+            "{location}"
+        );
+    }
+    assert_eq!(resolve(&base, "ftp://example.com/"), None);
+}
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use client_util::client::retry::{retry_after, Retries, RetryLayer, RetryPolicy};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds with `statuses` in turn then `200`, echoing the request body.
+fn flaky_server(
+    statuses: &'static [u16],
+    retry_after: Option<&'static str>,
+) -> (support::server::Server, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let server = support::server::http(move |req| {
+        let hit = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            let status = statuses.get(hit).copied().unwrap_or(200);
+            let mut builder = http::Response::builder().status(status);
+            if let Some(value) = retry_after {
+                builder = builder.header(http::header::RETRY_AFTER, value);
+            }
+            builder
+                .body(http_body_util::Full::new(body).map_err(Into::into).boxed())
+                .unwrap()
+        }
+    });
+    (server, hits)
+}
+
+fn client(
+    policy: RetryPolicy,
+) -> client_util::client::retry::Retry<client_util::client::HyperHttpClient<ClientBody>> {
+    ServiceBuilder::new()
+        .layer(RetryLayer::new(policy))
+        .service(build_http_client())
+}
+
+fn fast() -> RetryPolicy {
+    RetryPolicy::new().base_delay(Duration::from_millis(10))
+}
+
+#[tokio::test]
+async fn retries_unavailable() -> client_util::Result<()> {
+    let (server, hits) = flaky_server(&[503, 429], None);
+    let response = RequestBuilder::put(format!("http://{}/", server.addr()))?
+        .plain_text("replayed")
+        .send(client(fast()))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(response.extensions().get(), Some(&Retries(2)));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    assert_eq!(response.text().await?.body(), "replayed");
+    Ok(())
+}
+
+#[tokio::test]
+async fn gives_up_after_max_retries() -> client_util::Result<()> {
+    let (server, hits) = flaky_server(&[503; 8], None);
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client(fast().max_retries(2)))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.extensions().get(), Some(&Retries(2)));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn post_is_retried_only_when_idempotent() -> client_util::Result<()> {
+    let (server, hits) = flaky_server(&[503], None);
+    let response = RequestBuilder::post(format!("http://{}/", server.addr()))?
+        .plain_text("once")
+        .send(client(fast()))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    let (server, hits) = flaky_server(&[503], None);
+    let response = RequestBuilder::post(format!("http://{}/", server.addr()))?
+        .idempotent()
+        .plain_text("twice")
+        .send(client(fast()))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    assert_eq!(response.text().await?.body(), "twice");
+    Ok(())
+}
+
+#[tokio::test]
+async fn honors_retry_after() -> client_util::Result<()> {
+    let (server, _) = flaky_server(&[429], Some("1"));
+    let start = Instant::now();
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client(fast()))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    // the budget doesn't allow to wait that long
+    let (server, hits) = flaky_server(&[429], Some("5"));
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client(fast().budget(Duration::from_secs(1))))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.extensions().get(), Some(&Retries(0)));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // nor the max delay, without a budget
+    let (server, hits) = flaky_server(&[503], Some("86400"));
+    let start = Instant::now();
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client(fast()))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.extensions().get(), Some(&Retries(0)));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    Ok(())
+}
+
+#[test]
+fn parse_retry_after() {
+    let mut headers = http::HeaderMap::new();
+    assert_eq!(retry_after(&headers), None);
+    headers.insert(http::header::RETRY_AFTER, "120".parse().unwrap());
+    assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+    headers.insert(
+        http::header::RETRY_AFTER,
+        "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+    );
+    assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+}
+
+#[tokio::test]
+async fn retries_connect_errors() -> client_util::Result<()> {
+    // nothing listens on the port until the first retry
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+    });
+    let response = RequestBuilder::post(format!("http://{addr}/"))?
+        .plain_text("not sent yet")
+        .send(client(
+            fast()
+                .jitter(false)
+                .base_delay(Duration::from_millis(200))
+                .max_retries(1),
+        ))
+        .await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(response.extensions().get(), Some(&Retries(1)));
+    Ok(())
+}
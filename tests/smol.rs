@@ -0,0 +1,68 @@
+use client_util::client::rt::SharedExecutor;
+use client_util::prelude::*;
+use http::StatusCode;
+mod support;
+
+fn hello_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        http::Response::new(boxed_full(format!("hello {}", req.uri().path())))
+    })
+}
+
+// No tokio runtime is running on the test thread, the test server runs its own in another thread.
+#[test]
+fn smol_http_client() -> client_util::Result<()> {
+    let server = hello_server();
+    let client = HyperClientBuilder::new().build_smol_http();
+    smol::block_on(async {
+        let url = format!("http://{}/smol", server.addr());
+        for _ in 0..2 {
+            let response = RequestBuilder::get(&url)?
+                .empty()
+                .send(client.clone())
+                .await?
+                .text()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body(), "hello /smol");
+        }
+        client_util::Result::Ok(())
+    })
+}
+
+#[test]
+fn smol_resolves_hosts() -> client_util::Result<()> {
+    let server = hello_server();
+    let client = HyperClientBuilder::new().build_smol_http();
+    smol::block_on(async {
+        let url = format!("http://localhost:{}/named", server.addr().port());
+        let response = RequestBuilder::get(url)?
+            .empty()
+            .send(client)
+            .await?
+            .text()
+            .await?;
+        assert_eq!(response.body(), "hello /named");
+        client_util::Result::Ok(())
+    })
+}
+
+#[test]
+fn custom_executor() -> client_util::Result<()> {
+    let server = hello_server();
+    let executor = SharedExecutor::from_fn(|future| smol::spawn(future).detach());
+    let client = HyperClientBuilder::new()
+        .executor(executor)
+        .build_smol_http();
+    smol::block_on(async {
+        let url = format!("http://{}/custom", server.addr());
+        let response = RequestBuilder::get(url)?
+            .empty()
+            .send(client)
+            .await?
+            .text()
+            .await?;
+        assert_eq!(response.body(), "hello /custom");
+        client_util::Result::Ok(())
+    })
+}
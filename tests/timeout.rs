@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use client_util::client::dns::{DnsResolver, Resolve, Resolving};
+use client_util::client::timeout::{TimeoutError, TimeoutLayer, TimeoutPhase, Timeouts};
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use tower::ServiceBuilder;
+mod support;
+
+/// Responds after `head`, then sends 4 chunks `gap` apart.
+fn slow_server(head: Duration, gap: Duration) -> support::server::Server {
+    support::server::http(move |_req| async move {
+        tokio::time::sleep(head).await;
+        let chunks = futures_util::stream::unfold(0, move |i| async move {
+            if i == 4 {
+                return None;
+            }
+            tokio::time::sleep(gap).await;
+            let frame = http_body::Frame::data(bytes::Bytes::from(format!("{i}")));
+            Some((Ok::<_, std::convert::Infallible>(frame), i + 1))
+        });
+        let body = http_body_util::StreamBody::new(chunks);
+        http::Response::new(body.map_err(Into::into).boxed())
+    })
+}
+
+fn timeout_phase(error: &client_util::Error) -> Option<TimeoutPhase> {
+    assert!(error.is_timeout(), "{error}");
+    TimeoutError::find(error).map(|timeout| timeout.phase)
+}
+
+#[tokio::test]
+async fn response_timeout() -> client_util::Result<()> {
+    let server = slow_server(Duration::from_millis(300), Duration::ZERO);
+    let client = ServiceBuilder::new()
+        .layer(TimeoutLayer::new(Timeouts::new()))
+        .service(build_http_client());
+    let error = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .timeouts(Timeouts::new().response(Duration::from_millis(50)))
+        .empty()
+        .send(client)
+        .await
+        .expect_err("the response is too slow");
+    assert!(matches!(
+        error,
+        client_util::Error::Timeout(TimeoutError {
+            phase: TimeoutPhase::Response,
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn total_timeout_includes_the_body() -> client_util::Result<()> {
+    let server = slow_server(Duration::ZERO, Duration::from_millis(100));
+    let client = ServiceBuilder::new()
+        .layer(TimeoutLayer::new(Timeouts::new()))
+        .service(build_http_client());
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .timeout(Duration::from_millis(250))
+        .empty()
+        .send(client)
+        .await?;
+    let error = client_util::Error::from(response.text().await.expect_err("the body is too slow"));
+    assert_eq!(timeout_phase(&error), Some(TimeoutPhase::Total));
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_timeout_between_chunks() -> client_util::Result<()> {
+    let client = ServiceBuilder::new()
+        .layer(TimeoutLayer::new(
+            Timeouts::new().read(Duration::from_millis(150)),
+        ))
+        .service(build_http_client());
+
+    let server = slow_server(Duration::ZERO, Duration::from_millis(50));
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client.clone())
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "0123");
+
+    let server = slow_server(Duration::ZERO, Duration::from_millis(300));
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .empty()
+        .send(client.clone())
+        .await?;
+    let error = client_util::Error::from(response.text().await.expect_err("a chunk is too slow"));
+    assert_eq!(timeout_phase(&error), Some(TimeoutPhase::Read));
+
+    // the request overrides the default of the layer
+    let response = RequestBuilder::get(format!("http://{}/", server.addr()))?
+        .read_timeout(Duration::from_secs(5))
+        .empty()
+        .send(client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "0123");
+    Ok(())
+}
+
+struct PendingResolver;
+
+impl Resolve for PendingResolver {
+    fn resolve(&self, _name: client_util::client::dns::Name) -> Resolving {
+        Box::pin(futures_util::future::pending())
+    }
+}
+
+#[tokio::test]
+async fn connect_timeout() -> client_util::Result<()> {
+    let client = HyperClientBuilder::new()
+        .resolver(DnsResolver::new(PendingResolver))
+        .connect_timeout(Duration::from_millis(50))
+        .build_http();
+    let error = RequestBuilder::get("http://example.invalid/")?
+        .empty()
+        .send(client)
+        .await
+        .expect_err("the host never resolves");
+    assert_eq!(timeout_phase(&error), Some(TimeoutPhase::Connect));
+    Ok(())
+}
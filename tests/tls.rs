@@ -0,0 +1,256 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use ::rustls::pki_types::pem::PemObject;
+use ::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use client_util::client::metadata::{ConnectionInfo, TlsVersion};
+use client_util::client::tls::{Certificate, Identity};
+use client_util::prelude::*;
+use http::StatusCode;
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+
+struct Pki {
+    ca_pem: String,
+    server_pem: String,
+    server_key_pem: String,
+    client_pem: String,
+    client_key_pem: String,
+}
+
+fn pki() -> Pki {
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "client-util test ca");
+    let ca_key = KeyPair::generate().unwrap();
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let mut server_params = CertificateParams::new(vec!["localhost".to_owned()]).unwrap();
+    server_params
+        .distinguished_name
+        .push(DnType::CommonName, "localhost");
+    let server = server_params.signed_by(&server_key, &ca, &ca_key).unwrap();
+
+    let mut client_params = CertificateParams::new(vec!["client".to_owned()]).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "client");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_key = KeyPair::generate().unwrap();
+    let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+    Pki {
+        ca_pem: ca.pem(),
+        server_pem: server.pem(),
+        server_key_pem: server_key.serialize_pem(),
+        client_pem: client.pem(),
+        client_key_pem: client_key.serialize_pem(),
+    }
+}
+
+/// A TLS server which requires a client certificate issued by the test CA, and answers with the http version.
+async fn mtls_server(pki: &Pki) -> SocketAddr {
+    let provider = Arc::new(::rustls::crypto::aws_lc_rs::default_provider());
+    let mut roots = ::rustls::RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_slice(pki.ca_pem.as_bytes()).unwrap())
+        .unwrap();
+    let verifier = ::rustls::server::WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        provider.clone(),
+    )
+    .build()
+    .unwrap();
+    let mut config = ::rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![CertificateDer::from_pem_slice(pki.server_pem.as_bytes()).unwrap()],
+            PrivateKeyDer::from_pem_slice(pki.server_key_pem.as_bytes()).unwrap(),
+        )
+        .unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let svc = ::hyper::service::service_fn(|req: http::Request<_>| async move {
+                    let version = format!("{:?}", req.version());
+                    Ok::<_, Infallible>(http::Response::new(boxed_full(version)))
+                });
+                let _ = hyper_util::server::conn::auto::Builder::new(
+                    hyper_util::rt::TokioExecutor::new(),
+                )
+                .serve_connection(hyper_util::rt::TokioIo::new(stream), svc)
+                .await;
+            });
+        }
+    });
+    addr
+}
+
+fn builder(pki: &Pki) -> HyperClientBuilder {
+    HyperClientBuilder::new()
+        .tls_built_in_roots(false)
+        .add_root_certificate(Certificate::from_pem(pki.ca_pem.as_bytes()))
+        .identity(Identity::from_pem(
+            pki.client_pem.as_bytes(),
+            pki.client_key_pem.as_bytes(),
+        ))
+}
+
+async fn get_version<S, R>(client: S, addr: SocketAddr) -> client_util::Result<String>
+where
+    S: tower_service::Service<http::Request<ClientBody>, Response = http::Response<R>>
+        + Send
+        + Sync,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    R: http_body::Body<Data = bytes::Bytes> + Send + Sync + 'static,
+    R::Error: Into<BoxError>,
+{
+    let response = RequestBuilder::get(format!("https://localhost:{}/", addr.port()))?
+        .empty()
+        .send(client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    Ok(response.into_body())
+}
+
+#[tokio::test]
+async fn rustls_custom_root_and_identity() -> client_util::Result<()> {
+    let pki = pki();
+    let addr = mtls_server(&pki).await;
+
+    let client = builder(&pki).build_https_rustls().unwrap();
+    assert_eq!(get_version(client, addr).await?, "HTTP/2.0");
+
+    let client = builder(&pki)
+        .http_version(HttpVersionMode::Http1Only)
+        .build_https_rustls()
+        .unwrap();
+    assert_eq!(get_version(client, addr).await?, "HTTP/1.1");
+
+    let client = HyperClientBuilder::new()
+        .tls_built_in_roots(false)
+        .add_root_certificate(Certificate::from_pem(pki.ca_pem.as_bytes()))
+        .build_https_rustls()
+        .unwrap();
+    get_version(client, addr)
+        .await
+        .expect_err("the server requires a client certificate");
+    Ok(())
+}
+
+#[cfg(feature = "client-hyper-native-tls")]
+#[tokio::test]
+async fn native_tls_custom_root_and_identity() -> client_util::Result<()> {
+    let pki = pki();
+    let addr = mtls_server(&pki).await;
+
+    let client = builder(&pki).build_https_native_tls().unwrap();
+    assert_eq!(get_version(client, addr).await?, "HTTP/2.0");
+
+    let client = builder(&pki)
+        .http_version(HttpVersionMode::Http1Only)
+        .build_https_native_tls()
+        .unwrap();
+    assert_eq!(get_version(client, addr).await?, "HTTP/1.1");
+
+    let client = HyperClientBuilder::new()
+        .tls_built_in_roots(false)
+        .add_root_certificate(Certificate::from_pem(pki.ca_pem.as_bytes()))
+        .build_https_native_tls()
+        .unwrap();
+    get_version(client, addr)
+        .await
+        .expect_err("the server requires a client certificate");
+    Ok(())
+}
+
+#[tokio::test]
+async fn untrusted_root_is_rejected() -> client_util::Result<()> {
+    let pki = pki();
+    let another = self::pki();
+    let addr = mtls_server(&pki).await;
+    let client = HyperClientBuilder::new()
+        .tls_built_in_roots(false)
+        .add_root_certificate(Certificate::from_pem(another.ca_pem))
+        .identity(Identity::from_pem(pki.client_pem, pki.client_key_pem))
+        .build_https()
+        .unwrap();
+    get_version(client, addr)
+        .await
+        .expect_err("the server certificate is not issued by the trusted root");
+    Ok(())
+}
+
+async fn connection_info<S, R>(client: S, addr: SocketAddr) -> client_util::Result<ConnectionInfo>
+where
+    S: tower_service::Service<http::Request<ClientBody>, Response = http::Response<R>>
+        + Send
+        + Sync,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    R: http_body::Body<Data = bytes::Bytes> + Send + Sync + 'static,
+    R::Error: Into<BoxError>,
+{
+    let response = RequestBuilder::get(format!("https://localhost:{}/", addr.port()))?
+        .empty()
+        .send(client)
+        .await?;
+    Ok(response
+        .extensions()
+        .get::<ConnectionInfo>()
+        .cloned()
+        .expect("connection info"))
+}
+
+#[tokio::test]
+async fn tls_connection_info() -> client_util::Result<()> {
+    let pki = pki();
+    let addr = mtls_server(&pki).await;
+    let server_der = CertificateDer::from_pem_slice(pki.server_pem.as_bytes()).unwrap();
+
+    let info = connection_info(builder(&pki).build_https_rustls().unwrap(), addr).await?;
+    assert_eq!(
+        info.remote_addr.map(|remote| remote.port()),
+        Some(addr.port())
+    );
+    assert!(info.timings.dns.is_some());
+    assert!(info.timings.tls.is_some());
+    let tls = info.tls.expect("tls info");
+    assert_eq!(tls.version, Some(TlsVersion::Tls1_3));
+    assert_eq!(tls.alpn.as_deref(), Some(&b"h2"[..]));
+    assert_eq!(tls.peer_certificates[0].as_ref(), server_der.as_ref());
+
+    #[cfg(feature = "client-hyper-native-tls")]
+    {
+        let client = builder(&pki)
+            .http_version(HttpVersionMode::Http1Only)
+            .build_https_native_tls()
+            .unwrap();
+        let info = connection_info(client, addr).await?;
+        let tls = info.tls.expect("tls info");
+        assert_eq!(tls.alpn.as_deref(), Some(&b"http/1.1"[..]));
+        assert_eq!(
+            tls.peer_certificates,
+            vec![bytes::Bytes::from(server_der.to_vec())]
+        );
+    }
+    Ok(())
+}
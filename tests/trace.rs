@@ -0,0 +1,132 @@
+use client_util::client::trace::TraceLayer;
+use client_util::prelude::*;
+use http_body_util::BodyExt;
+use opentelemetry::trace::{SpanKind, Status, TracerProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use tower::ServiceBuilder;
+use tracing_subscriber::layer::SubscriberExt;
+mod support;
+
+/// Responds with the `traceparent` header of the request, the status is the path.
+fn server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let traceparent = req
+            .headers()
+            .get("traceparent")
+            .map(|value| value.to_str().unwrap().to_owned())
+            .unwrap_or_default();
+        let status = req.uri().path()[1..].parse().unwrap_or(200);
+        http::Response::builder()
+            .status(status)
+            .body(
+                http_body_util::Full::new(traceparent.into())
+                    .map_err(Into::into)
+                    .boxed(),
+            )
+            .unwrap()
+    })
+}
+
+/// Export the spans to memory while the guard is alive.
+fn exporter() -> (InMemorySpanExporter, tracing::subscriber::DefaultGuard) {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    (exporter, tracing::subscriber::set_default(subscriber))
+}
+
+fn attribute(span: &SpanData, key: &str) -> Option<String> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key.as_str() == key)
+        .map(|attribute| attribute.value.to_string())
+}
+
+#[tokio::test]
+async fn span_follows_the_conventions() -> client_util::Result<()> {
+    let server = server();
+    let (exporter, _guard) = exporter();
+    let client = ServiceBuilder::new()
+        .layer(TraceLayer::new())
+        .service(build_http_client());
+    let uri = format!(
+        "http://user:hunter2@{}/200?page=2&token=hunter2&SIG=abc",
+        server.addr()
+    );
+    let response = RequestBuilder::post(&uri)?
+        .plain_text("hello")
+        .send(client)
+        .await?;
+    let traceparent = response.text().await?.into_body();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let [span] = &spans[..] else {
+        panic!("{spans:?}")
+    };
+    assert_eq!(span.name, "POST");
+    assert_eq!(span.span_kind, SpanKind::Client);
+    assert_eq!(span.status, Status::Unset);
+    let expected = [
+        ("http.request.method", "POST".to_owned()),
+        (
+            "url.full",
+            format!(
+                "http://REDACTED@{}/200?page=2&token=REDACTED&SIG=REDACTED",
+                server.addr()
+            ),
+        ),
+        ("server.address", "127.0.0.1".to_owned()),
+        ("server.port", server.addr().port().to_string()),
+        ("http.request.body.size", "5".to_owned()),
+        ("http.response.status_code", "200".to_owned()),
+        ("http.response.body.size", traceparent.len().to_string()),
+        ("network.protocol.version", "1.1".to_owned()),
+        ("network.peer.address", "127.0.0.1".to_owned()),
+    ];
+    for (key, value) in expected {
+        assert_eq!(attribute(span, key).as_deref(), Some(&*value), "{key}");
+    }
+
+    // the server received the context of the span
+    let context = &span.span_context;
+    assert_eq!(
+        traceparent,
+        format!("00-{}-{}-01", context.trace_id(), context.span_id())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_errors() -> client_util::Result<()> {
+    // nothing listens on the port of a dropped server
+    let refused = format!("http://{}/", server().addr());
+    let server = server();
+    let (exporter, _guard) = exporter();
+    let client = ServiceBuilder::new()
+        .layer(TraceLayer::new())
+        .service(build_http_client());
+    RequestBuilder::get(format!("http://{}/503", server.addr()))?
+        .empty()
+        .send(client.clone())
+        .await?;
+    RequestBuilder::get(refused)?
+        .empty()
+        .send(client)
+        .await
+        .unwrap_err();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    assert_eq!(spans.len(), 2);
+    for (span, error) in spans.iter().zip(["503", "connect"]) {
+        assert!(matches!(span.status, Status::Error { .. }), "{span:?}");
+        assert_eq!(attribute(span, "error.type").as_deref(), Some(error));
+    }
+    assert!(spans[1]
+        .events
+        .iter()
+        .any(|event| event.name == "request failed"));
+    Ok(())
+}
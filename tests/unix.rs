@@ -0,0 +1,115 @@
+use std::convert::Infallible;
+use std::path::PathBuf;
+
+use client_util::prelude::*;
+use http::header::{CONNECTION, UPGRADE};
+use http::StatusCode;
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+
+fn socket_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("client-util-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn serve<F, Fut>(path: &PathBuf, func: F)
+where
+    F: Fn(http::Request<::hyper::body::Incoming>) -> Fut + Clone + Send + 'static,
+    Fut: std::future::Future<Output = http::Response<ClientBody>> + Send + 'static,
+{
+    let listener = UnixListener::bind(path).unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let func = func.clone();
+            let svc = ::hyper::service::service_fn(move |req| {
+                let fut = func(req);
+                async move { Ok::<_, Infallible>(fut.await) }
+            });
+            tokio::spawn(async move {
+                let _ = ::hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), svc)
+                    .with_upgrades()
+                    .await;
+            });
+        }
+    });
+}
+
+#[tokio::test]
+async fn unix_socket_json() -> client_util::Result<()> {
+    let path = socket_file("json");
+    serve(&path, |req| async move {
+        assert_eq!(req.uri().path(), "/containers/json");
+        assert_eq!(req.uri().query(), Some("all=true"));
+        http::Response::new(boxed_full(r#"[{"Id":"abc"}]"#))
+    });
+
+    let mut client = build_unix_client();
+    let response = RequestBuilder::get(unix_uri(&path, "/containers/json?all=true")?)?
+        .empty()
+        .send(&mut client)
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body()[0]["Id"], "abc");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unix_socket_fixed_path() -> client_util::Result<()> {
+    let path = socket_file("fixed");
+    serve(&path, |_req| async move {
+        http::Response::new(boxed_full("pong"))
+    });
+
+    let mut client =
+        hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+            .build(UnixConnector::with_socket(&path));
+    let response = RequestBuilder::get("http://localhost/_ping")?
+        .empty()
+        .send(&mut client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.body(), "pong");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unix_socket_upgrade() -> client_util::Result<()> {
+    let path = socket_file("upgrade");
+    serve(&path, |mut req| async move {
+        tokio::spawn(async move {
+            let upgraded = ::hyper::upgrade::on(&mut req).await.unwrap();
+            let mut io = TokioIo::new(upgraded);
+            let mut buf = [0u8; 4];
+            io.read_exact(&mut buf).await.unwrap();
+            io.write_all(&buf).await.unwrap();
+        });
+        http::Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "tcp")
+            .body(boxed_empty())
+            .unwrap()
+    });
+
+    let mut client = build_unix_client();
+    let response = RequestBuilder::post(unix_uri(&path, "/containers/abc/attach")?)?
+        .header(CONNECTION, "Upgrade")?
+        .header(UPGRADE, "tcp")?
+        .empty()
+        .send(&mut client)
+        .await?;
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    let mut io = TokioIo::new(response.hyper_upgrade().await?);
+    io.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use client_util::prelude::*;
+use http::{StatusCode, Version};
+use tower::ServiceBuilder;
+mod support;
+
+fn version_echo_server() -> support::server::Server {
+    support::server::http(|req| async move {
+        let version = format!("{:?}", req.version());
+        http::Response::new(boxed_full(version))
+    })
+}
+
+#[tokio::test]
+async fn h2c_prior_knowledge() -> client_util::Result<()> {
+    let server = version_echo_server();
+    let mut client = HyperClientBuilder::new()
+        .http_version(HttpVersionMode::Http2PriorKnowledge)
+        .build_http();
+
+    let url = format!("http://{}/", server.addr());
+    let response = RequestBuilder::get(url)?
+        .version(Version::HTTP_2)
+        .empty()
+        .send(&mut client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert_eq!(response.body(), "HTTP/2.0");
+    Ok(())
+}
+
+#[tokio::test]
+async fn http1_only() -> client_util::Result<()> {
+    let server = version_echo_server();
+    let mut client = HyperClientBuilder::new()
+        .http_version(HttpVersionMode::Http1Only)
+        .build_http();
+
+    let url = format!("http://{}/", server.addr());
+    let response = RequestBuilder::get(url)?
+        .empty()
+        .send(&mut client)
+        .await?
+        .text()
+        .await?;
+    assert_eq!(response.version(), Version::HTTP_11);
+    assert_eq!(response.body(), "HTTP/1.1");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unsupported_version_is_reported() -> client_util::Result<()> {
+    let server = version_echo_server();
+    let builder = HyperClientBuilder::new().http_version(HttpVersionMode::Http1Only);
+    let client = ServiceBuilder::new()
+        .layer(builder.version_check())
+        .service(builder.build_http());
+
+    let url = format!("http://{}/", server.addr());
+    let error = RequestBuilder::get(&url)?
+        .version(Version::HTTP_2)
+        .empty()
+        .send(client.clone())
+        .await
+        .expect_err("http/2 is not supported in http/1 only mode");
+    let client_util::Error::SendRequest(source) = error else {
+        panic!("unexpected error {error}");
+    };
+    let source = source
+        .downcast::<UnsupportedVersionError>()
+        .expect("should be an unsupported version error");
+    assert_eq!(source.version, Version::HTTP_2);
+    assert_eq!(source.mode, HttpVersionMode::Http1Only);
+
+    let response = RequestBuilder::get(&url)?.empty().send(client).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    Ok(())
+}
+
+#[test]
+fn version_mode_check() {
+    assert!(HttpVersionMode::Auto.check(Version::HTTP_2, true).is_ok());
+    assert!(HttpVersionMode::Auto.check(Version::HTTP_2, false).is_err());
+    assert!(HttpVersionMode::Http2PriorKnowledge
+        .check(Version::HTTP_2, false)
+        .is_ok());
+    assert!(HttpVersionMode::Http2PriorKnowledge
+        .check(Version::HTTP_3, false)
+        .is_err());
+    assert!(HttpVersionMode::Http1Only
+        .check(Version::HTTP_11, true)
+        .is_ok());
+}